@@ -2,8 +2,425 @@
 
 const { platform, arch } = process;
 
+opaque type ClientRegistrationStartResult = mixed;
+opaque type ClientRegistrationFinishResult = mixed;
+opaque type ClientLoginStartResult = mixed;
+
+type ServerKeyAttestation = {
+  +rootPublicKey: Buffer,
+  +attestation: Buffer,
+};
+
+type UserRecord = {
+  +userId: string,
+  +record: Buffer,
+};
+
+type KeyRotationReport = {
+  +resealed: $ReadOnlyArray<UserRecord>,
+  +needsReregistration: $ReadOnlyArray<string>,
+  +invalid: $ReadOnlyArray<string>,
+  +unprocessed: $ReadOnlyArray<string>,
+};
+
+// Only exported by builds with the `mock-rng` feature
+type MockRngSource = {
+  +seed?: Buffer,
+  +bytes?: Buffer,
+};
+
+type TransitionStoredCredentials = {
+  +record?: ?Buffer,
+  +legacyHash?: ?string,
+};
+
+type TransitionLoginRequest = {
+  +credentialRequest?: ?Buffer,
+  +password?: ?string,
+};
+
+type ClientLoginFinishResult = {
+  +credentialFinalization: Buffer,
+  +sessionKey: Buffer,
+  +exportKey: Buffer,
+  +serverPublicKey: Buffer,
+  +transcriptHash: Buffer,
+};
+
+type TransitionLoginStartResult = {
+  +credentialResponse: ?Buffer,
+  +serverLoginState: ?Buffer,
+  +ksf: ?number,
+  +needsReregistration: boolean,
+  +migratedRecord: ?Buffer,
+  +transcriptHash: ?Buffer,
+};
+
+type ServerOptions = {
+  +serverPrivateKey?: ?Buffer,
+  +tenant?: ?string,
+  +credentialIdentifier?: ?string,
+};
+
+type TenantOptions = {
+  +tenant?: ?string,
+};
+
+type ErrorCatalogEntry = {
+  +code: string,
+  +category: string,
+  +retriable: boolean,
+};
+
+type DerivationLabels = {
+  +sessionSubkey?: ?string,
+  +backupKey?: ?string,
+  +token?: ?string,
+};
+
+declare class AbortHandle {
+  constructor(): void;
+  abort(): void;
+  +aborted: boolean;
+}
+
+declare class Deriver {
+  +labels: {
+    +sessionSubkey: string,
+    +backupKey: string,
+    +token: string,
+  };
+  deriveSessionSubkey(
+    sessionKey: Buffer,
+    purpose: string,
+    length?: ?number,
+  ): Buffer;
+  deriveBackupKey(exportKey: Buffer): Buffer;
+  deriveToken(sessionKey: Buffer, purpose: string): Buffer;
+}
+
+declare class ResultChannel {
+  constructor(
+    listener: (requestId: number, error: ?Error, result: mixed) => mixed,
+  ): void;
+  close(): void;
+}
+
 type RustAPI = {
   +sum: (a: number, b: number) => number,
+  +verifyLegacyPassword: (legacyHash: string, password: string) => boolean,
+  +verifyLegacyPasswordAsync: (
+    legacyHash: string,
+    password: string,
+  ) => Promise<boolean>,
+  +migrateLegacyPassword: (
+    legacyHash: string,
+    password: string,
+    options?: ?ServerOptions,
+  ) => ?Buffer,
+  +migrateLegacyPasswordAsync: (
+    legacyHash: string,
+    password: string,
+    options?: ?ServerOptions,
+  ) => Promise<?Buffer>,
+  +serverTransitionLoginStart: (
+    stored: TransitionStoredCredentials,
+    request: TransitionLoginRequest,
+    options?: ?ServerOptions,
+  ) => TransitionLoginStartResult,
+  +serverTransitionLoginStartAsync: (
+    stored: TransitionStoredCredentials,
+    request: TransitionLoginRequest,
+    options?: ?ServerOptions,
+  ) => Promise<TransitionLoginStartResult>,
+  +serverTransitionLoginFinish: (
+    serverLoginState: Buffer,
+    credentialFinalization: Buffer,
+    options?: ?ServerOptions,
+  ) => Buffer,
+  +serverTransitionLoginFinishAsync: (
+    serverLoginState: Buffer,
+    credentialFinalization: Buffer,
+    options?: ?ServerOptions,
+  ) => Promise<Buffer>,
+  +serverReregistrationStart: (
+    sessionKey: Buffer,
+    registrationRequest: Buffer,
+    tag: Buffer,
+    options?: ?ServerOptions,
+  ) => {
+    +registrationResponse: Buffer,
+    +serverRegistrationState: Buffer,
+  },
+  +serverReregistrationFinish: (
+    sessionKey: Buffer,
+    serverRegistrationState: Buffer,
+    registrationUpload: Buffer,
+    tag: Buffer,
+    options?: ?ServerOptions,
+  ) => Buffer,
+  +clientRegisterStart: (password: string) => ClientRegistrationStartResult,
+  +clientRegisterStartAsync: (
+    password: string,
+  ) => Promise<ClientRegistrationStartResult>,
+  +getRegistrationStartMessageArray: (
+    result: ClientRegistrationStartResult,
+  ) => Buffer,
+  +getRegistrationStartStateArray: (
+    result: ClientRegistrationStartResult,
+  ) => Buffer,
+  +clientRegisterFinish: (
+    state: Buffer,
+    registrationResponse: Buffer,
+    serverKeyAttestation?: ?ServerKeyAttestation,
+  ) => ClientRegistrationFinishResult,
+  +clientRegisterFinishAsync: (
+    state: Buffer,
+    registrationResponse: Buffer,
+    serverKeyAttestation?: ?ServerKeyAttestation,
+  ) => Promise<ClientRegistrationFinishResult>,
+  +getRegistrationFinishMessageArray: (
+    result: ClientRegistrationFinishResult,
+  ) => Buffer,
+  +getRegistrationFinishExportKeyArray: (
+    result: ClientRegistrationFinishResult,
+  ) => Buffer,
+  +clientLoginStart: (password: string) => ClientLoginStartResult,
+  +getLoginStartMessageArray: (result: ClientLoginStartResult) => Buffer,
+  +getLoginStartStateArray: (result: ClientLoginStartResult) => Buffer,
+  +clientLoginFinish: (
+    state: Buffer,
+    credentialResponse: Buffer,
+    ksf: number,
+    serverKeyAttestation?: ?ServerKeyAttestation,
+  ) => Promise<ClientLoginFinishResult>,
+  +WireMessageType: {
+    +RegistrationRequest: number,
+    +RegistrationResponse: number,
+    +RegistrationUpload: number,
+    +CredentialRequest: number,
+    +CredentialResponse: number,
+    +CredentialFinalization: number,
+  },
+  +getSupportedWireVersions: () => $ReadOnlyArray<number>,
+  +negotiateVersion: (
+    clientSupported: $ReadOnlyArray<number>,
+    serverSupported: $ReadOnlyArray<number>,
+  ) => ?number,
+  +tagMessage: (version: number, messageType: number, payload: Buffer) => Buffer,
+  +untagMessage: (
+    message: Buffer,
+    expectedType: number,
+  ) => { +version: number, +payload: Buffer },
+  +setMinimumVersions: (versions: {
+    +wireVersion: number,
+    +suite: number,
+    +ksf: number,
+  }) => void,
+  +getMinimumVersions: () => {
+    +wireVersion: number,
+    +suite: number,
+    +ksf: number,
+  },
+  +getInteropVectors: () => string,
+  +initThreadPool: (numThreads?: ?number) => void,
+  +getThreadPoolSize: () => number,
+  +getMetrics: () => $ReadOnlyArray<{
+    +operation: string,
+    +count: number,
+    +failures: number,
+    +totalLatency: number,
+    +latencyBuckets: $ReadOnlyArray<number>,
+  }>,
+  +generateSealedServerSetup: () => string,
+  +loadServerSetupFromEnv: (
+    envVar?: ?string,
+    options?: ?TenantOptions,
+  ) => string,
+  +getServerSetupFingerprint: (options?: ?TenantOptions) => ?string,
+  +getServerSetupTenants: () => $ReadOnlyArray<string>,
+  +unloadServerSetup: (options?: ?TenantOptions) => boolean,
+  +getQueueDepth: () => number,
+  +onQueuePressure: (
+    threshold: number,
+    listener: ({ +queueDepth: number, +overloaded: boolean }) => mixed,
+  ) => void,
+  +batchServerLoginStart: (
+    record: Buffer,
+    count: number,
+    abort?: ?AbortHandle,
+    options?: ?ServerOptions,
+  ) => Promise<{
+    +count: number,
+    +failures: number,
+    +wallTime: number,
+    +meanLatency: number,
+    +minLatency: number,
+    +p50Latency: number,
+    +p99Latency: number,
+    +maxLatency: number,
+    +loginsPerSecond: number,
+  }>,
+  +initKsf: (overrideKsf?: ?number) => number,
+  +getRegistrationKsf: () => number,
+  +getKsfParams: (ksf: number) => {
+    +memoryKib: number,
+    +iterations: number,
+    +parallelism: number,
+  },
+  +forceReseed: () => void,
+  +bulkRegister: (
+    passwords: $ReadOnlyArray<string>,
+    responder: (
+      index: number,
+      registrationRequest: Buffer,
+    ) => Buffer | Promise<Buffer>,
+    concurrency?: ?number,
+    abort?: ?AbortHandle,
+  ) => Promise<
+    $ReadOnlyArray<{
+      +registrationUpload: ?Buffer,
+      +exportKey: ?Buffer,
+      +error: ?string,
+    }>,
+  >,
+  +setPasswordPolicy: (requirements: {
+    +minLength: number,
+    +minCharacterClasses: number,
+    +minEntropyBits: number,
+  }) => void,
+  +getPasswordPolicy: () => {
+    +minLength: number,
+    +minCharacterClasses: number,
+    +minEntropyBits: number,
+  },
+  +setLockoutPolicy: (settings: {
+    +freeAttempts: number,
+    +baseDelay: number,
+    +maxDelay: number,
+  }) => void,
+  +getLockoutPolicy: () => {
+    +freeAttempts: number,
+    +baseDelay: number,
+    +maxDelay: number,
+  },
+  +getLockoutRetryAfter: (
+    credentialIdentifier: string,
+    options?: ?TenantOptions,
+  ) => ?number,
+  +clearLockout: (
+    credentialIdentifier: string,
+    options?: ?TenantOptions,
+  ) => void,
+  +onSecurityEvent: (
+    listener: ?(event: {
+      +kind:
+        | 'registration_created'
+        | 'login_succeeded'
+        | 'login_failed'
+        | 'lockout_triggered',
+      +tenant: string,
+      +credentialIdentifier: ?string,
+      +method: ?('opaque' | 'legacy'),
+      +reason: ?string,
+      +source: ?string,
+      +lockoutDuration: ?number,
+      +timestamp: number,
+    }) => mixed,
+  ) => void,
+  +setFailureLatency: (milliseconds: number) => void,
+  +getFailureLatency: () => number,
+  +generateAttestationRootKey: () => Buffer,
+  +getAttestationRootPublicKey: (rootPrivateKey: Buffer) => Buffer,
+  +attestServerKey: (
+    rootPrivateKey: Buffer,
+    options?: ?ServerOptions,
+  ) => Buffer,
+  +verifyServerKeyAttestation: (
+    registrationResponse: Buffer,
+    serverKeyAttestation: ServerKeyAttestation,
+  ) => boolean,
+  +setReplayPolicy: (settings: { +window: number, +capacity: number }) => void,
+  +getReplayPolicy: () => { +window: number, +capacity: number },
+  +createKeystore: (
+    passphrase: string,
+    sealedServerSetup?: ?string,
+  ) => Promise<Buffer>,
+  +openKeystore: (
+    keystore: Buffer,
+    passphrase: string,
+    options?: ?TenantOptions,
+  ) => Promise<{ +fingerprint: string, +pepper: Buffer }>,
+  +rotateKeystorePassphrase: (
+    keystore: Buffer,
+    oldPassphrase: string,
+    newPassphrase: string,
+  ) => Promise<Buffer>,
+  +getFormatDescriptions: () => string,
+  +setMockRng?: (source: MockRngSource, draws: number) => void,
+  +withMockRng?: <T>(source: MockRngSource, callback: () => T) => T,
+  +clearMockRng?: () => void,
+  +getHandshakeSnapshot: (seed: Buffer, password: string) => string,
+  +getWireFingerprints: () => { +[messageType: string]: string },
+  +assertWireCompatibility: (expectedFingerprints: {
+    +[messageType: string]: string,
+  }) => void,
+  +planKeyRotation: (
+    oldSealedServerSetup: string,
+    newSealedServerSetup: string,
+    records: $ReadOnlyArray<UserRecord>,
+    abort?: ?AbortHandle,
+  ) => Promise<KeyRotationReport>,
+  +getErrorCatalog: () => $ReadOnlyArray<ErrorCatalogEntry>,
+  +AbortHandle: Class<AbortHandle>,
+  +ResultChannel: Class<ResultChannel>,
+  +clientRegisterFinishOnChannel: (
+    channel: ResultChannel,
+    state: Buffer,
+    registrationResponse: Buffer,
+    serverKeyAttestation?: ?ServerKeyAttestation,
+  ) => number,
+  +compressBlob: (data: Buffer) => Buffer,
+  +decompressBlob: (container: Buffer, maxLength?: ?number) => Buffer,
+  +isCompressedBlob: (data: Buffer) => boolean,
+  +createDeriver: (labels?: ?DerivationLabels) => Deriver,
+  +deriveSessionSubkey: (
+    sessionKey: Buffer,
+    purpose: string,
+    length?: ?number,
+  ) => Buffer,
+  +deriveBackupKey: (exportKey: Buffer) => Buffer,
+  +deriveToken: (sessionKey: Buffer, purpose: string) => Buffer,
+  +getSizes: () => { +[name: string]: number },
+  +serverSetup: (options?: ?TenantOptions) => string,
+  +serverRegisterStart: (
+    registrationRequest: Buffer,
+    options?: ?ServerOptions,
+  ) => {
+    +registrationResponse: Buffer,
+    +serverRegistrationState: Buffer,
+  },
+  +serverRegisterFinish: (
+    serverRegistrationState: Buffer,
+    registrationUpload: Buffer,
+    options?: ?ServerOptions,
+  ) => Buffer,
+  +serverLoginStart: (
+    record: Buffer,
+    credentialRequest: Buffer,
+    options?: ?ServerOptions,
+  ) => Promise<{
+    +credentialResponse: Buffer,
+    +serverLoginState: Buffer,
+    +ksf: number,
+    +needsReregistration: boolean,
+    +transcriptHash: Buffer,
+  }>,
+  +serverLoginFinish: (
+    serverLoginState: Buffer,
+    credentialFinalization: Buffer,
+    options?: ?ServerOptions,
+  ) => Promise<Buffer>,
 };
 
 async function getRustAPI(): Promise<RustAPI> {
@@ -28,8 +445,190 @@ async function getRustAPI(): Promise<RustAPI> {
     throw new Error('Failed to load native binding');
   }
 
-  const { sum } = nativeBinding.default;
-  return { sum };
+  const {
+    sum,
+    verifyLegacyPassword,
+    verifyLegacyPasswordAsync,
+    migrateLegacyPassword,
+    migrateLegacyPasswordAsync,
+    serverTransitionLoginStart,
+    serverTransitionLoginStartAsync,
+    serverTransitionLoginFinish,
+    serverTransitionLoginFinishAsync,
+    serverReregistrationStart,
+    serverReregistrationFinish,
+    clientRegisterStart,
+    clientRegisterStartAsync,
+    getRegistrationStartMessageArray,
+    getRegistrationStartStateArray,
+    clientRegisterFinish,
+    clientRegisterFinishAsync,
+    getRegistrationFinishMessageArray,
+    getRegistrationFinishExportKeyArray,
+    clientLoginStart,
+    getLoginStartMessageArray,
+    getLoginStartStateArray,
+    clientLoginFinish,
+    WireMessageType,
+    getSupportedWireVersions,
+    negotiateVersion,
+    tagMessage,
+    untagMessage,
+    setMinimumVersions,
+    getMinimumVersions,
+    getInteropVectors,
+    initThreadPool,
+    getThreadPoolSize,
+    getMetrics,
+    generateSealedServerSetup,
+    loadServerSetupFromEnv,
+    getServerSetupFingerprint,
+    getServerSetupTenants,
+    unloadServerSetup,
+    getQueueDepth,
+    onQueuePressure,
+    batchServerLoginStart,
+    initKsf,
+    getRegistrationKsf,
+    getKsfParams,
+    forceReseed,
+    bulkRegister,
+    setPasswordPolicy,
+    getPasswordPolicy,
+    setLockoutPolicy,
+    getLockoutPolicy,
+    getLockoutRetryAfter,
+    clearLockout,
+    onSecurityEvent,
+    setFailureLatency,
+    getFailureLatency,
+    generateAttestationRootKey,
+    getAttestationRootPublicKey,
+    attestServerKey,
+    verifyServerKeyAttestation,
+    setReplayPolicy,
+    getReplayPolicy,
+    createKeystore,
+    openKeystore,
+    rotateKeystorePassphrase,
+    getFormatDescriptions,
+    setMockRng,
+    withMockRng,
+    clearMockRng,
+    getHandshakeSnapshot,
+    getWireFingerprints,
+    assertWireCompatibility,
+    planKeyRotation,
+    getErrorCatalog,
+    AbortHandle,
+    ResultChannel,
+    clientRegisterFinishOnChannel,
+    compressBlob,
+    decompressBlob,
+    isCompressedBlob,
+    createDeriver,
+    deriveSessionSubkey,
+    deriveBackupKey,
+    deriveToken,
+    getSizes,
+    serverSetup,
+    serverRegisterStart,
+    serverRegisterFinish,
+    serverLoginStart,
+    serverLoginFinish,
+  } = nativeBinding.default;
+  return {
+    sum,
+    verifyLegacyPassword,
+    verifyLegacyPasswordAsync,
+    migrateLegacyPassword,
+    migrateLegacyPasswordAsync,
+    serverTransitionLoginStart,
+    serverTransitionLoginStartAsync,
+    serverTransitionLoginFinish,
+    serverTransitionLoginFinishAsync,
+    serverReregistrationStart,
+    serverReregistrationFinish,
+    clientRegisterStart,
+    clientRegisterStartAsync,
+    getRegistrationStartMessageArray,
+    getRegistrationStartStateArray,
+    clientRegisterFinish,
+    clientRegisterFinishAsync,
+    getRegistrationFinishMessageArray,
+    getRegistrationFinishExportKeyArray,
+    clientLoginStart,
+    getLoginStartMessageArray,
+    getLoginStartStateArray,
+    clientLoginFinish,
+    WireMessageType,
+    getSupportedWireVersions,
+    negotiateVersion,
+    tagMessage,
+    untagMessage,
+    setMinimumVersions,
+    getMinimumVersions,
+    getInteropVectors,
+    initThreadPool,
+    getThreadPoolSize,
+    getMetrics,
+    generateSealedServerSetup,
+    loadServerSetupFromEnv,
+    getServerSetupFingerprint,
+    getServerSetupTenants,
+    unloadServerSetup,
+    getQueueDepth,
+    onQueuePressure,
+    batchServerLoginStart,
+    initKsf,
+    getRegistrationKsf,
+    getKsfParams,
+    forceReseed,
+    bulkRegister,
+    setPasswordPolicy,
+    getPasswordPolicy,
+    setLockoutPolicy,
+    getLockoutPolicy,
+    getLockoutRetryAfter,
+    clearLockout,
+    onSecurityEvent,
+    setFailureLatency,
+    getFailureLatency,
+    generateAttestationRootKey,
+    getAttestationRootPublicKey,
+    attestServerKey,
+    verifyServerKeyAttestation,
+    setReplayPolicy,
+    getReplayPolicy,
+    createKeystore,
+    openKeystore,
+    rotateKeystorePassphrase,
+    getFormatDescriptions,
+    setMockRng,
+    withMockRng,
+    clearMockRng,
+    getHandshakeSnapshot,
+    getWireFingerprints,
+    assertWireCompatibility,
+    planKeyRotation,
+    getErrorCatalog,
+    AbortHandle,
+    ResultChannel,
+    clientRegisterFinishOnChannel,
+    compressBlob,
+    decompressBlob,
+    isCompressedBlob,
+    createDeriver,
+    deriveSessionSubkey,
+    deriveBackupKey,
+    deriveToken,
+    getSizes,
+    serverSetup,
+    serverRegisterStart,
+    serverRegisterFinish,
+    serverLoginStart,
+    serverLoginFinish,
+  };
 }
 
 export { getRustAPI };
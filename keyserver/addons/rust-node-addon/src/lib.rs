@@ -1,8 +1,16 @@
 #![deny(clippy::all)]
 
+#[cfg(feature = "node")]
 #[macro_use]
 extern crate napi_derive;
 
+#[cfg(feature = "node")]
+pub mod opaque;
+
+#[cfg(not(feature = "node"))]
+pub use comm_opaque::*;
+
+#[cfg(feature = "node")]
 #[napi]
 pub fn sum(a: i32, b: i32) -> i32 {
   a + b
@@ -0,0 +1,55 @@
+//! Interrupting long batch operations. An `AbortHandle` is passed to the
+//! operation when it starts; once it's aborted the operation stops taking
+//! new items, lets the items already in flight finish, and resolves with
+//! what it did so far, so nothing is left half-written. JavaScript callers
+//! holding an `AbortSignal` forward it with
+//! `signal.addEventListener('abort', () => handle.abort())`.
+
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+
+#[napi]
+#[derive(Default)]
+pub struct AbortHandle {
+  aborted: Arc<AtomicBool>,
+}
+
+#[napi]
+impl AbortHandle {
+  #[napi(constructor)]
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Can be called any number of times, including after the operation
+  /// finished
+  #[napi]
+  pub fn abort(&self) {
+    self.aborted.store(true, Ordering::Relaxed);
+  }
+
+  #[napi(getter)]
+  pub fn aborted(&self) -> bool {
+    self.aborted.load(Ordering::Relaxed)
+  }
+}
+
+/// What an operation keeps of its handle: the handle itself stays with
+/// JavaScript, which may abort it from any later turn of the event loop
+#[derive(Clone, Default)]
+pub(crate) struct AbortFlag(Arc<AtomicBool>);
+
+impl AbortFlag {
+  /// Follows `handle`, or is never raised if there isn't one
+  pub(crate) fn new(handle: Option<&AbortHandle>) -> Self {
+    handle
+      .map(|handle| Self(handle.aborted.clone()))
+      .unwrap_or_default()
+  }
+
+  pub(crate) fn is_raised(&self) -> bool {
+    self.0.load(Ordering::Relaxed)
+  }
+}
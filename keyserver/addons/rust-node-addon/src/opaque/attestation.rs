@@ -0,0 +1,69 @@
+//! Server key attestations. The root key is only needed where attestations
+//! are made, normally a release or provisioning script, not on the
+//! keyserver itself; the keyserver serves the attestation it was given
+//! alongside its registration responses.
+
+use comm_opaque::{attestation, Cipher};
+use napi::bindgen_prelude::{Buffer, BufferSlice};
+use opaque_ke::RegistrationResponse;
+
+use super::{handle_error, ServerOptions};
+
+#[napi]
+pub fn generate_attestation_root_key() -> Buffer {
+  attestation::generate_root_key().to_vec().into()
+}
+
+#[napi]
+pub fn get_attestation_root_public_key(
+  root_private_key: BufferSlice<'_>,
+) -> napi::Result<Buffer> {
+  attestation::root_public_key(&root_private_key)
+    .map(|key| key.to_vec().into())
+    .map_err(handle_error)
+}
+
+/// Signs the public key of the server the options select
+#[napi]
+pub fn attest_server_key(
+  root_private_key: BufferSlice<'_>,
+  options: Option<ServerOptions>,
+) -> napi::Result<Buffer> {
+  let server_public_key = options
+    .unwrap_or_default()
+    .keypair()?
+    .public()
+    .to_arr()
+    .to_vec();
+  attestation::attest_server_key(&root_private_key, &server_public_key)
+    .map(|attestation| attestation.to_vec().into())
+    .map_err(handle_error)
+}
+
+#[napi(object)]
+pub struct ServerKeyAttestation {
+  pub root_public_key: Buffer,
+  pub attestation: Buffer,
+}
+
+/// Whether `registrationResponse` came from a server key the attestation's
+/// root key attested. Throws if the response or root key is malformed.
+/// `clientRegisterFinish` makes the same check when given an attestation.
+#[napi]
+pub fn verify_server_key_attestation(
+  registration_response: BufferSlice<'_>,
+  server_key_attestation: ServerKeyAttestation,
+) -> napi::Result<bool> {
+  let registration_response =
+    RegistrationResponse::<Cipher>::deserialize(&registration_response)
+      .map_err(handle_error)?;
+  match attestation::verify_registration_response(
+    &server_key_attestation.root_public_key,
+    &registration_response,
+    &server_key_attestation.attestation,
+  ) {
+    Ok(()) => Ok(true),
+    Err(comm_opaque::Error::InvalidAttestation) => Ok(false),
+    Err(e) => Err(handle_error(e)),
+  }
+}
@@ -0,0 +1,141 @@
+//! Load testing helpers. These run the real server code path, so they can
+//! measure how many logins a keyserver can handle without having to drive
+//! thousands of JavaScript clients.
+
+use std::time::{Duration, Instant};
+
+use comm_opaque::{
+  record::PasswordRecord,
+  rng::CommRng,
+  transition::{self, LoginRequest, StoredCredentials},
+  Cipher,
+};
+use napi::{bindgen_prelude::Buffer, Env, JsObject};
+use opaque_ke::{ClientLogin, ClientLoginStartParameters, CredentialRequest};
+use rayon::prelude::*;
+
+use super::{
+  abort::{AbortFlag, AbortHandle},
+  handle_error, pool, ServerOptions,
+};
+
+/// All latencies are in microseconds. `wallTime` covers the whole batch,
+/// the others individual logins.
+#[napi(object)]
+pub struct BatchTimings {
+  pub count: u32,
+  pub failures: u32,
+  pub wall_time: f64,
+  pub mean_latency: f64,
+  pub min_latency: f64,
+  pub p50_latency: f64,
+  pub p99_latency: f64,
+  pub max_latency: f64,
+  pub logins_per_second: f64,
+}
+
+fn summarize(
+  mut latencies: Vec<Duration>,
+  failures: u32,
+  wall_time: Duration,
+) -> BatchTimings {
+  latencies.sort_unstable();
+  let micros = |latency: &Duration| latency.as_secs_f64() * 1e6;
+  let percentile = |p: usize| {
+    latencies
+      .get((latencies.len().saturating_sub(1)) * p / 100)
+      .map_or(0.0, micros)
+  };
+  let count = latencies.len();
+  BatchTimings {
+    count: count as u32,
+    failures,
+    wall_time: micros(&wall_time),
+    mean_latency: latencies.iter().map(micros).sum::<f64>()
+      / count.max(1) as f64,
+    min_latency: percentile(0),
+    p50_latency: percentile(50),
+    p99_latency: percentile(99),
+    max_latency: percentile(100),
+    logins_per_second: count as f64 / wall_time.as_secs_f64().max(f64::EPSILON),
+  }
+}
+
+/// Starts `count` server logins against `record` in parallel on the thread
+/// pool and resolves to their timings. Generating the client credential
+/// requests isn't timed. Logins not yet started when `abort` is aborted are
+/// skipped, and left out of the timings' `count`.
+#[napi]
+pub fn batch_server_login_start(
+  env: Env,
+  record: Buffer,
+  count: u32,
+  abort: Option<&AbortHandle>,
+  options: Option<ServerOptions>,
+) -> napi::Result<JsObject> {
+  let server_keypair = options.unwrap_or_default().keypair()?;
+  let abort = AbortFlag::new(abort);
+  PasswordRecord::deserialize(&record).map_err(handle_error)?;
+  let record = record.to_vec();
+  pool::spawn(&env, move || {
+    let record: &[u8] = &record;
+    let credential_requests = (0..count)
+      .into_par_iter()
+      .map(|_| {
+        ClientLogin::<Cipher>::start(
+          &mut CommRng,
+          b"load test",
+          ClientLoginStartParameters::default(),
+        )
+        .map(|result| result.message)
+        .map_err(handle_error)
+      })
+      .collect::<napi::Result<Vec<CredentialRequest<Cipher>>>>()?;
+
+    let start = Instant::now();
+    let results: Vec<(Duration, bool)> = credential_requests
+      .into_par_iter()
+      .filter(|_| !abort.is_raised())
+      .map(|credential_request| {
+        let login_start = Instant::now();
+        let succeeded = PasswordRecord::deserialize(record)
+          .and_then(|record| {
+            transition::server_login_start(
+              StoredCredentials {
+                record: Some(record),
+                legacy_hash: None,
+              },
+              LoginRequest::Opaque(Box::new(credential_request)),
+              &server_keypair,
+            )
+          })
+          .is_ok();
+        (login_start.elapsed(), succeeded)
+      })
+      .collect();
+    let wall_time = start.elapsed();
+
+    let failures = results.iter().filter(|(_, succeeded)| !succeeded).count();
+    let latencies = results.into_iter().map(|(latency, _)| latency).collect();
+    Ok(summarize(latencies, failures as u32, wall_time))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_summarize() {
+    let latencies = (1..=100).map(Duration::from_micros).collect();
+    let timings = summarize(latencies, 2, Duration::from_millis(1));
+    assert_eq!(timings.count, 100);
+    assert_eq!(timings.failures, 2);
+    assert_eq!(timings.min_latency, 1.0);
+    assert_eq!(timings.p50_latency, 50.0);
+    assert_eq!(timings.p99_latency, 99.0);
+    assert_eq!(timings.max_latency, 100.0);
+    assert_eq!(timings.mean_latency, 50.5);
+    assert!((timings.logins_per_second - 100_000.0).abs() < 1e-6);
+  }
+}
@@ -0,0 +1,342 @@
+//! Client registration for many accounts at once, for importing users. Each
+//! account goes through client start on the thread pool, the caller's
+//! responder on the JavaScript thread, then client finish on the pool, and
+//! up to `concurrency` accounts are in flight at the same time, so Argon2
+//! for one account overlaps with the server round trips of the others.
+//!
+//! Aborting stops new accounts from starting. Accounts already started still
+//! go through the responder and client finish, so every registration request
+//! the server has seen gets its upload.
+
+use std::sync::{
+  atomic::{AtomicUsize, Ordering},
+  Arc, Mutex,
+};
+
+use comm_opaque::{client, Cipher};
+use napi::{
+  bindgen_prelude::{Buffer, FromNapiValue},
+  sys,
+  threadsafe_function::{
+    ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction,
+    ThreadsafeFunctionCallMode,
+  },
+  CallContext, Env, JsBuffer, JsDeferred, JsError, JsFunction, JsObject,
+  JsUnknown, NapiValue,
+};
+use opaque_ke::{ClientRegistration, RegistrationResponse};
+
+use super::{
+  abort::{AbortFlag, AbortHandle},
+  pool,
+};
+
+const DEFAULT_CONCURRENCY: u32 = 64;
+
+/// Exactly one of `registrationUpload` (with `exportKey`) or `error` is set
+#[napi(object)]
+pub struct BulkRegistrationResult {
+  pub registration_upload: Option<Buffer>,
+  pub export_key: Option<Buffer>,
+  pub error: Option<String>,
+}
+
+impl BulkRegistrationResult {
+  fn failed(error: impl ToString) -> Self {
+    Self {
+      registration_upload: None,
+      export_key: None,
+      error: Some(error.to_string()),
+    }
+  }
+}
+
+/// Whatever the responder returned, kept with the env it was returned in so
+/// a promise can be chained from the callback
+struct Response {
+  env: sys::napi_env,
+  value: sys::napi_value,
+}
+
+impl FromNapiValue for Response {
+  unsafe fn from_napi_value(
+    env: sys::napi_env,
+    value: sys::napi_value,
+  ) -> napi::Result<Self> {
+    Ok(Self { env, value })
+  }
+}
+
+type Resolver =
+  Box<dyn FnOnce(Env) -> napi::Result<Vec<BulkRegistrationResult>> + Send>;
+type Responder = ThreadsafeFunction<(u32, Vec<u8>), ErrorStrategy::Fatal>;
+
+struct BulkRegistration {
+  passwords: Vec<String>,
+  next: AtomicUsize,
+  remaining: AtomicUsize,
+  results: Mutex<Vec<Option<BulkRegistrationResult>>>,
+  responder: Responder,
+  abort: AbortFlag,
+  deferred: Mutex<Option<JsDeferred<Vec<BulkRegistrationResult>, Resolver>>>,
+}
+
+impl BulkRegistration {
+  fn start_next(self: &Arc<Self>) {
+    if self.abort.is_raised() {
+      return self.skip_unstarted();
+    }
+    let index = self.next.fetch_add(1, Ordering::Relaxed);
+    if index >= self.passwords.len() {
+      return;
+    }
+    let bulk = self.clone();
+    pool::run(move || {
+      let password = bulk.passwords[index].as_bytes();
+      let client_start_result = match client::register_start(password) {
+        Ok(result) => result,
+        Err(e) => {
+          return bulk.finish_account(index, BulkRegistrationResult::failed(e))
+        }
+      };
+      let state = client_start_result.state;
+      let request = client_start_result.message.serialize();
+      let responder_bulk = bulk.clone();
+      bulk.responder.call_with_return_value(
+        (index as u32, request),
+        ThreadsafeFunctionCallMode::NonBlocking,
+        move |response: Response| {
+          responder_bulk.await_response(index, state, response);
+          Ok(())
+        },
+      );
+    });
+  }
+
+  /// Runs on the JavaScript thread with whatever the responder returned
+  fn await_response(
+    self: &Arc<Self>,
+    index: usize,
+    state: ClientRegistration<Cipher>,
+    response: Response,
+  ) {
+    let env = unsafe { Env::from_raw(response.env) };
+    let response =
+      unsafe { JsUnknown::from_raw_unchecked(env.raw(), response.value) };
+    if !response.is_promise().unwrap_or(false) {
+      let response = JsBuffer::try_from(response)
+        .and_then(|buffer| buffer.into_value())
+        .map(|buffer| buffer.to_vec());
+      return self.finish_registration(
+        index,
+        state,
+        response.map_err(|e| e.to_string()),
+      );
+    }
+    // `then` callbacks can be called more than once as far as napi knows,
+    // so the state is handed to whichever one runs first
+    let pending = Arc::new(Mutex::new(Some((self.clone(), state))));
+    let attached = (|| -> napi::Result<()> {
+      let promise: JsObject = response.try_into()?;
+      let then: JsFunction = promise.get_named_property("then")?;
+      let fulfilled_pending = pending.clone();
+      let on_fulfilled = env.create_function_from_closure(
+        "onFulfilled",
+        move |ctx: CallContext<'_>| {
+          let response = ctx
+            .get::<JsBuffer>(0)
+            .and_then(|buffer| buffer.into_value())
+            .map(|buffer| buffer.to_vec())
+            .map_err(|e| e.to_string());
+          if let Some((bulk, state)) = take(&fulfilled_pending) {
+            bulk.finish_registration(index, state, response);
+          }
+          ctx.env.get_undefined()
+        },
+      )?;
+      let rejected_pending = pending.clone();
+      let on_rejected = env.create_function_from_closure(
+        "onRejected",
+        move |ctx: CallContext<'_>| {
+          let reason = ctx
+            .get::<JsUnknown>(0)
+            .and_then(|reason| reason.coerce_to_string())
+            .and_then(|reason| reason.into_utf8())
+            .and_then(|reason| reason.into_owned())
+            .unwrap_or_else(|_| "responder rejected".to_string());
+          if let Some((bulk, state)) = take(&rejected_pending) {
+            bulk.finish_registration(index, state, Err(reason));
+          }
+          ctx.env.get_undefined()
+        },
+      )?;
+      then.call(Some(&promise), &[on_fulfilled, on_rejected])?;
+      Ok(())
+    })();
+    if let Err(e) = attached {
+      if let Some((bulk, _)) = take(&pending) {
+        bulk.finish_account(index, BulkRegistrationResult::failed(e));
+      }
+    }
+  }
+
+  fn finish_registration(
+    self: &Arc<Self>,
+    index: usize,
+    state: ClientRegistration<Cipher>,
+    response: Result<Vec<u8>, String>,
+  ) {
+    let bulk = self.clone();
+    pool::run(move || {
+      let result = response
+        .and_then(|response| {
+          RegistrationResponse::deserialize(&response)
+            .map_err(|e| e.to_string())
+        })
+        .and_then(|response| {
+          client::register_finish(state, response).map_err(|e| e.to_string())
+        });
+      let result = match result {
+        Ok(finish_result) => BulkRegistrationResult {
+          registration_upload: Some(finish_result.message.serialize().into()),
+          export_key: Some(finish_result.export_key.to_vec().into()),
+          error: None,
+        },
+        Err(e) => BulkRegistrationResult::failed(e),
+      };
+      bulk.finish_account(index, result);
+    });
+  }
+
+  fn finish_account(
+    self: &Arc<Self>,
+    index: usize,
+    result: BulkRegistrationResult,
+  ) {
+    self.results.lock().unwrap_or_else(|e| e.into_inner())[index] =
+      Some(result);
+    if self.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+      self.resolve();
+    } else {
+      self.start_next();
+    }
+  }
+
+  /// Fails every account that hasn't started yet, all at once, so skipped
+  /// accounts don't each start (and skip) the next one
+  fn skip_unstarted(&self) {
+    let count = self.passwords.len();
+    let first = self.next.swap(count, Ordering::Relaxed);
+    if first >= count {
+      return;
+    }
+    let mut results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+    for result in &mut results[first..] {
+      *result =
+        Some(BulkRegistrationResult::failed("aborted before it started"));
+    }
+    drop(results);
+    let skipped = count - first;
+    if self.remaining.fetch_sub(skipped, Ordering::AcqRel) == skipped {
+      self.resolve();
+    }
+  }
+
+  fn resolve(&self) {
+    let results = std::mem::take(
+      &mut *self.results.lock().unwrap_or_else(|e| e.into_inner()),
+    );
+    let deferred = self
+      .deferred
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .take();
+    if let Some(deferred) = deferred {
+      deferred.resolve(Box::new(move |_| {
+        Ok(
+          results
+            .into_iter()
+            .map(|result| result.expect("every account finished"))
+            .collect(),
+        )
+      }));
+    }
+  }
+}
+
+fn take<T>(pending: &Mutex<Option<T>>) -> Option<T> {
+  pending.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+/// Calls the responder bound as `this`. If it throws, returns a promise
+/// rejected with what it threw, so the account fails as if the responder
+/// had rejected; an exception escaping into the threadsafe function would
+/// abort the process.
+fn call_responder(ctx: CallContext<'_>) -> napi::Result<JsUnknown> {
+  let responder: JsFunction = ctx.this()?;
+  let args = [ctx.get::<JsUnknown>(0)?, ctx.get::<JsUnknown>(1)?];
+  responder.call(None, &args).or_else(|e| {
+    let promise = ctx
+      .env
+      .get_global()?
+      .get_named_property::<JsFunction>("Promise")?
+      .coerce_to_object()?;
+    let reject: JsFunction = promise.get_named_property("reject")?;
+    reject.call(Some(&promise), &[JsError::from(e).into_unknown(*ctx.env)])
+  })
+}
+
+/// Registers every password in `passwords`, calling
+/// `responder(index, registrationRequest)` for each one. The responder
+/// returns the server's registration response (or a promise of it).
+/// Resolves to one result per password, in order; a failure for one account
+/// doesn't stop the others. Once `abort` is aborted, the promise resolves
+/// as soon as the accounts in flight finish, and accounts that never
+/// started fail with "aborted before it started".
+#[napi]
+pub fn bulk_register(
+  env: Env,
+  passwords: Vec<String>,
+  responder: JsFunction,
+  concurrency: Option<u32>,
+  abort: Option<&AbortHandle>,
+) -> napi::Result<JsObject> {
+  let call_responder = env
+    .create_function_from_closure("callResponder", call_responder)?
+    .coerce_to_object()?;
+  let bind: JsFunction = call_responder.get_named_property("bind")?;
+  let responder: JsFunction =
+    bind.call(Some(&call_responder), &[responder])?.try_into()?;
+  let responder: Responder = responder.create_threadsafe_function(
+    0,
+    |ctx: ThreadSafeCallContext<(u32, Vec<u8>)>| {
+      let (index, request) = ctx.value;
+      Ok(vec![
+        ctx.env.create_uint32(index)?.into_unknown(),
+        ctx
+          .env
+          .create_buffer_with_data(request)?
+          .into_raw()
+          .into_unknown(),
+      ])
+    },
+  )?;
+  let (deferred, promise) = env.create_deferred()?;
+  let count = passwords.len();
+  let bulk = Arc::new(BulkRegistration {
+    passwords,
+    next: AtomicUsize::new(0),
+    remaining: AtomicUsize::new(count),
+    results: Mutex::new((0..count).map(|_| None).collect()),
+    responder,
+    abort: AbortFlag::new(abort),
+    deferred: Mutex::new(Some(deferred)),
+  });
+  if count == 0 {
+    bulk.resolve();
+  }
+  for _ in 0..concurrency.unwrap_or(DEFAULT_CONCURRENCY).max(1) {
+    bulk.start_next();
+  }
+  Ok(promise)
+}
@@ -0,0 +1,118 @@
+//! Results pushed to JavaScript through a long-lived callback, for flows
+//! that finish on a native thread at a time JavaScript can't predict (a
+//! KMS signing a value, say) and would otherwise have to be polled. A
+//! `ResultChannel` wraps one listener; each flow started on it returns a
+//! request ID at once, and later calls `listener(requestId, error, result)`
+//! from the JavaScript thread with exactly one of `error` and `result` set.
+
+use std::sync::{
+  atomic::{AtomicU32, Ordering},
+  Arc, Mutex,
+};
+
+use napi::{
+  bindgen_prelude::ToNapiValue,
+  threadsafe_function::{
+    ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction,
+    ThreadsafeFunctionCallMode,
+  },
+  Env, JsFunction, JsUnknown, NapiValue,
+};
+
+use super::{invalid_state, pool};
+
+/// Turns a flow's result into a JavaScript value, on the JavaScript thread
+type IntoJs = Box<dyn FnOnce(&Env) -> napi::Result<JsUnknown> + Send>;
+
+struct Delivery {
+  request_id: u32,
+  result: napi::Result<IntoJs>,
+}
+
+type Listener = ThreadsafeFunction<Delivery, ErrorStrategy::Fatal>;
+
+fn delivery_args(
+  ctx: ThreadSafeCallContext<Delivery>,
+) -> napi::Result<Vec<JsUnknown>> {
+  let env = ctx.env;
+  let request_id = env.create_uint32(ctx.value.request_id)?.into_unknown();
+  let result = ctx.value.result.and_then(|into_js| into_js(&env));
+  Ok(match result {
+    Ok(value) => vec![request_id, env.get_null()?.into_unknown(), value],
+    Err(e) => vec![
+      request_id,
+      env.create_error(e)?.into_unknown(),
+      env.get_undefined()?.into_unknown(),
+    ],
+  })
+}
+
+#[napi]
+pub struct ResultChannel {
+  /// Shared with the flows in progress, which check it again before
+  /// delivering
+  listener: Arc<Mutex<Option<Listener>>>,
+  next_request_id: AtomicU32,
+}
+
+#[napi]
+impl ResultChannel {
+  /// The channel keeps the process alive until it's closed
+  #[napi(constructor)]
+  pub fn new(listener: JsFunction) -> napi::Result<Self> {
+    Ok(Self {
+      listener: Arc::new(Mutex::new(Some(
+        listener.create_threadsafe_function(0, delivery_args)?,
+      ))),
+      next_request_id: AtomicU32::new(0),
+    })
+  }
+
+  /// Stops delivering results, including those of flows still running,
+  /// and lets the process exit. Starting a flow on a closed channel throws.
+  #[napi]
+  pub fn close(&self) {
+    self
+      .listener
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .take();
+  }
+}
+
+impl ResultChannel {
+  /// Runs `task` on the pool and returns the request ID its result will be
+  /// delivered with
+  pub(crate) fn spawn<T, F>(&self, task: F) -> napi::Result<u32>
+  where
+    T: ToNapiValue + Send + 'static,
+    F: FnOnce() -> napi::Result<T> + Send + 'static,
+  {
+    if self
+      .listener
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .is_none()
+    {
+      return Err(invalid_state("result channel is closed"));
+    }
+    let listener = self.listener.clone();
+    let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+    pool::run(move || {
+      let result = task().map(|value| -> IntoJs {
+        Box::new(move |env| unsafe {
+          let raw = T::to_napi_value(env.raw(), value)?;
+          Ok(JsUnknown::from_raw_unchecked(env.raw(), raw))
+        })
+      });
+      let listener = listener.lock().unwrap_or_else(|e| e.into_inner());
+      if let Some(listener) = listener.as_ref() {
+        listener.call(
+          Delivery { request_id, result },
+          ThreadsafeFunctionCallMode::NonBlocking,
+        );
+      }
+    });
+    Ok(request_id)
+  }
+}
@@ -0,0 +1,105 @@
+//! Client login. The start returns an opaque handle in the boxed-result
+//! style of `client_registration`, read with the `getLoginStart*Array`
+//! functions; its state is the serialized `ClientLoggingIn` of
+//! comm-opaque's `api`, so a login can be finished in another process than
+//! the one that started it. The finish resolves to a plain object.
+
+use comm_opaque::{api::ClientLoggingIn, attestation, OpaqueClient};
+use napi::{
+  bindgen_prelude::{Buffer, BufferSlice, External},
+  Env, JsObject,
+};
+
+use super::{
+  attestation::ServerKeyAttestation, handle_error, invalid_argument, pool,
+};
+
+pub struct ClientLoginStartResult {
+  state: ClientLoggingIn,
+  credential_request: Vec<u8>,
+}
+
+#[napi]
+pub fn client_login_start(
+  password: String,
+) -> napi::Result<External<ClientLoginStartResult>> {
+  let (state, credential_request) =
+    OpaqueClient::login(password.as_bytes()).map_err(handle_error)?;
+  Ok(External::new(ClientLoginStartResult {
+    state,
+    credential_request,
+  }))
+}
+
+#[napi]
+pub fn get_login_start_message_array(
+  result: External<ClientLoginStartResult>,
+) -> Buffer {
+  result.credential_request.clone().into()
+}
+
+/// Holds the password, so has to be kept as carefully
+#[napi]
+pub fn get_login_start_state_array(
+  result: External<ClientLoginStartResult>,
+) -> napi::Result<Buffer> {
+  result
+    .state
+    .serialize()
+    .map(Buffer::from)
+    .map_err(handle_error)
+}
+
+/// `credentialFinalization` goes to the server, which only considers the
+/// user authenticated once it has checked it. `exportKey` is stable across
+/// logins with the same password; `serverPublicKey` is the key of the
+/// server the user registered with, for clients that pin it; and
+/// `transcriptHash` identifies the login, as on the server.
+#[napi(object)]
+pub struct ClientLoginFinishResult {
+  pub credential_finalization: Buffer,
+  pub session_key: Buffer,
+  pub export_key: Buffer,
+  pub server_public_key: Buffer,
+  pub transcript_hash: Buffer,
+}
+
+/// `state` is the array returned by `getLoginStartStateArray`, and `ksf`
+/// the KSF parameter set the server sent with the credential response.
+/// Resolves to a `ClientLoginFinishResult`; rejects if the password
+/// is wrong or the server isn't the one the user registered with. With
+/// `serverKeyAttestation`, also rejects unless its root key attested the
+/// server's key.
+#[napi]
+pub fn client_login_finish(
+  env: Env,
+  state: BufferSlice<'_>,
+  credential_response: Buffer,
+  ksf: u32,
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> napi::Result<JsObject> {
+  let client_logging_in =
+    ClientLoggingIn::deserialize(&state).map_err(handle_error)?;
+  let ksf = u8::try_from(ksf)
+    .map_err(|_| invalid_argument("ksf must be between 0 and 255"))?;
+  pool::spawn(&env, move || {
+    let logged_in = client_logging_in
+      .finish(&credential_response, ksf)
+      .map_err(handle_error)?;
+    if let Some(server_key_attestation) = server_key_attestation {
+      attestation::verify_server_key(
+        &server_key_attestation.root_public_key,
+        &logged_in.server_public_key,
+        &server_key_attestation.attestation,
+      )
+      .map_err(handle_error)?;
+    }
+    Ok(ClientLoginFinishResult {
+      credential_finalization: logged_in.credential_finalization.into(),
+      session_key: logged_in.session_key.into(),
+      export_key: logged_in.export_key.into(),
+      server_public_key: logged_in.server_public_key.into(),
+      transcript_hash: logged_in.transcript_hash.into(),
+    })
+  })
+}
@@ -0,0 +1,177 @@
+//! Client registration in the boxed-result style: each step returns an
+//! opaque handle, and the message/state/key bytes are read out of it with
+//! the `get*Array` functions. `clientRegisterFinishOnChannel` delivers the
+//! bytes themselves through a `ResultChannel` instead.
+//!
+//! `clientRegisterFinish` runs Argon2 on the calling thread, which blocks
+//! the event loop for as long as the hash takes; `clientRegisterFinishAsync`
+//! runs it on the thread pool instead. `clientRegisterStartAsync` does the
+//! same for the start step, for callers that keep every group operation off
+//! the event loop. The handles are the same either way.
+
+use comm_opaque::{attestation, client, Cipher};
+use napi::{
+  bindgen_prelude::{Buffer, BufferSlice, External},
+  Env, JsObject,
+};
+use opaque_ke::{
+  ClientRegistration, ClientRegistrationFinishResult,
+  ClientRegistrationStartResult, RegistrationResponse,
+};
+
+use super::{
+  attestation::ServerKeyAttestation, channel::ResultChannel, handle_error,
+  handle_policy_error, pool,
+};
+
+/// Throws with code `PASSWORD_POLICY` if `password` doesn't meet the
+/// password policy
+#[napi]
+pub fn client_register_start(
+  env: Env,
+  password: String,
+) -> napi::Result<External<ClientRegistrationStartResult<Cipher>>> {
+  client::register_start(password.as_bytes())
+    .map(External::new)
+    .map_err(|e| handle_policy_error(&env, e))
+}
+
+/// `clientRegisterStart` on the thread pool, resolving to the same handle.
+/// Not `pool::spawn_external`: the error is converted once the result is
+/// back on the JavaScript thread.
+#[napi]
+pub fn client_register_start_async(
+  env: Env,
+  password: String,
+) -> napi::Result<JsObject> {
+  let (deferred, promise) = env.create_deferred()?;
+  pool::run(move || {
+    let result = client::register_start(password.as_bytes());
+    deferred.resolve(move |env| {
+      result
+        .map(External::new)
+        .map_err(|e| handle_policy_error(&env, e))
+    });
+  });
+  Ok(promise)
+}
+
+#[napi]
+pub fn get_registration_start_message_array(
+  result: External<ClientRegistrationStartResult<Cipher>>,
+) -> Buffer {
+  result.message.serialize().into()
+}
+
+#[napi]
+pub fn get_registration_start_state_array(
+  result: External<ClientRegistrationStartResult<Cipher>>,
+) -> Buffer {
+  result.state.serialize().into()
+}
+
+/// `state` is the array returned by `getRegistrationStartStateArray`.
+/// Returns a `ClientRegistrationFinishResult` handle. With
+/// `serverKeyAttestation`, throws unless the response came from a server
+/// key its root key attested.
+#[napi]
+pub fn client_register_finish(
+  state: BufferSlice<'_>,
+  registration_response: BufferSlice<'_>,
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> napi::Result<External<ClientRegistrationFinishResult<Cipher>>> {
+  let (client_registration, registration_response) = parse_finish_arguments(
+    &state,
+    &registration_response,
+    server_key_attestation,
+  )?;
+  client::register_finish(client_registration, registration_response)
+    .map(External::new)
+    .map_err(handle_error)
+}
+
+/// `clientRegisterFinish` on the thread pool, resolving to the same handle.
+/// Arguments are checked, and the attestation verified, before it returns.
+#[napi]
+pub fn client_register_finish_async(
+  env: Env,
+  state: BufferSlice<'_>,
+  registration_response: BufferSlice<'_>,
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> napi::Result<JsObject> {
+  let (client_registration, registration_response) = parse_finish_arguments(
+    &state,
+    &registration_response,
+    server_key_attestation,
+  )?;
+  pool::spawn_external(&env, move || {
+    client::register_finish(client_registration, registration_response)
+      .map_err(handle_error)
+  })
+}
+
+#[napi(object)]
+pub struct ClientRegistrationFinishArrays {
+  pub registration_upload: Buffer,
+  pub export_key: Buffer,
+}
+
+/// `clientRegisterFinish`, delivering a `ClientRegistrationFinishArrays`
+/// through `channel`. Returns the request ID.
+#[napi]
+pub fn client_register_finish_on_channel(
+  channel: &ResultChannel,
+  state: BufferSlice<'_>,
+  registration_response: BufferSlice<'_>,
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> napi::Result<u32> {
+  let (client_registration, registration_response) = parse_finish_arguments(
+    &state,
+    &registration_response,
+    server_key_attestation,
+  )?;
+  channel.spawn(move || {
+    let finish_result =
+      client::register_finish(client_registration, registration_response)
+        .map_err(handle_error)?;
+    Ok(ClientRegistrationFinishArrays {
+      registration_upload: finish_result.message.serialize().into(),
+      export_key: finish_result.export_key.to_vec().into(),
+    })
+  })
+}
+
+fn parse_finish_arguments(
+  state: &[u8],
+  registration_response: &[u8],
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> napi::Result<(ClientRegistration<Cipher>, RegistrationResponse<Cipher>)> {
+  let client_registration =
+    ClientRegistration::<Cipher>::deserialize(state).map_err(handle_error)?;
+  let registration_response =
+    RegistrationResponse::deserialize(registration_response)
+      .map_err(handle_error)?;
+  if let Some(server_key_attestation) = server_key_attestation {
+    attestation::verify_registration_response(
+      &server_key_attestation.root_public_key,
+      &registration_response,
+      &server_key_attestation.attestation,
+    )
+    .map_err(handle_error)?;
+  }
+  Ok((client_registration, registration_response))
+}
+
+#[napi]
+pub fn get_registration_finish_message_array(
+  result: External<ClientRegistrationFinishResult<Cipher>>,
+) -> Buffer {
+  result.message.serialize().into()
+}
+
+#[napi]
+pub fn get_registration_finish_export_key_array(
+  result: External<ClientRegistrationFinishResult<Cipher>>,
+) -> Buffer {
+  result.export_key.to_vec().into()
+}
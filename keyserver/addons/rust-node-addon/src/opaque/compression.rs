@@ -0,0 +1,42 @@
+//! The compressed storage container (see `compression` in comm-opaque), for
+//! records and states the keyserver keeps in the database or Redis. Every
+//! other function takes the uncompressed bytes, so values are decompressed
+//! before being passed back in.
+
+use comm_opaque::compression;
+use napi::bindgen_prelude::{Buffer, BufferSlice};
+
+use super::handle_error;
+
+/// Throws if `data` is 4 GiB or more
+#[napi]
+pub fn compress_blob(data: BufferSlice<'_>) -> napi::Result<Buffer> {
+  compression::compress(&data)
+    .map(Buffer::from)
+    .map_err(handle_error)
+}
+
+/// Throws if `container` is corrupted or would decompress to more than
+/// `maxLength` bytes (1 MiB by default). Anything that isn't a container is
+/// returned unchanged, so stores can switch to compression gradually.
+#[napi]
+pub fn decompress_blob(
+  container: BufferSlice<'_>,
+  max_length: Option<u32>,
+) -> napi::Result<Buffer> {
+  if !compression::is_compressed(&container) {
+    return Ok(container.to_vec().into());
+  }
+  let max_length = max_length
+    .map_or(compression::DEFAULT_MAX_DECOMPRESSED_LEN, |max_length| {
+      max_length as usize
+    });
+  compression::decompress(&container, max_length)
+    .map(Buffer::from)
+    .map_err(handle_error)
+}
+
+#[napi]
+pub fn is_compressed_blob(data: BufferSlice<'_>) -> bool {
+  compression::is_compressed(&data)
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use napi::bindgen_prelude::BufferSlice;
+
+use super::{handle_error, invalid_argument};
+
+/// JSON transcripts of the shared OPAQUE fixtures, for JS test suites (such
+/// as the web client's) to check their own implementation against
+#[napi]
+pub fn get_interop_vectors() -> napi::Result<String> {
+  comm_opaque::conformance::interop_vectors_json().map_err(handle_error)
+}
+
+/// A snapshot of a deterministic registration and login (see
+/// `handshake_snapshot_json` in comm-opaque), for Jest snapshot tests that
+/// should fail on any wire format change. `seed` must be 32 bytes.
+#[napi]
+pub fn get_handshake_snapshot(
+  seed: BufferSlice<'_>,
+  password: String,
+) -> napi::Result<String> {
+  let seed = seed
+    .as_ref()
+    .try_into()
+    .map_err(|_| invalid_argument("seed must be 32 bytes"))?;
+  comm_opaque::conformance::handshake_snapshot_json(seed, password.as_bytes())
+    .map_err(handle_error)
+}
+
+/// Fingerprints of this build's wire formats, keyed by message type, to
+/// store and later pass to `assertWireCompatibility`
+#[napi]
+pub fn get_wire_fingerprints() -> HashMap<String, String> {
+  comm_opaque::conformance::wire_fingerprints()
+    .into_iter()
+    .map(|(name, fingerprint)| (name.to_string(), fingerprint.to_string()))
+    .collect()
+}
+
+/// Throws, naming the message types that differ, unless this build still
+/// serializes everything the way the build that produced
+/// `expectedFingerprints` did. Meant to be called at startup, so a
+/// dependency bump that changed serialization stops the keyserver.
+#[napi]
+pub fn assert_wire_compatibility(
+  expected_fingerprints: HashMap<String, String>,
+) -> napi::Result<()> {
+  comm_opaque::conformance::assert_wire_compatibility(
+    &expected_fingerprints.into_iter().collect(),
+  )
+  .map_err(handle_error)
+}
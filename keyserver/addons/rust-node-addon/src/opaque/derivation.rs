@@ -0,0 +1,135 @@
+use comm_opaque::derivation::{self, DerivationLabels};
+use napi::bindgen_prelude::{Buffer, BufferSlice};
+
+use super::{handle_error, invalid_argument};
+
+/// Labels left out keep their default value
+#[napi(object)]
+#[derive(Default)]
+pub struct DerivationLabelSettings {
+  pub session_subkey: Option<String>,
+  pub backup_key: Option<String>,
+  pub token: Option<String>,
+}
+
+/// Derives keys under the labels it was created with (see `createDeriver`)
+#[napi]
+pub struct Deriver {
+  inner: derivation::Deriver,
+}
+
+#[napi]
+impl Deriver {
+  #[napi(getter)]
+  pub fn labels(&self) -> DerivationLabelSettings {
+    let labels = self.inner.labels();
+    DerivationLabelSettings {
+      session_subkey: Some(labels.session_subkey.to_string()),
+      backup_key: Some(labels.backup_key.to_string()),
+      token: Some(labels.token.to_string()),
+    }
+  }
+
+  /// `length` defaults to 32 bytes
+  #[napi]
+  pub fn derive_session_subkey(
+    &self,
+    session_key: BufferSlice<'_>,
+    purpose: String,
+    length: Option<u32>,
+  ) -> napi::Result<Buffer> {
+    self
+      .inner
+      .session_subkey(&session_key, &purpose, subkey_length(length)?)
+      .map(Buffer::from)
+      .map_err(handle_error)
+  }
+
+  #[napi]
+  pub fn derive_backup_key(
+    &self,
+    export_key: BufferSlice<'_>,
+  ) -> napi::Result<Buffer> {
+    self
+      .inner
+      .backup_key(&export_key)
+      .map(Buffer::from)
+      .map_err(handle_error)
+  }
+
+  #[napi]
+  pub fn derive_token(
+    &self,
+    session_key: BufferSlice<'_>,
+    purpose: String,
+  ) -> napi::Result<Buffer> {
+    self
+      .inner
+      .token(&session_key, &purpose)
+      .map(Buffer::from)
+      .map_err(handle_error)
+  }
+}
+
+/// A deriver for services sharing a session or export key with another,
+/// so the two derive different keys from it. Keep one per service and pass
+/// it wherever keys are derived: keys derived under other labels don't
+/// match. Throws if a label is empty or two are the same.
+#[napi]
+pub fn create_deriver(
+  labels: Option<DerivationLabelSettings>,
+) -> napi::Result<Deriver> {
+  let labels = labels.unwrap_or_default();
+  let default = DerivationLabels::DEFAULT;
+  derivation::Deriver::new(DerivationLabels {
+    session_subkey: labels
+      .session_subkey
+      .map_or(default.session_subkey, Into::into),
+    backup_key: labels.backup_key.map_or(default.backup_key, Into::into),
+    token: labels.token.map_or(default.token, Into::into),
+  })
+  .map(|inner| Deriver { inner })
+  .map_err(handle_error)
+}
+
+/// `deriveSessionSubkey`, `deriveBackupKey` and `deriveToken` derive with
+/// the default labels, like a `Deriver` created without any
+#[napi]
+pub fn derive_session_subkey(
+  session_key: BufferSlice<'_>,
+  purpose: String,
+  length: Option<u32>,
+) -> napi::Result<Buffer> {
+  derivation::derive_session_subkey(
+    &session_key,
+    &purpose,
+    subkey_length(length)?,
+  )
+  .map(Buffer::from)
+  .map_err(handle_error)
+}
+
+#[napi]
+pub fn derive_backup_key(export_key: BufferSlice<'_>) -> napi::Result<Buffer> {
+  derivation::derive_backup_key(&export_key)
+    .map(Buffer::from)
+    .map_err(handle_error)
+}
+
+#[napi]
+pub fn derive_token(
+  session_key: BufferSlice<'_>,
+  purpose: String,
+) -> napi::Result<Buffer> {
+  derivation::derive_token(&session_key, &purpose)
+    .map(Buffer::from)
+    .map_err(handle_error)
+}
+
+fn subkey_length(length: Option<u32>) -> napi::Result<usize> {
+  match length {
+    Some(length) => usize::try_from(length)
+      .map_err(|_| invalid_argument("length is too large")),
+    None => Ok(derivation::DERIVED_KEY_LEN),
+  }
+}
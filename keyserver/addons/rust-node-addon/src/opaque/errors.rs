@@ -0,0 +1,36 @@
+use comm_opaque::ERROR_CATALOG;
+
+/// Thrown by `invalid_argument`
+pub(crate) const INVALID_ARGUMENT: &str = "INVALID_ARGUMENT";
+/// Thrown by `invalid_state`
+pub(crate) const INVALID_STATE: &str = "INVALID_STATE";
+
+#[napi(object)]
+pub struct ErrorCatalogEntry {
+  pub code: String,
+  pub category: String,
+  pub retriable: bool,
+}
+
+fn entry(code: &str, category: &str, retriable: bool) -> ErrorCatalogEntry {
+  ErrorCatalogEntry {
+    code: code.to_string(),
+    category: category.to_string(),
+    retriable,
+  }
+}
+
+/// Every code a thrown error's message can start with: comm-opaque's, then
+/// the addon's own. Meant for generating the keyserver's error mapping and
+/// the client SDKs' error types, rather than for use at runtime.
+#[napi]
+pub fn get_error_catalog() -> Vec<ErrorCatalogEntry> {
+  ERROR_CATALOG
+    .iter()
+    .map(|info| entry(info.code, info.category.name(), info.retriable))
+    .chain([
+      entry(INVALID_ARGUMENT, "argument", false),
+      entry(INVALID_STATE, "configuration", false),
+    ])
+    .collect()
+}
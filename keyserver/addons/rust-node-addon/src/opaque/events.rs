@@ -0,0 +1,117 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use comm_opaque::events::{self, SecurityEvent};
+use napi::{
+  threadsafe_function::{
+    ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction,
+    ThreadsafeFunctionCallMode,
+  },
+  Env, JsFunction,
+};
+
+/// `kind` is one of `registration_created`, `login_succeeded`,
+/// `login_failed` and `lockout_triggered`, and `tenant` the tenant of the
+/// call that emitted it; the other fields are set where they apply to that
+/// kind. `timestamp` is in milliseconds since the epoch.
+#[napi(object)]
+pub struct SecurityEventInfo {
+  pub kind: String,
+  pub tenant: String,
+  pub credential_identifier: Option<String>,
+  pub method: Option<String>,
+  pub reason: Option<String>,
+  pub source: Option<String>,
+  pub lockout_duration: Option<u32>,
+  pub timestamp: f64,
+}
+
+impl From<&SecurityEvent> for SecurityEventInfo {
+  fn from(event: &SecurityEvent) -> Self {
+    let mut info = SecurityEventInfo {
+      kind: String::new(),
+      tenant: String::new(),
+      credential_identifier: None,
+      method: None,
+      reason: None,
+      source: None,
+      lockout_duration: None,
+      timestamp: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since_epoch| since_epoch.as_millis() as f64),
+    };
+    match event {
+      SecurityEvent::RegistrationCreated {
+        tenant,
+        credential_identifier,
+        source,
+      } => {
+        info.kind = "registration_created".to_string();
+        info.tenant = tenant.clone();
+        info.credential_identifier = credential_identifier.clone();
+        info.source = Some(source.name().to_string());
+      }
+      SecurityEvent::LoginSucceeded {
+        tenant,
+        credential_identifier,
+        method,
+      } => {
+        info.kind = "login_succeeded".to_string();
+        info.tenant = tenant.clone();
+        info.credential_identifier = credential_identifier.clone();
+        info.method = Some(method.name().to_string());
+      }
+      SecurityEvent::LoginFailed {
+        tenant,
+        credential_identifier,
+        method,
+        reason,
+      } => {
+        info.kind = "login_failed".to_string();
+        info.tenant = tenant.clone();
+        info.credential_identifier = credential_identifier.clone();
+        info.method = Some(method.name().to_string());
+        info.reason = Some(reason.name().to_string());
+      }
+      SecurityEvent::LockoutTriggered {
+        tenant,
+        credential_identifier,
+        duration,
+      } => {
+        info.kind = "lockout_triggered".to_string();
+        info.tenant = tenant.clone();
+        info.credential_identifier = Some(credential_identifier.clone());
+        info.lockout_duration =
+          Some(duration.as_millis().min(u32::MAX.into()) as u32);
+      }
+    }
+    info
+  }
+}
+
+/// Calls `listener` with a `SecurityEventInfo` for every login and
+/// registration event, or stops if `listener` is null. Replaces any previous
+/// listener. Events from the thread pool are queued for the JavaScript
+/// thread, so `listener` sees them shortly after they happen. The listener
+/// doesn't keep the process alive.
+#[napi]
+pub fn on_security_event(
+  env: Env,
+  listener: Option<JsFunction>,
+) -> napi::Result<()> {
+  let Some(listener) = listener else {
+    events::set_event_sink(None);
+    return Ok(());
+  };
+  let mut listener: ThreadsafeFunction<
+    SecurityEventInfo,
+    ErrorStrategy::Fatal,
+  > = listener.create_threadsafe_function(
+    0,
+    |ctx: ThreadSafeCallContext<SecurityEventInfo>| Ok(vec![ctx.value]),
+  )?;
+  listener.unref(&env)?;
+  events::set_event_sink(Some(Box::new(move |event| {
+    listener.call(event.into(), ThreadsafeFunctionCallMode::NonBlocking);
+  })));
+  Ok(())
+}
@@ -0,0 +1,21 @@
+use std::collections::HashMap;
+
+/// JSON descriptions of every record, message and file format comm-opaque
+/// writes, for services that validate these values without parsing them
+#[napi]
+pub fn get_format_descriptions() -> String {
+  comm_opaque::formats::formats_json()
+}
+
+/// Byte lengths of every message, state, key and record, keyed by name (see
+/// `sizes` in comm-opaque). Entries ending in `_overhead` are lengths
+/// without the variable part: the password for client states, the payload
+/// for tagged messages and compressed blobs. The lengths are those of the
+/// default ciphersuite; they don't apply under FIPS mode.
+#[napi]
+pub fn get_sizes() -> HashMap<String, u32> {
+  comm_opaque::sizes::SIZES
+    .iter()
+    .map(|(name, len)| (name.to_string(), *len as u32))
+    .collect()
+}
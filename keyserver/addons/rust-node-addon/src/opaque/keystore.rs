@@ -0,0 +1,83 @@
+//! Keystore files, for keeping the server keypair and pepper on disk. They
+//! take Argon2 with 64 MiB to open, so every function runs on the thread
+//! pool.
+
+use comm_opaque::{
+  keystore::{self, Keystore},
+  server_setup::ServerSetup,
+};
+use napi::{bindgen_prelude::Buffer, Env, JsObject};
+
+use super::{
+  handle_error, pool, server_setup::install_server_setup, TenantOptions,
+};
+
+/// Resolves to the contents of a new keystore file holding a new pepper and
+/// the key from `sealedServerSetup`, or a new key if none is given
+#[napi]
+pub fn create_keystore(
+  env: Env,
+  passphrase: String,
+  sealed_server_setup: Option<String>,
+) -> napi::Result<JsObject> {
+  pool::spawn(&env, move || {
+    let keystore = match sealed_server_setup {
+      Some(sealed) => Keystore::with_new_pepper(
+        ServerSetup::unseal(&sealed).map_err(handle_error)?,
+      ),
+      None => Keystore::generate(),
+    };
+    keystore
+      .seal(passphrase.as_bytes())
+      .map(Buffer::from)
+      .map_err(handle_error)
+  })
+}
+
+#[napi(object)]
+pub struct OpenedKeystore {
+  pub fingerprint: String,
+  pub pepper: Buffer,
+}
+
+/// Decrypts `keystore` and loads its server setup for `tenant`, as
+/// `loadServerSetupFromEnv` does. Rejects if the passphrase is wrong, the
+/// file was modified, or the setup clashes with one already loaded.
+#[napi]
+pub fn open_keystore(
+  env: Env,
+  keystore: Buffer,
+  passphrase: String,
+  options: Option<TenantOptions>,
+) -> napi::Result<JsObject> {
+  let tenant = options.unwrap_or_default().tenant;
+  pool::spawn(&env, move || {
+    let opened =
+      Keystore::open(&keystore, passphrase.as_bytes()).map_err(handle_error)?;
+    let fingerprint =
+      install_server_setup(tenant.as_deref(), opened.server_setup().clone())?;
+    Ok(OpenedKeystore {
+      fingerprint,
+      pepper: opened.pepper().to_vec().into(),
+    })
+  })
+}
+
+/// Resolves to `keystore` re-encrypted under `newPassphrase`
+#[napi]
+pub fn rotate_keystore_passphrase(
+  env: Env,
+  keystore: Buffer,
+  old_passphrase: String,
+  new_passphrase: String,
+) -> napi::Result<JsObject> {
+  pool::spawn(&env, move || {
+    keystore::rotate_passphrase(
+      &keystore,
+      old_passphrase.as_bytes(),
+      new_passphrase.as_bytes(),
+    )
+    .map(Buffer::from)
+    .map_err(handle_error)
+  })
+}
@@ -0,0 +1,44 @@
+use comm_opaque::ksf;
+
+use super::{handle_error, invalid_argument};
+
+#[napi(object)]
+pub struct KsfParams {
+  pub memory_kib: u32,
+  pub iterations: u32,
+  pub parallelism: u32,
+}
+
+fn ksf_id(ksf: u32) -> napi::Result<u8> {
+  u8::try_from(ksf)
+    .map_err(|_| invalid_argument(format!("invalid KSF id {}", ksf)))
+}
+
+/// Picks the KSF parameter set for registrations from here on:
+/// `overrideKsf` if given, otherwise the one suited to this host's memory
+/// and cores. Returns the chosen id. `serverRegisterFinish` and the
+/// re-registration and migration functions stamp records with it, so
+/// clients registering against this server have to pick the same set.
+/// Until this is called, the original Argon2 defaults (id 1) are used.
+#[napi]
+pub fn init_ksf(override_ksf: Option<u32>) -> napi::Result<u32> {
+  let override_ksf = override_ksf.map(ksf_id).transpose()?;
+  ksf::init_ksf(override_ksf)
+    .map(u32::from)
+    .map_err(handle_error)
+}
+
+#[napi]
+pub fn get_registration_ksf() -> u32 {
+  ksf::registration_ksf().into()
+}
+
+#[napi]
+pub fn get_ksf_params(ksf: u32) -> napi::Result<KsfParams> {
+  let params = ksf::ksf_params(ksf_id(ksf)?).map_err(handle_error)?;
+  Ok(KsfParams {
+    memory_kib: params.memory_kib,
+    iterations: params.iterations,
+    parallelism: params.parallelism,
+  })
+}
@@ -0,0 +1,184 @@
+//! Uniform latency for failed logins. With a failure latency set, a server
+//! login step on the thread pool that fails doesn't reject its promise until
+//! that long after it was called, whatever the failure: unknown user, wrong
+//! password, lockout or a malformed request all take the same time to
+//! report, so response times don't reveal which one it was. Failures that
+//! took longer than the latency are reported as soon as they happen.
+//!
+//! Only failures are padded. A login start for a user who exists succeeds
+//! without delay, while one for an unknown user fails, so padding doesn't
+//! hide whether a user exists.
+//!
+//! Delayed settlements wait on a single timer thread rather than on the
+//! thread pool, so padding never holds up other operations.
+
+use std::{
+  cmp::Ordering,
+  collections::BinaryHeap,
+  sync::{
+    atomic::{self, AtomicU32},
+    Condvar, Mutex, Once,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+/// In milliseconds; 0 turns padding off
+static FAILURE_LATENCY: AtomicU32 = AtomicU32::new(0);
+
+/// Sets how long after it was called a failed login step rejects, in
+/// milliseconds. Pick a value above the slowest failure you expect, such as
+/// a legacy login's bcrypt check; 0, the default, turns padding off.
+#[napi]
+pub fn set_failure_latency(milliseconds: u32) {
+  FAILURE_LATENCY.store(milliseconds, atomic::Ordering::Relaxed);
+}
+
+#[napi]
+pub fn get_failure_latency() -> u32 {
+  FAILURE_LATENCY.load(atomic::Ordering::Relaxed)
+}
+
+/// When a failure of an operation that began at `started` should be
+/// reported, or `None` if it should be reported right away
+pub(crate) fn failure_deadline(started: Instant) -> Option<Instant> {
+  padded_deadline(started, get_failure_latency(), Instant::now())
+}
+
+fn padded_deadline(
+  started: Instant,
+  latency_ms: u32,
+  now: Instant,
+) -> Option<Instant> {
+  if latency_ms == 0 {
+    return None;
+  }
+  let deadline = started + Duration::from_millis(latency_ms.into());
+  (deadline > now).then_some(deadline)
+}
+
+struct Scheduled {
+  deadline: Instant,
+  /// Breaks ties so tasks with the same deadline run in the order they were
+  /// scheduled
+  sequence: u64,
+  task: Box<dyn FnOnce() + Send>,
+}
+
+impl PartialEq for Scheduled {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+/// Reversed, so `BinaryHeap` pops the earliest deadline first
+impl Ord for Scheduled {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (other.deadline, other.sequence).cmp(&(self.deadline, self.sequence))
+  }
+}
+
+struct Queue {
+  tasks: BinaryHeap<Scheduled>,
+  next_sequence: u64,
+}
+
+static QUEUE: Mutex<Queue> = Mutex::new(Queue {
+  tasks: BinaryHeap::new(),
+  next_sequence: 0,
+});
+static WAKEUP: Condvar = Condvar::new();
+static TIMER_THREAD: Once = Once::new();
+
+fn run_timer() {
+  let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  loop {
+    let now = Instant::now();
+    let wait = match queue.tasks.peek() {
+      Some(next) if next.deadline <= now => {
+        let next = queue.tasks.pop().expect("peeked task is still queued");
+        drop(queue);
+        (next.task)();
+        queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+        continue;
+      }
+      Some(next) => Some(next.deadline - now),
+      None => None,
+    };
+    queue = match wait {
+      Some(wait) => {
+        WAKEUP
+          .wait_timeout(queue, wait)
+          .unwrap_or_else(|e| e.into_inner())
+          .0
+      }
+      None => WAKEUP.wait(queue).unwrap_or_else(|e| e.into_inner()),
+    };
+  }
+}
+
+/// Runs `task` on the timer thread once `deadline` has passed. Tasks should
+/// only settle a promise or do similarly quick work, since they run one at
+/// a time.
+pub(crate) fn run_at(deadline: Instant, task: impl FnOnce() + Send + 'static) {
+  TIMER_THREAD.call_once(|| {
+    thread::Builder::new()
+      .name("comm-opaque-timer".to_string())
+      .spawn(run_timer)
+      .expect("timer thread can be spawned");
+  });
+  let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  let sequence = queue.next_sequence;
+  queue.next_sequence += 1;
+  queue.tasks.push(Scheduled {
+    deadline,
+    sequence,
+    task: Box::new(task),
+  });
+  drop(queue);
+  WAKEUP.notify_one();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::mpsc;
+
+  #[test]
+  fn test_padded_deadline() {
+    let started = Instant::now();
+    let now = started + Duration::from_millis(40);
+    assert_eq!(padded_deadline(started, 0, now), None);
+    assert_eq!(padded_deadline(started, 40, now), None);
+    assert_eq!(
+      padded_deadline(started, 100, now),
+      Some(started + Duration::from_millis(100))
+    );
+  }
+
+  #[test]
+  fn test_tasks_run_in_deadline_order() {
+    let (sender, receiver) = mpsc::channel();
+    let start = Instant::now();
+    for (label, delay) in [(2, 60), (0, 20), (1, 20)] {
+      let sender = sender.clone();
+      run_at(start + Duration::from_millis(delay), move || {
+        sender.send((label, Instant::now())).unwrap();
+      });
+    }
+    let ran: Vec<_> = receiver.iter().take(3).collect();
+    assert_eq!(
+      ran.iter().map(|(label, _)| *label).collect::<Vec<_>>(),
+      [0, 1, 2]
+    );
+    assert!(ran[2].1 >= start + Duration::from_millis(60));
+  }
+}
@@ -0,0 +1,66 @@
+use comm_opaque::key_backend::ServerKeyBackend;
+use napi::{bindgen_prelude::Buffer, Env, JsObject};
+
+use super::{handle_error, pool, ServerOptions};
+
+/// Whether the password matches the bcrypt hash. Hashes on the calling
+/// thread; `verifyLegacyPasswordAsync` doesn't block the event loop.
+#[napi]
+pub fn verify_legacy_password(
+  legacy_hash: String,
+  password: String,
+) -> napi::Result<bool> {
+  comm_opaque::legacy::verify_legacy_password(&legacy_hash, &password)
+    .map_err(handle_error)
+}
+
+/// `verifyLegacyPassword` on the thread pool
+#[napi]
+pub fn verify_legacy_password_async(
+  env: Env,
+  legacy_hash: String,
+  password: String,
+) -> napi::Result<JsObject> {
+  pool::spawn(&env, move || verify_legacy_password(legacy_hash, password))
+}
+
+/// The OPAQUE password record to store in place of the legacy hash, or null
+/// if the password doesn't match. Hashes on the calling thread;
+/// `migrateLegacyPasswordAsync` doesn't block the event loop.
+#[napi]
+pub fn migrate_legacy_password(
+  legacy_hash: String,
+  password: String,
+  options: Option<ServerOptions>,
+) -> napi::Result<Option<Buffer>> {
+  let server_keypair = options.unwrap_or_default().keypair()?;
+  migrate(&legacy_hash, &password, &server_keypair)
+}
+
+/// `migrateLegacyPassword` on the thread pool
+#[napi]
+pub fn migrate_legacy_password_async(
+  env: Env,
+  legacy_hash: String,
+  password: String,
+  options: Option<ServerOptions>,
+) -> napi::Result<JsObject> {
+  let server_keypair = options.unwrap_or_default().keypair()?;
+  pool::spawn(&env, move || {
+    migrate(&legacy_hash, &password, &server_keypair)
+  })
+}
+
+fn migrate(
+  legacy_hash: &str,
+  password: &str,
+  server_keypair: &dyn ServerKeyBackend,
+) -> napi::Result<Option<Buffer>> {
+  let record = comm_opaque::legacy::migrate_legacy_password(
+    legacy_hash,
+    password,
+    server_keypair,
+  )
+  .map_err(handle_error)?;
+  Ok(record.map(|record| record.serialize().into()))
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use comm_opaque::lockout::{self, LockoutPolicy};
+
+use super::{server_setup::tenant_name, TenantOptions};
+
+/// Durations in milliseconds
+#[napi(object)]
+pub struct LockoutSettings {
+  pub free_attempts: u32,
+  pub base_delay: u32,
+  pub max_delay: u32,
+}
+
+/// A zero `baseDelay` or `maxDelay` turns lockout off. Any other policy
+/// makes `credentialIdentifier` required on every login call.
+#[napi]
+pub fn set_lockout_policy(settings: LockoutSettings) {
+  lockout::set_lockout_policy(LockoutPolicy {
+    free_attempts: settings.free_attempts,
+    base_delay: Duration::from_millis(settings.base_delay.into()),
+    max_delay: Duration::from_millis(settings.max_delay.into()),
+  });
+}
+
+#[napi]
+pub fn get_lockout_policy() -> LockoutSettings {
+  let policy = lockout::lockout_policy();
+  LockoutSettings {
+    free_attempts: policy.free_attempts,
+    base_delay: policy.base_delay.as_millis() as u32,
+    max_delay: policy.max_delay.as_millis() as u32,
+  }
+}
+
+/// Milliseconds until `credentialIdentifier` can log in again on `tenant`,
+/// or null if it isn't locked out
+#[napi]
+pub fn get_lockout_retry_after(
+  credential_identifier: String,
+  options: Option<TenantOptions>,
+) -> Option<u32> {
+  let options = options.unwrap_or_default();
+  lockout::retry_after(
+    tenant_name(options.tenant.as_deref()),
+    &credential_identifier,
+  )
+  .map(|remaining| remaining.as_millis().min(u32::MAX.into()) as u32)
+}
+
+/// Forgets `credentialIdentifier`'s failed attempts on `tenant`, e.g. after
+/// a password reset
+#[napi]
+pub fn clear_lockout(
+  credential_identifier: String,
+  options: Option<TenantOptions>,
+) {
+  let options = options.unwrap_or_default();
+  lockout::record_success(
+    tenant_name(options.tenant.as_deref()),
+    &credential_identifier,
+  );
+}
@@ -0,0 +1,31 @@
+use comm_opaque::metrics;
+
+/// Latencies are in microseconds. `latencyBuckets[i]` counts operations
+/// that took less than 2^i microseconds (the last bucket also counts
+/// everything slower).
+#[napi(object)]
+pub struct OperationMetrics {
+  pub operation: String,
+  pub count: i64,
+  pub failures: i64,
+  pub total_latency: i64,
+  pub latency_buckets: Vec<i64>,
+}
+
+#[napi]
+pub fn get_metrics() -> Vec<OperationMetrics> {
+  metrics::snapshot()
+    .into_iter()
+    .map(|snapshot| OperationMetrics {
+      operation: snapshot.operation.name().to_string(),
+      count: snapshot.count as i64,
+      failures: snapshot.failures as i64,
+      total_latency: snapshot.total_micros as i64,
+      latency_buckets: snapshot
+        .latency_buckets
+        .iter()
+        .map(|&count| count as i64)
+        .collect(),
+    })
+    .collect()
+}
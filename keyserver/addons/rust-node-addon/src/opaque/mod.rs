@@ -0,0 +1,239 @@
+//! OPAQUE bindings. Synchronous functions take serialized states and records
+//! as `BufferSlice`, which borrows the JavaScript buffer for the duration of
+//! the call without copying it or creating a reference to it; JavaScript
+//! can't run, and so can't modify the buffer, until the call returns.
+
+pub mod abort;
+pub mod attestation;
+pub mod batch;
+pub mod bulk_registration;
+pub mod channel;
+pub mod client_login;
+pub mod client_registration;
+pub mod compression;
+pub mod conformance;
+pub mod derivation;
+pub mod errors;
+pub mod events;
+pub mod formats;
+pub mod keystore;
+pub mod ksf;
+pub mod latency;
+pub mod legacy;
+pub mod lockout;
+pub mod metrics;
+pub mod policy;
+pub mod pool;
+pub mod replay;
+pub mod rng;
+pub mod rotation;
+pub mod server;
+pub mod server_setup;
+pub mod transition;
+pub mod upgrade;
+pub mod version;
+
+use std::{collections::BTreeMap, sync::RwLock};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use napi::{bindgen_prelude::Buffer, Env, Error, Status};
+use opaque_ke::keypair::KeyPair;
+use sha2::{Digest, Sha256};
+
+const PARSED_KEYPAIRS_LIMIT: usize = 16;
+
+/// Server keypairs parsed from `serverPrivateKey` options, keyed by a hash
+/// of the private key so secrets are never compared directly
+static PARSED_KEYPAIRS: RwLock<BTreeMap<[u8; 32], KeyPair<RistrettoPoint>>> =
+  RwLock::new(BTreeMap::new());
+
+/// Every error message starts with one of the codes `getErrorCatalog`
+/// lists, then a colon
+pub(crate) fn handle_error(e: impl Into<comm_opaque::Error>) -> Error {
+  let e = e.into();
+  Error::new(Status::GenericFailure, format!("{}: {}", e.code(), e))
+}
+
+/// `handle_error` for steps that check the password policy. Violations are
+/// thrown with the code `PASSWORD_POLICY` rather than one of napi's
+/// statuses, so callers can tell them apart from bad arguments
+/// (`InvalidArg`) and failures of the protocol itself (`GenericFailure`).
+/// Setting the code takes an `Env`, so this runs on the JavaScript thread.
+pub(crate) fn handle_policy_error(env: &Env, e: comm_opaque::Error) -> Error {
+  if !matches!(e, comm_opaque::Error::PasswordPolicy(_)) {
+    return handle_error(e);
+  }
+  let code = e.code();
+  let thrown = env.create_error(handle_error(e)).and_then(|mut error| {
+    error.set_named_property("code", env.create_string(code)?)?;
+    Ok(error)
+  });
+  match thrown {
+    Ok(error) => Error::from(error.into_unknown()),
+    Err(e) => e,
+  }
+}
+
+/// For arguments JavaScript passed that can't be used, such as a key of the
+/// wrong length
+pub(crate) fn invalid_argument(message: impl ToString) -> Error {
+  Error::new(
+    Status::InvalidArg,
+    format!("{}: {}", errors::INVALID_ARGUMENT, message.to_string()),
+  )
+}
+
+/// For calls the addon isn't set up for, such as a login before the server
+/// setup is loaded
+pub(crate) fn invalid_state(message: impl ToString) -> Error {
+  Error::new(
+    Status::GenericFailure,
+    format!("{}: {}", errors::INVALID_STATE, message.to_string()),
+  )
+}
+
+/// Parsing a private key derives its public key, a scalar multiplication,
+/// and every call passes one of the same few keys, so parsed keypairs are
+/// memoized. That saves the parse and nothing more: the login's own group
+/// operations multiply per-login points by the server's scalar, which no
+/// table for the server key can speed up, and the ones by the generator
+/// already use curve25519-dalek's precomputed table.
+pub(crate) fn parse_server_keypair(
+  server_private_key: &[u8],
+) -> napi::Result<KeyPair<RistrettoPoint>> {
+  let key_hash: [u8; 32] = Sha256::digest(server_private_key).into();
+  let parsed = PARSED_KEYPAIRS.read().unwrap_or_else(|e| e.into_inner());
+  if let Some(keypair) = parsed.get(&key_hash) {
+    return Ok(keypair.clone());
+  }
+  drop(parsed);
+  // opaque-ke panics on keys of the wrong length
+  if server_private_key.len() != 32 {
+    return Err(invalid_argument("server private key must be 32 bytes"));
+  }
+  let keypair =
+    KeyPair::from_private_key_slice(server_private_key).map_err(|e| {
+      invalid_argument(format!("invalid server private key: {}", e))
+    })?;
+  let mut parsed = PARSED_KEYPAIRS.write().unwrap_or_else(|e| e.into_inner());
+  if parsed.len() >= PARSED_KEYPAIRS_LIMIT {
+    parsed.clear();
+  }
+  parsed.insert(key_hash, keypair.clone());
+  Ok(keypair)
+}
+
+/// The options every server call takes last, so that adding one doesn't
+/// move any other argument. The keypair is `serverPrivateKey` if given,
+/// otherwise that of the setup loaded for `tenant`. Lockouts and security
+/// events are recorded under `tenant` and `credentialIdentifier`, by the
+/// calls that record them; without a tenant, they use the default one.
+/// Login calls throw without a `credentialIdentifier` unless lockout is
+/// turned off (a lockout policy with a zero delay).
+#[napi(object)]
+#[derive(Default)]
+pub struct ServerOptions {
+  pub server_private_key: Option<Buffer>,
+  pub tenant: Option<String>,
+  pub credential_identifier: Option<String>,
+}
+
+impl ServerOptions {
+  pub(crate) fn keypair(&self) -> napi::Result<KeyPair<RistrettoPoint>> {
+    match &self.server_private_key {
+      Some(server_private_key) => parse_server_keypair(server_private_key),
+      None => Ok(
+        server_setup::loaded_server_setup(self.tenant.as_deref())?
+          .keypair()
+          .clone(),
+      ),
+    }
+  }
+
+  pub(crate) fn tenant(&self) -> &str {
+    server_setup::tenant_name(self.tenant.as_deref())
+  }
+
+  pub(crate) fn credential_identifier(&self) -> Option<&str> {
+    self.credential_identifier.as_deref()
+  }
+
+  /// The identifier a login is throttled under: `credentialIdentifier`,
+  /// which is required while the lockout policy locks, so that leaving it
+  /// out can't get around the lockout
+  pub(crate) fn lockout_identifier(&self) -> napi::Result<Option<&str>> {
+    match self.credential_identifier() {
+      None if comm_opaque::lockout::lockout_policy().locks() => {
+        Err(invalid_argument(
+          "credentialIdentifier is required while lockout is enabled",
+        ))
+      }
+      identifier => Ok(identifier),
+    }
+  }
+}
+
+/// For calls that only need to know the tenant, such as loading a setup
+#[napi(object)]
+#[derive(Default)]
+pub struct TenantOptions {
+  pub tenant: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use comm_opaque::Cipher;
+  use opaque_ke::{ciphersuite::CipherSuite, rand::rngs::OsRng};
+
+  #[test]
+  fn test_parsed_keypairs_are_memoized() {
+    let keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let private_key = keypair.private().to_arr();
+    for _ in 0..2 {
+      let parsed = parse_server_keypair(&private_key).unwrap();
+      assert_eq!(parsed.public().to_arr(), keypair.public().to_arr());
+    }
+    let key_hash: [u8; 32] = Sha256::digest(&private_key).into();
+    assert!(PARSED_KEYPAIRS.read().unwrap().contains_key(&key_hash));
+    assert!(parse_server_keypair(&[0; 3]).is_err());
+  }
+
+  #[test]
+  fn test_wrong_length_server_keys_throw() {
+    for key in [&[0; 0][..], &[1; 31], &[1; 33], &[1; 64]] {
+      let thrown = parse_server_keypair(key).unwrap_err();
+      assert_eq!(thrown.status, Status::InvalidArg);
+      assert!(thrown.reason.starts_with(errors::INVALID_ARGUMENT));
+    }
+  }
+
+  #[test]
+  fn test_thrown_errors_dont_quote_secrets() {
+    let legacy_hash = "$2b$04$SECRETSALTSECRETSALT";
+    let e = comm_opaque::legacy::verify_legacy_password(legacy_hash, "hunter2")
+      .unwrap_err();
+    let thrown = handle_error(e);
+    assert!(!thrown.reason.contains("SECRETSALT"), "{}", thrown.reason);
+    assert!(!thrown.reason.contains("hunter2"), "{}", thrown.reason);
+  }
+
+  #[test]
+  fn test_thrown_codes_are_in_the_catalog() {
+    let catalog = errors::get_error_catalog();
+    for thrown in [
+      handle_error(comm_opaque::Error::KeyBackend),
+      invalid_argument("bad"),
+      invalid_state("bad"),
+    ] {
+      let code = thrown.reason.split(':').next().unwrap();
+      assert!(catalog.iter().any(|entry| entry.code == code), "{}", code);
+    }
+  }
+
+  #[test]
+  fn test_opaque_matches_shared_fixtures() {
+    let mismatches = comm_opaque::conformance::verify_all().unwrap();
+    assert!(mismatches.is_empty(), "{:#?}", mismatches);
+  }
+}
@@ -0,0 +1,73 @@
+use comm_opaque::{
+  policy::{self, PasswordPolicy, VersionPolicy},
+  record::SuiteVersion,
+};
+
+use super::invalid_argument;
+
+#[napi(object)]
+pub struct MinimumVersions {
+  pub wire_version: u32,
+  pub suite: u32,
+  pub ksf: u32,
+}
+
+fn to_u8(value: u32, name: &str) -> napi::Result<u8> {
+  u8::try_from(value)
+    .map_err(|_| invalid_argument(format!("invalid {} version", name)))
+}
+
+/// Messages and records below these versions are rejected from now on
+#[napi]
+pub fn set_minimum_versions(versions: MinimumVersions) -> napi::Result<()> {
+  policy::set_version_policy(VersionPolicy {
+    min_wire_version: u16::try_from(versions.wire_version)
+      .map_err(|_| invalid_argument("invalid wire version"))?,
+    min_suite_version: SuiteVersion {
+      suite: to_u8(versions.suite, "suite")?,
+      ksf: to_u8(versions.ksf, "KSF")?,
+    },
+  });
+  Ok(())
+}
+
+#[napi]
+pub fn get_minimum_versions() -> MinimumVersions {
+  let policy = policy::version_policy();
+  MinimumVersions {
+    wire_version: policy.min_wire_version.into(),
+    suite: policy.min_suite_version.suite.into(),
+    ksf: policy.min_suite_version.ksf.into(),
+  }
+}
+
+#[napi(object)]
+pub struct PasswordRequirements {
+  pub min_length: u32,
+  pub min_character_classes: u32,
+  pub min_entropy_bits: u32,
+}
+
+/// Applies to every `clientRegisterStart` from now on
+#[napi]
+pub fn set_password_policy(
+  requirements: PasswordRequirements,
+) -> napi::Result<()> {
+  policy::set_password_policy(PasswordPolicy {
+    min_length: requirements.min_length as usize,
+    min_character_classes: u8::try_from(requirements.min_character_classes)
+      .map_err(|_| invalid_argument("invalid number of character classes"))?,
+    min_entropy_bits: requirements.min_entropy_bits,
+  });
+  Ok(())
+}
+
+#[napi]
+pub fn get_password_policy() -> PasswordRequirements {
+  let policy = policy::password_policy();
+  PasswordRequirements {
+    min_length: policy.min_length as u32,
+    min_character_classes: policy.min_character_classes.into(),
+    min_entropy_bits: policy.min_entropy_bits,
+  }
+}
@@ -0,0 +1,196 @@
+//! The one thread pool that runs every slow native operation (Argon2,
+//! bcrypt, batch operations), so they never block the event loop and their
+//! combined CPU use is bounded by a single limit rather than by the number
+//! of calls in flight.
+//!
+//! A task may hold on to a `Buffer` argument instead of copying it (napi
+//! releases the reference on the JavaScript thread however it's dropped),
+//! in which case the caller must not modify that buffer until the promise
+//! settles, the same contract as Node's own asynchronous APIs.
+
+use std::{
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex, OnceLock,
+  },
+  time::Instant,
+};
+
+use napi::{
+  bindgen_prelude::{External, ToNapiValue},
+  threadsafe_function::{
+    ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction,
+    ThreadsafeFunctionCallMode,
+  },
+  Env, JsFunction, JsObject,
+};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use super::{invalid_state, latency};
+
+static POOL: OnceLock<ThreadPool> = OnceLock::new();
+
+/// Operations spawned but not yet finished, whether queued or running
+static QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+static PRESSURE_THRESHOLD: AtomicUsize = AtomicUsize::new(0);
+static PRESSURE_LISTENER: Mutex<
+  Option<ThreadsafeFunction<QueuePressure, ErrorStrategy::Fatal>>,
+> = Mutex::new(None);
+
+fn build_pool(num_threads: Option<u32>) -> napi::Result<ThreadPool> {
+  ThreadPoolBuilder::new()
+    .num_threads(num_threads.unwrap_or(0) as usize)
+    .thread_name(|index| format!("comm-opaque-{}", index))
+    .build()
+    .map_err(invalid_state)
+}
+
+pub(crate) fn pool() -> &'static ThreadPool {
+  POOL.get_or_init(|| {
+    build_pool(None).expect("default thread pool can be created")
+  })
+}
+
+/// Sizes the thread pool; the default is one thread per CPU. Throws if the
+/// pool is already running, which it is after the first slow operation, so
+/// call this at startup.
+#[napi]
+pub fn init_thread_pool(num_threads: Option<u32>) -> napi::Result<()> {
+  let pool = build_pool(num_threads)?;
+  POOL
+    .set(pool)
+    .map_err(|_| invalid_state("thread pool is already initialized"))
+}
+
+#[napi]
+pub fn get_thread_pool_size() -> u32 {
+  pool().current_num_threads() as u32
+}
+
+#[napi]
+pub fn get_queue_depth() -> u32 {
+  QUEUE_DEPTH.load(Ordering::Relaxed) as u32
+}
+
+#[napi(object)]
+pub struct QueuePressure {
+  pub queue_depth: u32,
+  pub overloaded: bool,
+}
+
+/// Calls `listener` with `overloaded: true` whenever the queue depth rises
+/// to `threshold`, and with `overloaded: false` when it falls back below.
+/// Replaces any previous listener; a threshold of 0 turns notifications off.
+/// The listener doesn't keep the process alive.
+#[napi]
+pub fn on_queue_pressure(
+  env: Env,
+  threshold: u32,
+  listener: JsFunction,
+) -> napi::Result<()> {
+  let mut listener: ThreadsafeFunction<QueuePressure, ErrorStrategy::Fatal> =
+    listener.create_threadsafe_function(
+      0,
+      |ctx: ThreadSafeCallContext<QueuePressure>| Ok(vec![ctx.value]),
+    )?;
+  listener.unref(&env)?;
+  *PRESSURE_LISTENER.lock().unwrap_or_else(|e| e.into_inner()) = Some(listener);
+  PRESSURE_THRESHOLD.store(threshold as usize, Ordering::Relaxed);
+  Ok(())
+}
+
+/// Depth changes one at a time, so every crossing passes through exactly
+/// `threshold` (on the way up) or `threshold - 1` (on the way down)
+fn notify_if_crossed(previous_depth: usize, depth: usize) {
+  let threshold = PRESSURE_THRESHOLD.load(Ordering::Relaxed);
+  if threshold == 0 || previous_depth.max(depth) != threshold {
+    return;
+  }
+  let listener = PRESSURE_LISTENER.lock().unwrap_or_else(|e| e.into_inner());
+  if let Some(listener) = listener.as_ref() {
+    listener.call(
+      QueuePressure {
+        queue_depth: depth as u32,
+        overloaded: depth >= threshold,
+      },
+      ThreadsafeFunctionCallMode::NonBlocking,
+    );
+  }
+}
+
+/// Runs `task` on the pool and returns a promise for its result
+pub(crate) fn spawn<T, F>(env: &Env, task: F) -> napi::Result<JsObject>
+where
+  T: ToNapiValue + Send + 'static,
+  F: FnOnce() -> napi::Result<T> + Send + 'static,
+{
+  spawn_with(env, task, |value| value)
+}
+
+/// Like `spawn`, for tasks whose result is returned as an opaque handle
+pub(crate) fn spawn_external<T, F>(env: &Env, task: F) -> napi::Result<JsObject>
+where
+  T: Send + 'static,
+  F: FnOnce() -> napi::Result<T> + Send + 'static,
+{
+  spawn_with(env, task, External::new)
+}
+
+/// Like `spawn`, for login steps: if `task` fails, the promise rejects no
+/// sooner than the failure latency after this call
+pub(crate) fn spawn_padded<T, F>(env: &Env, task: F) -> napi::Result<JsObject>
+where
+  T: ToNapiValue + Send + 'static,
+  F: FnOnce() -> napi::Result<T> + Send + 'static,
+{
+  let started = Instant::now();
+  let (deferred, promise) = env.create_deferred()?;
+  run(move || match task() {
+    Ok(value) => deferred.resolve(move |_| Ok(value)),
+    Err(e) => match latency::failure_deadline(started) {
+      Some(deadline) => latency::run_at(deadline, move || deferred.reject(e)),
+      None => deferred.reject(e),
+    },
+  });
+  Ok(promise)
+}
+
+fn spawn_with<T, R, F>(
+  env: &Env,
+  task: F,
+  into_js: fn(T) -> R,
+) -> napi::Result<JsObject>
+where
+  T: Send + 'static,
+  R: ToNapiValue + 'static,
+  F: FnOnce() -> napi::Result<T> + Send + 'static,
+{
+  let (deferred, promise) = env.create_deferred()?;
+  run(move || match task() {
+    Ok(value) => deferred.resolve(move |_| Ok(into_js(value))),
+    Err(e) => deferred.reject(e),
+  });
+  Ok(promise)
+}
+
+/// Runs `task` on the pool, counting it towards the queue depth
+pub(crate) fn run(task: impl FnOnce() + Send + 'static) {
+  let depth = QUEUE_DEPTH.fetch_add(1, Ordering::Relaxed);
+  notify_if_crossed(depth, depth + 1);
+  pool().spawn(move || {
+    task();
+    let depth = QUEUE_DEPTH.fetch_sub(1, Ordering::Relaxed);
+    notify_if_crossed(depth, depth - 1);
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_pool_size() {
+    assert_eq!(build_pool(Some(3)).unwrap().current_num_threads(), 3);
+    assert!(build_pool(None).unwrap().current_num_threads() >= 1);
+  }
+}
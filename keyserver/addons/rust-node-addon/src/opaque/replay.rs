@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use comm_opaque::replay::{self, ReplayPolicy};
+
+/// `window` in milliseconds; 0 turns replay detection off
+#[napi(object)]
+pub struct ReplaySettings {
+  pub window: u32,
+  pub capacity: u32,
+}
+
+/// Replays are only caught by the process that answered the original
+/// request: every process keeps its own cache in memory, so a cluster or a
+/// set of keyservers behind a load balancer doesn't share one, and a
+/// restart clears it
+#[napi]
+pub fn set_replay_policy(settings: ReplaySettings) {
+  replay::set_replay_policy(ReplayPolicy {
+    window: Duration::from_millis(settings.window.into()),
+    capacity: settings.capacity as usize,
+  });
+}
+
+#[napi]
+pub fn get_replay_policy() -> ReplaySettings {
+  let policy = replay::replay_policy();
+  ReplaySettings {
+    window: policy.window.as_millis().min(u32::MAX.into()) as u32,
+    capacity: policy.capacity.min(u32::MAX as usize) as u32,
+  }
+}
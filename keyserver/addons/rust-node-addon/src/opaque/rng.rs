@@ -0,0 +1,68 @@
+/// Makes every thread seed its random number generator from the OS again
+/// before its next use. Worth calling after anything that may have duplicated
+/// the process's memory, such as restoring a VM snapshot; forks are detected
+/// automatically.
+#[napi]
+pub fn force_reseed() {
+  comm_opaque::rng::force_reseed();
+}
+
+/// Exactly one of `seed` (32 bytes) and `bytes` (repeated as needed)
+#[cfg(feature = "mock-rng")]
+#[napi(object)]
+pub struct MockRngSource {
+  pub seed: Option<napi::bindgen_prelude::Buffer>,
+  pub bytes: Option<napi::bindgen_prelude::Buffer>,
+}
+
+#[cfg(feature = "mock-rng")]
+fn mock_source(
+  source: MockRngSource,
+) -> napi::Result<comm_opaque::rng::MockSource> {
+  use super::invalid_argument;
+  use comm_opaque::rng::MockSource;
+
+  match (source.seed, source.bytes) {
+    (Some(seed), None) => {
+      Ok(MockSource::Seed(seed.as_ref().try_into().map_err(
+        |_| invalid_argument("mock RNG seed must be 32 bytes"),
+      )?))
+    }
+    (None, Some(bytes)) if !bytes.is_empty() => {
+      Ok(MockSource::Bytes(bytes.to_vec()))
+    }
+    _ => Err(invalid_argument("mock RNG needs a seed or non-empty bytes")),
+  }
+}
+
+/// Test builds only (the `mock-rng` feature): serves the next `draws`
+/// random draws from `source`, so tests can expect exact outputs. Draws on
+/// every thread count, so prefer `withMockRng` when other operations may
+/// run meanwhile.
+#[cfg(feature = "mock-rng")]
+#[napi]
+pub fn set_mock_rng(source: MockRngSource, draws: u32) -> napi::Result<()> {
+  comm_opaque::rng::set_mock_rng(mock_source(source)?, draws);
+  Ok(())
+}
+
+/// Test builds only (the `mock-rng` feature): calls `callback` and returns
+/// what it returns, with every random draw it makes on the JavaScript
+/// thread served from `source`. Only synchronous functions are covered,
+/// since the others draw on the thread pool.
+#[cfg(feature = "mock-rng")]
+#[napi]
+pub fn with_mock_rng(
+  source: MockRngSource,
+  callback: napi::JsFunction,
+) -> napi::Result<napi::JsUnknown> {
+  comm_opaque::rng::with_mock_rng(mock_source(source)?, || {
+    callback.call_without_args(None)
+  })
+}
+
+#[cfg(feature = "mock-rng")]
+#[napi]
+pub fn clear_mock_rng() {
+  comm_opaque::rng::clear_mock_rng();
+}
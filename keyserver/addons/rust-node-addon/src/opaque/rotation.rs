@@ -0,0 +1,77 @@
+//! Planning a server key rotation campaign over exported records
+
+use comm_opaque::{rotation, server_setup::ServerSetup};
+use napi::{bindgen_prelude::Buffer, Env, JsObject};
+
+use super::{
+  abort::{AbortFlag, AbortHandle},
+  handle_error, pool,
+};
+
+#[napi(object)]
+pub struct UserRecord {
+  pub user_id: String,
+  pub record: Buffer,
+}
+
+#[napi(object)]
+pub struct KeyRotationReport {
+  /// Records to store in place of the exported ones; only their container
+  /// format changed
+  pub resealed: Vec<UserRecord>,
+  /// Users who have to register again (after their next login) before the
+  /// old key can be retired
+  pub needs_reregistration: Vec<String>,
+  /// Users whose records don't parse
+  pub invalid: Vec<String>,
+  /// Users whose records weren't looked at because the plan was aborted,
+  /// to pass to a later call
+  pub unprocessed: Vec<String>,
+}
+
+/// Resolves to a `KeyRotationReport` for moving `records` from the old
+/// sealed setup to the new one. An OPAQUE record commits to the server key,
+/// so if the keys differ every user ends up in `needsReregistration`.
+/// Aborting `abort` stops the plan at the next record.
+#[napi]
+pub fn plan_key_rotation(
+  env: Env,
+  old_sealed_server_setup: String,
+  new_sealed_server_setup: String,
+  records: Vec<UserRecord>,
+  abort: Option<&AbortHandle>,
+) -> napi::Result<JsObject> {
+  let abort = AbortFlag::new(abort);
+  let old_setup =
+    ServerSetup::unseal(&old_sealed_server_setup).map_err(handle_error)?;
+  let new_setup =
+    ServerSetup::unseal(&new_sealed_server_setup).map_err(handle_error)?;
+  let records: Vec<(String, Vec<u8>)> = records
+    .into_iter()
+    .map(|UserRecord { user_id, record }| (user_id, record.to_vec()))
+    .collect();
+  pool::spawn(&env, move || {
+    let mut records = records.into_iter();
+    let until_aborted = std::iter::from_fn(|| {
+      if abort.is_raised() {
+        None
+      } else {
+        records.next()
+      }
+    });
+    let report = rotation::plan_rotation(&old_setup, &new_setup, until_aborted);
+    Ok(KeyRotationReport {
+      resealed: report
+        .resealed
+        .into_iter()
+        .map(|(user_id, record)| UserRecord {
+          user_id,
+          record: record.into(),
+        })
+        .collect(),
+      needs_reregistration: report.needs_reregistration,
+      invalid: report.invalid,
+      unprocessed: records.map(|(user_id, _)| user_id).collect(),
+    })
+  })
+}
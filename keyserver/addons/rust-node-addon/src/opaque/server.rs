@@ -0,0 +1,194 @@
+//! The server half of registration and login, interoperating with the
+//! client bindings (`clientRegister*`, `clientLogin*`). Messages and states
+//! go in and out as buffers; the login state is comm-opaque's
+//! `ServerLoggingIn`, sealed under a key derived from the server's, so a
+//! login can be finished by another worker than the one that started it,
+//! and the state can be stored (in Redis, say) without exposing the
+//! session key.
+//!
+//! Every call takes `ServerOptions` last. Logins count towards the
+//! `credentialIdentifier`'s lockout and emit security events like
+//! `serverTransitionLogin*`; users with only a legacy hash go through those
+//! instead.
+
+use comm_opaque::{
+  api::{LoginStateKey, ServerLoggingIn, ServerRegistering},
+  events::{self, LoginMethod, RegistrationSource, SecurityEvent},
+  lockout,
+  record::PasswordRecord,
+  server_setup::ServerSetup,
+  OpaqueServer,
+};
+use napi::{
+  bindgen_prelude::{Buffer, BufferSlice},
+  Env, JsObject,
+};
+
+use super::{handle_error, pool, server_setup, ServerOptions, TenantOptions};
+
+/// Generates a server setup, loads it for `tenant` and returns it sealed,
+/// to store and pass to the other workers (see `loadServerSetupFromEnv`).
+/// Throws if `tenant` already has a setup.
+#[napi]
+pub fn server_setup(options: Option<TenantOptions>) -> napi::Result<String> {
+  let setup = ServerSetup::generate();
+  let sealed = setup.seal();
+  server_setup::install_server_setup(
+    options.unwrap_or_default().tenant.as_deref(),
+    setup,
+  )?;
+  Ok(sealed)
+}
+
+#[napi(object)]
+pub struct ServerRegistrationStartResult {
+  pub registration_response: Buffer,
+  pub server_registration_state: Buffer,
+}
+
+#[napi]
+pub fn server_register_start(
+  registration_request: BufferSlice<'_>,
+  options: Option<ServerOptions>,
+) -> napi::Result<ServerRegistrationStartResult> {
+  let server = OpaqueServer::new(options.unwrap_or_default().keypair()?);
+  let (server_registering, registration_response) = server
+    .register(&registration_request)
+    .map_err(handle_error)?;
+  Ok(ServerRegistrationStartResult {
+    registration_response: registration_response.into(),
+    server_registration_state: server_registering.serialize().into(),
+  })
+}
+
+/// Returns the record to store for the user. The options' `tenant` and
+/// `credentialIdentifier` only label the security event.
+#[napi]
+pub fn server_register_finish(
+  server_registration_state: BufferSlice<'_>,
+  registration_upload: BufferSlice<'_>,
+  options: Option<ServerOptions>,
+) -> napi::Result<Buffer> {
+  let options = options.unwrap_or_default();
+  let record = ServerRegistering::deserialize(&server_registration_state)
+    .and_then(|state| state.finish(&registration_upload))
+    .map_err(handle_error)?;
+  events::emit(SecurityEvent::RegistrationCreated {
+    tenant: options.tenant().to_string(),
+    credential_identifier: options.credential_identifier,
+    source: RegistrationSource::Registration,
+  });
+  Ok(record.serialize().into())
+}
+
+/// `ksf` is the KSF parameter set to send to the client with the credential
+/// response, and `needsReregistration` means the client should be asked to
+/// register again once the login completes. `transcriptHash` identifies the
+/// login once it finishes.
+#[napi(object)]
+pub struct ServerLoginStartResult {
+  pub credential_response: Buffer,
+  pub server_login_state: Buffer,
+  pub ksf: u32,
+  pub needs_reregistration: bool,
+  pub transcript_hash: Buffer,
+}
+
+/// Resolves to a `ServerLoginStartResult`. Rejects while the options'
+/// `credentialIdentifier` is locked out; every failure is padded to the
+/// failure latency. A login that starts counts as a failure towards the
+/// lockout until `serverLoginFinish` succeeds, since the client already
+/// learns from the response whether its password was right.
+#[napi]
+pub fn server_login_start(
+  env: Env,
+  record: Buffer,
+  credential_request: Buffer,
+  options: Option<ServerOptions>,
+) -> napi::Result<JsObject> {
+  let options = options.unwrap_or_default();
+  pool::spawn_padded(&env, move || {
+    let tenant = options.tenant();
+    let credential_identifier = options.lockout_identifier()?;
+    if let Some(identifier) = credential_identifier {
+      let lockout = lockout::check(tenant, identifier);
+      if lockout.is_err() {
+        events::emit_login(
+          tenant,
+          Some(identifier),
+          LoginMethod::Opaque,
+          &lockout,
+        );
+      }
+      lockout.map_err(handle_error)?;
+    }
+    let server = OpaqueServer::new(options.keypair()?);
+    let started = LoginStateKey::derive(server.server_key()).and_then(|key| {
+      let record = PasswordRecord::deserialize(&record)?;
+      let (server_logging_in, credential_response) =
+        server.login(record, &credential_request)?;
+      login_start_result(server_logging_in, credential_response, &key)
+    });
+    match (&started, credential_identifier) {
+      (Ok(_), Some(identifier)) => {
+        lockout::record_login_start(tenant, identifier)
+      }
+      (Ok(_), None) => (),
+      // A login that started emits its event once it finishes
+      (Err(_), _) => events::emit_login(
+        tenant,
+        credential_identifier,
+        LoginMethod::Opaque,
+        &started,
+      ),
+    }
+    started.map_err(handle_error)
+  })
+}
+
+fn login_start_result(
+  server_logging_in: ServerLoggingIn,
+  credential_response: Vec<u8>,
+  key: &LoginStateKey,
+) -> Result<ServerLoginStartResult, comm_opaque::Error> {
+  Ok(ServerLoginStartResult {
+    credential_response: credential_response.into(),
+    server_login_state: server_logging_in.seal(key)?.into(),
+    ksf: server_logging_in.ksf().into(),
+    needs_reregistration: server_logging_in.needs_reregistration(),
+    transcript_hash: server_logging_in.transcript_hash().to_vec().into(),
+  })
+}
+
+/// Resolves to the session key, and clears the failure the start counted
+/// towards the options' `credentialIdentifier`'s lockout. The options must
+/// select the server that started the login, whose key the state is sealed
+/// under. Rejects if the state doesn't open or the client failed to
+/// authenticate, after the failure latency; the start's failure then
+/// stands.
+#[napi]
+pub fn server_login_finish(
+  env: Env,
+  server_login_state: Buffer,
+  credential_finalization: Buffer,
+  options: Option<ServerOptions>,
+) -> napi::Result<JsObject> {
+  let options = options.unwrap_or_default();
+  pool::spawn_padded(&env, move || {
+    let tenant = options.tenant();
+    let credential_identifier = options.lockout_identifier()?;
+    let session_key = LoginStateKey::derive(&options.keypair()?)
+      .and_then(|key| ServerLoggingIn::unseal(&server_login_state, &key))
+      .and_then(|state| state.finish(&credential_finalization));
+    if let (Ok(_), Some(identifier)) = (&session_key, credential_identifier) {
+      lockout::record_success(tenant, identifier);
+    }
+    events::emit_login(
+      tenant,
+      credential_identifier,
+      LoginMethod::Opaque,
+      &session_key,
+    );
+    session_key.map(Buffer::from).map_err(handle_error)
+  })
+}
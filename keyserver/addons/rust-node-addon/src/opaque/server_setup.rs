@@ -0,0 +1,161 @@
+//! Server keypair shared by every worker of a Node cluster. The primary
+//! generates (or reads) the sealed setup once and passes it to the workers
+//! in the environment; each worker loads it and reports its fingerprint back
+//! so the primary can refuse to serve if any two differ.
+//!
+//! A process serving several communities loads one setup per tenant, by
+//! passing a `tenant` option wherever a setup is loaded or used; calls
+//! without one use the default tenant. Each tenant must have its own key.
+//! Lockouts, replay detection and security events are kept per tenant too,
+//! so the same credential identifier on two tenants is two users.
+
+use std::{
+  collections::BTreeMap,
+  sync::{Arc, RwLock},
+};
+
+use comm_opaque::server_setup::ServerSetup;
+
+use super::{handle_error, invalid_state, TenantOptions};
+
+/// The tenant of calls that don't name one
+const DEFAULT_TENANT: &str = "default";
+
+static SERVER_SETUPS: RwLock<BTreeMap<String, Arc<ServerSetup>>> =
+  RwLock::new(BTreeMap::new());
+
+pub(crate) fn tenant_name(tenant: Option<&str>) -> &str {
+  tenant.unwrap_or(DEFAULT_TENANT)
+}
+
+/// The setup loaded for `tenant` by `loadServerSetupFromEnv` or
+/// `openKeystore`
+pub(crate) fn loaded_server_setup(
+  tenant: Option<&str>,
+) -> napi::Result<Arc<ServerSetup>> {
+  let tenant = tenant_name(tenant);
+  SERVER_SETUPS
+    .read()
+    .unwrap_or_else(|e| e.into_inner())
+    .get(tenant)
+    .cloned()
+    .ok_or_else(|| {
+      invalid_state(format!(
+        "server setup has not been loaded for tenant {}",
+        tenant
+      ))
+    })
+}
+
+#[napi]
+pub fn generate_sealed_server_setup() -> String {
+  ServerSetup::generate().seal()
+}
+
+/// Loads the sealed setup from `envVar` (`COMM_OPAQUE_SERVER_SETUP` by
+/// default) for `tenant` and returns its fingerprint. Throws if a setup with
+/// a different key is already loaded for `tenant`, or if another tenant
+/// uses this key.
+#[napi]
+pub fn load_server_setup_from_env(
+  env_var: Option<String>,
+  options: Option<TenantOptions>,
+) -> napi::Result<String> {
+  let setup =
+    ServerSetup::from_env(env_var.as_deref()).map_err(handle_error)?;
+  install_server_setup(options.unwrap_or_default().tenant.as_deref(), setup)
+}
+
+/// Makes `setup` the setup loaded for `tenant` and returns its fingerprint,
+/// unless the tenant already has a setup with a different key or another
+/// tenant has this one
+pub(crate) fn install_server_setup(
+  tenant: Option<&str>,
+  setup: ServerSetup,
+) -> napi::Result<String> {
+  let tenant = tenant_name(tenant);
+  let fingerprint = setup.fingerprint();
+  let mut loaded = SERVER_SETUPS.write().unwrap_or_else(|e| e.into_inner());
+  if let Some((other, _)) = loaded.iter().find(|(other, existing)| {
+    *other != tenant && existing.fingerprint() == fingerprint
+  }) {
+    return Err(invalid_state(format!(
+      "this server setup is already loaded for tenant {}",
+      other
+    )));
+  }
+  match loaded.get(tenant) {
+    Some(existing) if existing.fingerprint() != fingerprint => {
+      return Err(invalid_state(format!(
+        "a different server setup is already loaded for tenant {}",
+        tenant
+      )))
+    }
+    Some(_) => (),
+    None => {
+      loaded.insert(tenant.to_string(), Arc::new(setup));
+    }
+  }
+  Ok(fingerprint)
+}
+
+/// Fingerprint of the setup loaded for `tenant`, or null if none is loaded
+#[napi]
+pub fn get_server_setup_fingerprint(
+  options: Option<TenantOptions>,
+) -> Option<String> {
+  loaded_server_setup(options.unwrap_or_default().tenant.as_deref())
+    .ok()
+    .map(|setup| setup.fingerprint())
+}
+
+/// The tenants with a loaded setup, the default one included
+#[napi]
+pub fn get_server_setup_tenants() -> Vec<String> {
+  SERVER_SETUPS
+    .read()
+    .unwrap_or_else(|e| e.into_inner())
+    .keys()
+    .cloned()
+    .collect()
+}
+
+/// Forgets `tenant`'s setup, so its calls fail until one is loaded again.
+/// Returns whether one was loaded.
+#[napi]
+pub fn unload_server_setup(options: Option<TenantOptions>) -> bool {
+  let options = options.unwrap_or_default();
+  SERVER_SETUPS
+    .write()
+    .unwrap_or_else(|e| e.into_inner())
+    .remove(tenant_name(options.tenant.as_deref()))
+    .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_tenants_are_isolated() {
+    let first = ServerSetup::generate();
+    let second = ServerSetup::generate();
+    let fingerprint = install_server_setup(Some("a"), first.clone()).unwrap();
+    assert_eq!(
+      install_server_setup(Some("a"), first.clone()).unwrap(),
+      fingerprint
+    );
+    assert!(install_server_setup(Some("a"), second.clone()).is_err());
+    assert!(install_server_setup(Some("b"), first).is_err());
+    install_server_setup(Some("b"), second.clone()).unwrap();
+    assert_eq!(
+      loaded_server_setup(Some("b")).unwrap().fingerprint(),
+      second.fingerprint()
+    );
+    assert!(loaded_server_setup(Some("c")).is_err());
+    assert!(unload_server_setup(Some(TenantOptions {
+      tenant: Some("a".to_string()),
+    })));
+    assert!(loaded_server_setup(Some("a")).is_err());
+  }
+}
@@ -0,0 +1,263 @@
+use comm_opaque::{
+  api::{LoginStateKey, ServerLoggingIn},
+  events::{self, LoginMethod, RegistrationSource, SecurityEvent},
+  lockout,
+  record::PasswordRecord,
+  transition::{self, LoginOutcome, LoginRequest, StoredCredentials},
+};
+use napi::{
+  bindgen_prelude::{Buffer, BufferSlice},
+  Env, JsObject,
+};
+use opaque_ke::CredentialRequest;
+
+use super::{
+  handle_error, invalid_argument, invalid_state, pool, ServerOptions,
+};
+
+#[napi(object)]
+pub struct TransitionStoredCredentials {
+  pub record: Option<Buffer>,
+  pub legacy_hash: Option<String>,
+}
+
+#[napi(object)]
+pub struct TransitionLoginRequest {
+  pub credential_request: Option<Buffer>,
+  pub password: Option<String>,
+}
+
+/// Exactly one of `credentialResponse` (with `serverLoginState` and `ksf`,
+/// the KSF parameter set the client has to finish the login with) or
+/// `migratedRecord` is set. `needsReregistration` means the client should be
+/// asked to register again once the login completes. `transcriptHash`,
+/// set with `credentialResponse`, identifies the login once it finishes, for
+/// channel binding; the client computes the same value. `serverLoginState`
+/// is sealed the same way as `serverLoginStart`'s, under the key of the
+/// server that started the login.
+#[napi(object)]
+pub struct TransitionLoginStartResult {
+  pub credential_response: Option<Buffer>,
+  pub server_login_state: Option<Buffer>,
+  pub ksf: Option<u32>,
+  pub needs_reregistration: bool,
+  pub migrated_record: Option<Buffer>,
+  pub transcript_hash: Option<Buffer>,
+}
+
+/// Starts a login on the calling thread; see
+/// `serverTransitionLoginStartAsync`, which legacy logins should prefer since
+/// they hash the password twice (bcrypt, then Argon2). Throws while the
+/// options' `credentialIdentifier` is locked out. A legacy login's result
+/// counts towards its lockout; an OPAQUE login counts as a failure once it
+/// starts, until `serverTransitionLoginFinish` succeeds.
+#[napi]
+pub fn server_transition_login_start(
+  stored: TransitionStoredCredentials,
+  request: TransitionLoginRequest,
+  options: Option<ServerOptions>,
+) -> napi::Result<TransitionLoginStartResult> {
+  transition_login_start(stored, request, &options.unwrap_or_default())
+}
+
+/// `serverTransitionLoginStart` on the thread pool, resolving to a
+/// `TransitionLoginStartResult`. Every failure, including a malformed
+/// request, is padded to the failure latency.
+#[napi]
+pub fn server_transition_login_start_async(
+  env: Env,
+  stored: TransitionStoredCredentials,
+  request: TransitionLoginRequest,
+  options: Option<ServerOptions>,
+) -> napi::Result<JsObject> {
+  let options = options.unwrap_or_default();
+  pool::spawn_padded(&env, move || {
+    transition_login_start(stored, request, &options)
+  })
+}
+
+fn transition_login_start(
+  stored: TransitionStoredCredentials,
+  request: TransitionLoginRequest,
+  options: &ServerOptions,
+) -> napi::Result<TransitionLoginStartResult> {
+  let method = match request.credential_request {
+    Some(_) => LoginMethod::Opaque,
+    None => LoginMethod::Legacy,
+  };
+  let tenant = options.tenant();
+  if let Some(identifier) = options.lockout_identifier()? {
+    let lockout = lockout::check(tenant, identifier);
+    if lockout.is_err() {
+      events::emit_login(tenant, Some(identifier), method, &lockout);
+    }
+    lockout.map_err(handle_error)?;
+  }
+  let server_keypair = options.keypair()?;
+  let record = stored
+    .record
+    .map(|bytes| PasswordRecord::deserialize(&bytes))
+    .transpose()
+    .map_err(handle_error)?;
+  let credential_request = request.credential_request.map(Vec::from);
+  let request = match (&credential_request, request.password) {
+    (Some(credential_request), None) => LoginRequest::Opaque(Box::new(
+      CredentialRequest::deserialize(credential_request)
+        .map_err(handle_error)?,
+    )),
+    (None, Some(password)) => LoginRequest::Legacy(password),
+    _ => {
+      return Err(invalid_argument(
+        "exactly one of credentialRequest or password must be provided",
+      ))
+    }
+  };
+  let stored = StoredCredentials {
+    record,
+    legacy_hash: stored.legacy_hash,
+  };
+  let key = LoginStateKey::derive(&server_keypair).map_err(handle_error)?;
+  let outcome =
+    transition::server_login_start(stored, request, &server_keypair);
+  record_login_start(options, method, &outcome);
+  login_start_result(
+    outcome.map_err(handle_error)?,
+    credential_request.as_deref(),
+    &key,
+  )
+}
+
+/// An OPAQUE login that started counts as a failure until it finishes, and
+/// emits no event yet; everything else is final
+fn record_login_start(
+  options: &ServerOptions,
+  method: LoginMethod,
+  outcome: &Result<LoginOutcome, comm_opaque::Error>,
+) {
+  let tenant = options.tenant();
+  let credential_identifier = options.credential_identifier();
+  if let Ok(LoginOutcome::OpaqueStarted { .. }) = outcome {
+    if let Some(identifier) = credential_identifier {
+      lockout::record_login_start(tenant, identifier);
+    }
+    return;
+  }
+  if let (LoginMethod::Legacy, Some(identifier)) =
+    (method, credential_identifier)
+  {
+    lockout::record_outcome(tenant, identifier, outcome);
+  }
+  events::emit_login(tenant, credential_identifier, method, outcome);
+  if let Ok(LoginOutcome::LegacyMigrated(_)) = outcome {
+    events::emit(SecurityEvent::RegistrationCreated {
+      tenant: tenant.to_string(),
+      credential_identifier: credential_identifier.map(str::to_string),
+      source: RegistrationSource::LegacyMigration,
+    });
+  }
+}
+
+fn login_start_result(
+  outcome: LoginOutcome,
+  credential_request: Option<&[u8]>,
+  key: &LoginStateKey,
+) -> napi::Result<TransitionLoginStartResult> {
+  match outcome {
+    LoginOutcome::OpaqueStarted {
+      result,
+      ksf,
+      needs_reregistration,
+    } => {
+      // Only OPAQUE requests start OPAQUE logins
+      let credential_request = credential_request
+        .ok_or_else(|| invalid_state("OPAQUE login without a request"))?;
+      let (server_logging_in, credential_response) =
+        ServerLoggingIn::from_start(
+          *result,
+          ksf,
+          needs_reregistration,
+          credential_request,
+        )
+        .map_err(handle_error)?;
+      Ok(TransitionLoginStartResult {
+        credential_response: Some(credential_response.into()),
+        server_login_state: Some(
+          server_logging_in.seal(key).map_err(handle_error)?.into(),
+        ),
+        ksf: Some(ksf.into()),
+        needs_reregistration,
+        migrated_record: None,
+        transcript_hash: Some(
+          server_logging_in.transcript_hash().to_vec().into(),
+        ),
+      })
+    }
+    LoginOutcome::LegacyMigrated(record) => Ok(TransitionLoginStartResult {
+      credential_response: None,
+      server_login_state: None,
+      ksf: None,
+      needs_reregistration: false,
+      migrated_record: Some(record.serialize().into()),
+      transcript_hash: None,
+    }),
+  }
+}
+
+/// Completes the OPAQUE branch of `serverTransitionLoginStart`, returning
+/// the session key, and clears the failure the start counted towards the
+/// options' `credentialIdentifier`'s lockout. The options must select the
+/// server that started the login, whose key the state is sealed under.
+/// Throws if the state doesn't open or the client failed to authenticate.
+#[napi]
+pub fn server_transition_login_finish(
+  server_login_state: BufferSlice<'_>,
+  credential_finalization: Buffer,
+  options: Option<ServerOptions>,
+) -> napi::Result<Buffer> {
+  transition_login_finish(
+    &server_login_state,
+    &credential_finalization,
+    &options.unwrap_or_default(),
+  )
+}
+
+/// `serverTransitionLoginFinish` on the thread pool, resolving to the
+/// session key. A rejection is padded to the failure latency.
+#[napi]
+pub fn server_transition_login_finish_async(
+  env: Env,
+  server_login_state: Buffer,
+  credential_finalization: Buffer,
+  options: Option<ServerOptions>,
+) -> napi::Result<JsObject> {
+  let options = options.unwrap_or_default();
+  pool::spawn_padded(&env, move || {
+    transition_login_finish(
+      &server_login_state,
+      &credential_finalization,
+      &options,
+    )
+  })
+}
+
+fn transition_login_finish(
+  server_login_state: &[u8],
+  credential_finalization: &[u8],
+  options: &ServerOptions,
+) -> napi::Result<Buffer> {
+  let credential_identifier = options.lockout_identifier()?;
+  let session_key = LoginStateKey::derive(&options.keypair()?)
+    .and_then(|key| ServerLoggingIn::unseal(server_login_state, &key))
+    .and_then(|state| state.finish(credential_finalization));
+  let tenant = options.tenant();
+  if let (Ok(_), Some(identifier)) = (&session_key, credential_identifier) {
+    lockout::record_success(tenant, identifier);
+  }
+  events::emit_login(
+    tenant,
+    credential_identifier,
+    LoginMethod::Opaque,
+    &session_key,
+  );
+  session_key.map(Buffer::from).map_err(handle_error)
+}
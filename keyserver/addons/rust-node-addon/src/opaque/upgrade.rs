@@ -0,0 +1,73 @@
+use comm_opaque::{
+  events::{self, RegistrationSource, SecurityEvent},
+  upgrade::{self, UpgradeSession},
+  Cipher,
+};
+use napi::bindgen_prelude::{Buffer, BufferSlice};
+use opaque_ke::ServerRegistration;
+
+use super::{handle_error, ServerOptions};
+
+#[napi(object)]
+pub struct ReregistrationStartResult {
+  pub registration_response: Buffer,
+  pub server_registration_state: Buffer,
+}
+
+/// `sessionKey` is the key returned by the login that flagged the record with
+/// `needsReregistration`; `tag` is the client's MAC over the request.
+#[napi]
+pub fn server_reregistration_start(
+  session_key: Buffer,
+  registration_request: Buffer,
+  tag: Buffer,
+  options: Option<ServerOptions>,
+) -> napi::Result<ReregistrationStartResult> {
+  let server_keypair = options.unwrap_or_default().keypair()?;
+  let server_registration_start_result = upgrade::server_reregistration_start(
+    &UpgradeSession::new(&session_key),
+    &registration_request,
+    &tag,
+    &server_keypair,
+  )
+  .map_err(handle_error)?;
+  Ok(ReregistrationStartResult {
+    registration_response: server_registration_start_result
+      .message
+      .serialize()
+      .into(),
+    server_registration_state: server_registration_start_result
+      .state
+      .serialize()
+      .into(),
+  })
+}
+
+/// Returns the record to store in place of the outdated one. The options'
+/// `tenant` and `credentialIdentifier` only label the security event.
+#[napi]
+pub fn server_reregistration_finish(
+  session_key: Buffer,
+  server_registration_state: BufferSlice<'_>,
+  registration_upload: BufferSlice<'_>,
+  tag: Buffer,
+  options: Option<ServerOptions>,
+) -> napi::Result<Buffer> {
+  let options = options.unwrap_or_default();
+  let server_registration =
+    ServerRegistration::<Cipher>::deserialize(&server_registration_state)
+      .map_err(handle_error)?;
+  let record = upgrade::server_reregistration_finish(
+    &UpgradeSession::new(&session_key),
+    server_registration,
+    &registration_upload,
+    &tag,
+  )
+  .map_err(handle_error)?;
+  events::emit(SecurityEvent::RegistrationCreated {
+    tenant: options.tenant().to_string(),
+    credential_identifier: options.credential_identifier,
+    source: RegistrationSource::Reregistration,
+  });
+  Ok(record.serialize().into())
+}
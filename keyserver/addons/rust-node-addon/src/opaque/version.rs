@@ -0,0 +1,91 @@
+use comm_opaque::version::{self, MessageType};
+use napi::bindgen_prelude::Buffer;
+
+use super::{handle_error, invalid_argument};
+
+#[napi]
+pub enum WireMessageType {
+  RegistrationRequest,
+  RegistrationResponse,
+  RegistrationUpload,
+  CredentialRequest,
+  CredentialResponse,
+  CredentialFinalization,
+}
+
+impl From<WireMessageType> for MessageType {
+  fn from(message_type: WireMessageType) -> Self {
+    match message_type {
+      WireMessageType::RegistrationRequest => Self::RegistrationRequest,
+      WireMessageType::RegistrationResponse => Self::RegistrationResponse,
+      WireMessageType::RegistrationUpload => Self::RegistrationUpload,
+      WireMessageType::CredentialRequest => Self::CredentialRequest,
+      WireMessageType::CredentialResponse => Self::CredentialResponse,
+      WireMessageType::CredentialFinalization => Self::CredentialFinalization,
+    }
+  }
+}
+
+#[napi(object)]
+pub struct UntaggedMessage {
+  pub version: u32,
+  pub payload: Buffer,
+}
+
+fn to_wire_versions(versions: Vec<u32>) -> napi::Result<Vec<u16>> {
+  versions
+    .into_iter()
+    .map(|version| {
+      u16::try_from(version).map_err(|_| {
+        invalid_argument(format!("invalid wire version {}", version))
+      })
+    })
+    .collect()
+}
+
+#[napi]
+pub fn get_supported_wire_versions() -> Vec<u32> {
+  version::SUPPORTED_WIRE_VERSIONS
+    .iter()
+    .map(|&version| version.into())
+    .collect()
+}
+
+/// Returns the highest version both sides support, or null if none
+#[napi]
+pub fn negotiate_version(
+  client_supported: Vec<u32>,
+  server_supported: Vec<u32>,
+) -> napi::Result<Option<u32>> {
+  Ok(
+    version::negotiate_version(
+      &to_wire_versions(client_supported)?,
+      &to_wire_versions(server_supported)?,
+    )
+    .map(u32::from),
+  )
+}
+
+#[napi]
+pub fn tag_message(
+  version: u32,
+  message_type: WireMessageType,
+  payload: Buffer,
+) -> napi::Result<Buffer> {
+  let version = to_wire_versions(vec![version])?[0];
+  Ok(version::tag_message(version, message_type.into(), &payload).into())
+}
+
+#[napi]
+pub fn untag_message(
+  message: Buffer,
+  expected_type: WireMessageType,
+) -> napi::Result<UntaggedMessage> {
+  let (version, payload) =
+    version::untag_message(&message, expected_type.into())
+      .map_err(handle_error)?;
+  Ok(UntaggedMessage {
+    version: version.into(),
+    payload: payload.to_vec().into(),
+  })
+}
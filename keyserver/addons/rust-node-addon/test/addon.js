@@ -0,0 +1,22 @@
+// Loads the debug build made by `yarn build:debug`, for the tests in this
+// directory. index.js can't be used since it has Flow annotations.
+
+import { createRequire } from 'module';
+
+const require = createRequire(import.meta.url);
+
+const triples = {
+  'darwin-x64': 'darwin-x64',
+  'darwin-arm64': 'darwin-arm64',
+  'linux-x64': 'linux-x64-gnu',
+  'linux-arm64': 'linux-arm64-gnu',
+};
+
+const triple = triples[`${process.platform}-${process.arch}`];
+if (!triple) {
+  throw new Error(
+    `Unsupported OS: ${process.platform}, architecture: ${process.arch}`,
+  );
+}
+
+export default require(`../napi/rust-node-addon.${triple}.node`);
@@ -0,0 +1,55 @@
+import assert from 'assert';
+import { before, describe, it } from 'node:test';
+
+import addon from './addon.js';
+
+describe('bulkRegister', () => {
+  before(() => {
+    addon.serverSetup();
+  });
+
+  const respond = (index, request) =>
+    addon.serverRegisterStart(request).registrationResponse;
+
+  it('registers every account', async () => {
+    const results = await addon.bulkRegister(['a', 'b', 'c'], respond);
+    assert.strictEqual(results.length, 3);
+    for (const result of results) {
+      assert.ok(result.registrationUpload);
+      assert.ok(result.exportKey);
+      assert.strictEqual(result.error, undefined);
+    }
+  });
+
+  it('fails only the account whose responder throws', async () => {
+    const results = await addon.bulkRegister(
+      ['a', 'b', 'c'],
+      (index, request) => {
+        if (index === 1) {
+          throw new Error('responder threw');
+        }
+        return respond(index, request);
+      },
+    );
+    assert.match(results[1].error, /responder threw/);
+    assert.strictEqual(results[1].registrationUpload, undefined);
+    assert.ok(results[0].registrationUpload);
+    assert.ok(results[2].registrationUpload);
+  });
+
+  it('fails only the account whose responder rejects', async () => {
+    const results = await addon.bulkRegister(
+      ['a', 'b', 'c'],
+      async (index, request) => {
+        if (index === 1) {
+          throw new Error('responder rejected');
+        }
+        return respond(index, request);
+      },
+    );
+    assert.match(results[1].error, /responder rejected/);
+    assert.strictEqual(results[1].registrationUpload, undefined);
+    assert.ok(results[0].registrationUpload);
+    assert.ok(results[2].registrationUpload);
+  });
+});
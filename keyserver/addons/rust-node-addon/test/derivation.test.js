@@ -0,0 +1,34 @@
+import assert from 'assert';
+import { describe, it } from 'node:test';
+
+import addon from './addon.js';
+
+describe('createDeriver', () => {
+  const sessionKey = Buffer.alloc(64, 7);
+
+  it('derives under its own labels only', () => {
+    const deriver = addon.createDeriver({ token: 'identity service token' });
+    assert.equal(deriver.labels.token, 'identity service token');
+    assert.notDeepEqual(
+      deriver.deriveToken(sessionKey, 'backup'),
+      addon.deriveToken(sessionKey, 'backup'),
+    );
+    assert.deepEqual(
+      deriver.deriveSessionSubkey(sessionKey, 'messages'),
+      addon.deriveSessionSubkey(sessionKey, 'messages'),
+    );
+  });
+
+  it('leaves other derivers alone', () => {
+    const before = addon.createDeriver().deriveToken(sessionKey, '');
+    addon.createDeriver({ token: 'another token' });
+    assert.deepEqual(addon.createDeriver().deriveToken(sessionKey, ''), before);
+    assert.deepEqual(addon.deriveToken(sessionKey, ''), before);
+  });
+
+  it('throws on labels that are not distinct', () => {
+    assert.throws(() =>
+      addon.createDeriver({ backupKey: 'same', token: 'same' }),
+    );
+  });
+});
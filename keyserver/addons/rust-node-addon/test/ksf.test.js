@@ -0,0 +1,56 @@
+import assert from 'assert';
+import { before, describe, it } from 'node:test';
+
+import addon from './addon.js';
+
+const options = { credentialIdentifier: 'alice' };
+
+function register(password) {
+  const start = addon.clientRegisterStart(password);
+  const { registrationResponse, serverRegistrationState } =
+    addon.serverRegisterStart(addon.getRegistrationStartMessageArray(start));
+  const finish = addon.clientRegisterFinish(
+    addon.getRegistrationStartStateArray(start),
+    registrationResponse,
+  );
+  return addon.serverRegisterFinish(
+    serverRegistrationState,
+    addon.getRegistrationFinishMessageArray(finish),
+  );
+}
+
+async function logIn(record, password) {
+  const start = addon.clientLoginStart(password);
+  const serverStart = await addon.serverLoginStart(
+    record,
+    addon.getLoginStartMessageArray(start),
+    options,
+  );
+  const clientFinish = await addon.clientLoginFinish(
+    addon.getLoginStartStateArray(start),
+    serverStart.credentialResponse,
+    serverStart.ksf,
+  );
+  const sessionKey = await addon.serverLoginFinish(
+    serverStart.serverLoginState,
+    clientFinish.credentialFinalization,
+    options,
+  );
+  assert.deepEqual(sessionKey, clientFinish.sessionKey);
+  return serverStart.ksf;
+}
+
+describe('initKsf', () => {
+  before(() => {
+    addon.serverSetup();
+  });
+
+  it('applies to new registrations and not to stored records', async () => {
+    const stored = register('hunter2');
+    assert.equal(addon.initKsf(2), 2);
+    const registered = register('hunter2');
+    assert.equal(await logIn(registered, 'hunter2'), 2);
+    assert.equal(await logIn(stored, 'hunter2'), 1);
+    addon.initKsf(1);
+  });
+});
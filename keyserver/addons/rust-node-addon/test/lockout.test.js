@@ -0,0 +1,54 @@
+import assert from 'assert';
+import { before, describe, it } from 'node:test';
+
+import addon from './addon.js';
+
+const legacyHash =
+  '$2b$04$DXQGkEB8WhkVDXar/aL6FOTBdcPg4Ex10H0LQbFSUkxbaoj07PHEK';
+
+function startLogin(record, options) {
+  const start = addon.clientLoginStart('wrong guess');
+  return addon.serverTransitionLoginStart(
+    { record },
+    { credentialRequest: addon.getLoginStartMessageArray(start) },
+    options,
+  );
+}
+
+describe('lockout', () => {
+  let record;
+
+  before(() => {
+    addon.serverSetup();
+    record = addon.serverTransitionLoginStart(
+      { legacyHash },
+      { password: 'hunter2' },
+      { credentialIdentifier: 'carol' },
+    ).migratedRecord;
+    addon.setLockoutPolicy({
+      freeAttempts: 2,
+      baseDelay: 60000,
+      maxDelay: 60000,
+    });
+  });
+
+  it('locks after starts that are never finished', () => {
+    const options = { credentialIdentifier: 'carol' };
+    for (let i = 0; i < 3; i++) {
+      assert.ok(startLogin(record, options).credentialResponse);
+    }
+    assert.ok(addon.getLockoutRetryAfter('carol'));
+    assert.throws(() => startLogin(record, options));
+  });
+
+  it('requires a credentialIdentifier while enabled', () => {
+    assert.throws(() => startLogin(record), /credentialIdentifier/);
+    assert.throws(
+      () =>
+        addon.serverTransitionLoginFinish(Buffer.alloc(16), Buffer.alloc(16)),
+      /credentialIdentifier/,
+    );
+    addon.setLockoutPolicy({ freeAttempts: 0, baseDelay: 0, maxDelay: 0 });
+    assert.ok(startLogin(record).credentialResponse);
+  });
+});
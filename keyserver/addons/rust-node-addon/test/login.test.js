@@ -0,0 +1,103 @@
+import assert from 'assert';
+import { before, describe, it } from 'node:test';
+
+import addon from './addon.js';
+
+const options = { credentialIdentifier: 'alice' };
+
+async function register(password, clientRegisterFinish) {
+  const start = addon.clientRegisterStart(password);
+  const { registrationResponse, serverRegistrationState } =
+    addon.serverRegisterStart(addon.getRegistrationStartMessageArray(start));
+  const finish = await clientRegisterFinish(
+    addon.getRegistrationStartStateArray(start),
+    registrationResponse,
+  );
+  return addon.serverRegisterFinish(
+    serverRegistrationState,
+    addon.getRegistrationFinishMessageArray(finish),
+  );
+}
+
+async function logIn(record, password) {
+  const start = addon.clientLoginStart(password);
+  const serverStart = await addon.serverLoginStart(
+    record,
+    addon.getLoginStartMessageArray(start),
+    options,
+  );
+  const clientFinish = await addon.clientLoginFinish(
+    addon.getLoginStartStateArray(start),
+    serverStart.credentialResponse,
+    serverStart.ksf,
+  );
+  return { serverStart, clientFinish };
+}
+
+describe('clientLogin* against serverLogin*', () => {
+  let record;
+
+  before(async () => {
+    addon.serverSetup();
+    record = await register('hunter2', addon.clientRegisterFinish);
+  });
+
+  it('agrees on the session', async () => {
+    const { serverStart, clientFinish } = await logIn(record, 'hunter2');
+    assert.equal(clientFinish.serverPublicKey.length, 32);
+    assert.equal(clientFinish.exportKey.length, 64);
+    assert.deepEqual(clientFinish.transcriptHash, serverStart.transcriptHash);
+    const sessionKey = await addon.serverLoginFinish(
+      serverStart.serverLoginState,
+      clientFinish.credentialFinalization,
+      options,
+    );
+    assert.deepEqual(sessionKey, clientFinish.sessionKey);
+  });
+
+  it('keeps the export key across logins', async () => {
+    const first = await logIn(record, 'hunter2');
+    const second = await logIn(record, 'hunter2');
+    assert.deepEqual(
+      first.clientFinish.exportKey,
+      second.clientFinish.exportKey,
+    );
+    assert.notDeepEqual(
+      first.clientFinish.sessionKey,
+      second.clientFinish.sessionKey,
+    );
+  });
+
+  it('rejects a wrong password on the client', async () => {
+    await assert.rejects(logIn(record, 'hunter3'));
+  });
+
+  it('logs in with a record registered on the thread pool', async () => {
+    const asyncRecord = await register(
+      'correct horse',
+      addon.clientRegisterFinishAsync,
+    );
+    const { serverStart, clientFinish } = await logIn(
+      asyncRecord,
+      'correct horse',
+    );
+    const sessionKey = await addon.serverLoginFinish(
+      serverStart.serverLoginState,
+      clientFinish.credentialFinalization,
+      options,
+    );
+    assert.deepEqual(sessionKey, clientFinish.sessionKey);
+  });
+});
+
+describe('clientRegisterFinish', () => {
+  const garbage = Buffer.alloc(16);
+
+  it('throws synchronously', () => {
+    assert.throws(() => addon.clientRegisterFinish(garbage, garbage));
+  });
+
+  it('has an async variant that checks its arguments first', () => {
+    assert.throws(() => addon.clientRegisterFinishAsync(garbage, garbage));
+  });
+});
@@ -0,0 +1,32 @@
+import assert from 'assert';
+import { after, before, describe, it } from 'node:test';
+
+import addon from './addon.js';
+
+describe('clientRegisterStart', () => {
+  let previousPolicy;
+  before(() => {
+    previousPolicy = addon.getPasswordPolicy();
+    addon.setPasswordPolicy({
+      minLength: 12,
+      minCharacterClasses: 1,
+      minEntropyBits: 0,
+    });
+  });
+  after(() => addon.setPasswordPolicy(previousPolicy));
+
+  const violation = { code: 'PASSWORD_POLICY' };
+
+  it('throws policy violations with their own code', () => {
+    assert.throws(() => addon.clientRegisterStart('short'), violation);
+  });
+
+  it('rejects with the same code when async', async () => {
+    await assert.rejects(addon.clientRegisterStartAsync('short'), violation);
+  });
+
+  it('accepts a password that meets the policy', async () => {
+    assert.ok(addon.clientRegisterStart('long enough password'));
+    assert.ok(await addon.clientRegisterStartAsync('long enough password'));
+  });
+});
@@ -0,0 +1,83 @@
+import assert from 'assert';
+import { before, describe, it } from 'node:test';
+
+import addon from './addon.js';
+
+async function startLogin(record, password, credentialIdentifier) {
+  const start = addon.clientLoginStart(password);
+  const serverStart = await addon.serverLoginStart(
+    record,
+    addon.getLoginStartMessageArray(start),
+    { credentialIdentifier },
+  );
+  return { start, serverStart };
+}
+
+describe('serverLogin* lockout', () => {
+  let record;
+
+  before(() => {
+    addon.serverSetup();
+    addon.setLockoutPolicy({
+      freeAttempts: 2,
+      baseDelay: 60000,
+      maxDelay: 60000,
+    });
+    addon.setFailureLatency(0);
+    const start = addon.clientRegisterStart('hunter2');
+    const { registrationResponse, serverRegistrationState } =
+      addon.serverRegisterStart(addon.getRegistrationStartMessageArray(start));
+    const finish = addon.clientRegisterFinish(
+      addon.getRegistrationStartStateArray(start),
+      registrationResponse,
+    );
+    record = addon.serverRegisterFinish(
+      serverRegistrationState,
+      addon.getRegistrationFinishMessageArray(finish),
+    );
+  });
+
+  it('clears a start once the login finishes', async () => {
+    for (let i = 0; i < 4; i++) {
+      const { start, serverStart } = await startLogin(record, 'hunter2', 'bob');
+      const clientFinish = await addon.clientLoginFinish(
+        addon.getLoginStartStateArray(start),
+        serverStart.credentialResponse,
+        serverStart.ksf,
+      );
+      const sessionKey = await addon.serverLoginFinish(
+        serverStart.serverLoginState,
+        clientFinish.credentialFinalization,
+        { credentialIdentifier: 'bob' },
+      );
+      assert.deepEqual(sessionKey, clientFinish.sessionKey);
+    }
+    assert.strictEqual(addon.getLockoutRetryAfter('bob'), null);
+  });
+
+  it('locks after repeated starts with no finish', async () => {
+    for (let i = 0; i < 3; i++) {
+      await startLogin(record, 'wrong guess', 'mallory');
+    }
+    assert.ok(addon.getLockoutRetryAfter('mallory'));
+    await assert.rejects(startLogin(record, 'wrong guess', 'mallory'));
+  });
+
+  it('keeps counting a start whose state does not open', async () => {
+    const { serverStart } = await startLogin(record, 'hunter2', 'dave');
+    const corrupted = Buffer.from(serverStart.serverLoginState);
+    corrupted[corrupted.length - 1] ^= 1;
+    await assert.rejects(
+      addon.serverLoginFinish(corrupted, Buffer.alloc(64), {
+        credentialIdentifier: 'dave',
+      }),
+    );
+    await startLogin(record, 'hunter2', 'dave');
+    await startLogin(record, 'hunter2', 'dave');
+    assert.ok(addon.getLockoutRetryAfter('dave'));
+  });
+
+  it('requires a credentialIdentifier', async () => {
+    await assert.rejects(startLogin(record, 'hunter2'), /credentialIdentifier/);
+  });
+});
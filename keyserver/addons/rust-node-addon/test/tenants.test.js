@@ -0,0 +1,73 @@
+import assert from 'assert';
+import { before, describe, it } from 'node:test';
+
+import addon from './addon.js';
+
+const legacyHash =
+  '$2b$04$DXQGkEB8WhkVDXar/aL6FOTBdcPg4Ex10H0LQbFSUkxbaoj07PHEK';
+
+describe('tenants', () => {
+  before(() => {
+    addon.serverSetup({ tenant: 'first' });
+    addon.serverSetup({ tenant: 'second' });
+    addon.setLockoutPolicy({
+      freeAttempts: 0,
+      baseDelay: 60000,
+      maxDelay: 60000,
+    });
+  });
+
+  it('have their own server setups', () => {
+    const first = addon.getServerSetupFingerprint({ tenant: 'first' });
+    const second = addon.getServerSetupFingerprint({ tenant: 'second' });
+    assert.ok(first);
+    assert.ok(second);
+    assert.notStrictEqual(first, second);
+    assert.strictEqual(
+      addon.getServerSetupFingerprint({ tenant: 'none' }),
+      null,
+    );
+  });
+
+  it('have their own lockouts and events', async () => {
+    const events = [];
+    addon.onSecurityEvent(event => events.push(event));
+    const options = { tenant: 'first', credentialIdentifier: 'alice' };
+    assert.throws(() =>
+      addon.serverTransitionLoginStart(
+        { legacyHash },
+        { password: 'wrong' },
+        options,
+      ),
+    );
+    assert.ok(addon.getLockoutRetryAfter('alice', { tenant: 'first' }));
+    assert.strictEqual(
+      addon.getLockoutRetryAfter('alice', { tenant: 'second' }),
+      null,
+    );
+    const result = addon.serverTransitionLoginStart(
+      { legacyHash },
+      { password: 'hunter2' },
+      { tenant: 'second', credentialIdentifier: 'alice' },
+    );
+    assert.ok(result.migratedRecord);
+    // Events are delivered on a later turn of the event loop
+    await new Promise(resolve => setTimeout(resolve, 50));
+    addon.onSecurityEvent(null);
+    assert.ok(
+      events.some(
+        event => event.kind === 'lockout_triggered' && event.tenant === 'first',
+      ),
+    );
+    assert.ok(
+      events.some(
+        event => event.kind === 'login_succeeded' && event.tenant === 'second',
+      ),
+    );
+    addon.clearLockout('alice', { tenant: 'first' });
+    assert.strictEqual(
+      addon.getLockoutRetryAfter('alice', { tenant: 'first' }),
+      null,
+    );
+  });
+});
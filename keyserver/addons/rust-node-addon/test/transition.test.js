@@ -0,0 +1,63 @@
+import assert from 'assert';
+import { describe, it } from 'node:test';
+
+import addon from './addon.js';
+
+describe('serverTransitionLoginFinish', () => {
+  const garbage = Buffer.alloc(16);
+
+  it('throws synchronously', () => {
+    assert.throws(() => addon.serverTransitionLoginFinish(garbage, garbage));
+  });
+
+  it('has an async variant that rejects', async () => {
+    await assert.rejects(
+      addon.serverTransitionLoginFinishAsync(garbage, garbage),
+    );
+  });
+});
+
+describe('serverTransitionLoginStart', () => {
+  const legacyHash =
+    '$2b$04$DXQGkEB8WhkVDXar/aL6FOTBdcPg4Ex10H0LQbFSUkxbaoj07PHEK';
+  const options = { credentialIdentifier: 'erin' };
+
+  it('seals its state and rejects one that was tampered with', async () => {
+    addon.serverSetup();
+    const { migratedRecord } = addon.serverTransitionLoginStart(
+      { legacyHash },
+      { password: 'hunter2' },
+      options,
+    );
+    const start = addon.clientLoginStart('hunter2');
+    const serverStart = addon.serverTransitionLoginStart(
+      { record: migratedRecord },
+      { credentialRequest: addon.getLoginStartMessageArray(start) },
+      options,
+    );
+    const clientFinish = await addon.clientLoginFinish(
+      addon.getLoginStartStateArray(start),
+      serverStart.credentialResponse,
+      serverStart.ksf,
+    );
+    assert.strictEqual(
+      serverStart.serverLoginState.subarray(0, 4).toString(),
+      'cSLS',
+    );
+    const tampered = Buffer.from(serverStart.serverLoginState);
+    tampered[tampered.length - 1] ^= 1;
+    assert.throws(() =>
+      addon.serverTransitionLoginFinish(
+        tampered,
+        clientFinish.credentialFinalization,
+        options,
+      ),
+    );
+    const sessionKey = addon.serverTransitionLoginFinish(
+      serverStart.serverLoginState,
+      clientFinish.credentialFinalization,
+      options,
+    );
+    assert.deepEqual(sessionKey, clientFinish.sessionKey);
+  });
+});
@@ -501,3 +501,12 @@ async fn send_to_mpsc<T>(
   }
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  #[test]
+  fn opaque_matches_shared_fixtures() {
+    let mismatches = comm_opaque::conformance::verify_all().unwrap();
+    assert!(mismatches.is_empty(), "{:#?}", mismatches);
+  }
+}
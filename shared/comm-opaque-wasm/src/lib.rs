@@ -0,0 +1,275 @@
+//! WebAssembly exports of comm-opaque's client API, published as
+//! `@comm/opaque-wasm` (see package.json) for clients that can't load the
+//! native addon. Build with `yarn build` from this directory, and test with
+//! `wasm-pack test --node`.
+//!
+//! The exports mirror the keyserver addon's, under the same JS names and
+//! with the same arguments, and the ones that return a Promise there return
+//! one here. Only the client side is exported: the server side keeps
+//! lockout, replay and metrics state keyed on `Instant`, which wasm32
+//! doesn't have. There is no thread pool to hand work to, so those Promises
+//! are settled by the time they are returned.
+
+use comm_opaque::{
+  api::{ClientLoggedIn, ClientLoggingIn},
+  attestation, client, conformance, formats, Cipher, Error, OpaqueClient,
+};
+use js_sys::Promise;
+use opaque_ke::{ClientRegistration, RegistrationResponse};
+use wasm_bindgen::prelude::*;
+
+fn to_js_error(error: Error) -> JsError {
+  JsError::new(&error.to_string())
+}
+
+fn settled<T: Into<JsValue>>(result: Result<T, JsError>) -> Promise {
+  match result {
+    Ok(value) => Promise::resolve(&value.into()),
+    Err(e) => Promise::reject(&e.into()),
+  }
+}
+
+#[wasm_bindgen]
+extern "C" {
+  /// `{ rootPublicKey, attestation }`, as the native addon takes it
+  pub type ServerKeyAttestation;
+
+  #[wasm_bindgen(method, getter, js_name = rootPublicKey)]
+  fn root_public_key(this: &ServerKeyAttestation) -> Vec<u8>;
+
+  #[wasm_bindgen(method, getter)]
+  fn attestation(this: &ServerKeyAttestation) -> Vec<u8>;
+}
+
+#[wasm_bindgen]
+pub struct ClientRegistrationStartResult(
+  opaque_ke::ClientRegistrationStartResult<Cipher>,
+);
+
+#[wasm_bindgen]
+pub struct ClientRegistrationFinishResult(
+  opaque_ke::ClientRegistrationFinishResult<Cipher>,
+);
+
+#[wasm_bindgen(js_name = clientRegisterStart)]
+pub fn client_register_start(
+  password: &str,
+) -> Result<ClientRegistrationStartResult, JsError> {
+  client::register_start(password.as_bytes())
+    .map(ClientRegistrationStartResult)
+    .map_err(to_js_error)
+}
+
+#[wasm_bindgen(js_name = getRegistrationStartMessageArray)]
+pub fn get_registration_start_message_array(
+  result: &ClientRegistrationStartResult,
+) -> Vec<u8> {
+  result.0.message.serialize()
+}
+
+#[wasm_bindgen(js_name = getRegistrationStartStateArray)]
+pub fn get_registration_start_state_array(
+  result: &ClientRegistrationStartResult,
+) -> Vec<u8> {
+  result.0.state.serialize()
+}
+
+fn parse_finish_arguments(
+  state: &[u8],
+  registration_response: &[u8],
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> Result<(ClientRegistration<Cipher>, RegistrationResponse<Cipher>), JsError>
+{
+  let client_registration = ClientRegistration::<Cipher>::deserialize(state)
+    .map_err(|e| to_js_error(e.into()))?;
+  let registration_response =
+    RegistrationResponse::deserialize(registration_response)
+      .map_err(|e| to_js_error(e.into()))?;
+  if let Some(server_key_attestation) = server_key_attestation {
+    attestation::verify_registration_response(
+      &server_key_attestation.root_public_key(),
+      &registration_response,
+      &server_key_attestation.attestation(),
+    )
+    .map_err(to_js_error)?;
+  }
+  Ok((client_registration, registration_response))
+}
+
+/// `state` is the array returned by `getRegistrationStartStateArray`. With
+/// `serverKeyAttestation`, throws unless the response came from a server
+/// key its root key attested.
+#[wasm_bindgen(js_name = clientRegisterFinish)]
+pub fn client_register_finish(
+  state: &[u8],
+  registration_response: &[u8],
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> Result<ClientRegistrationFinishResult, JsError> {
+  let (client_registration, registration_response) = parse_finish_arguments(
+    state,
+    registration_response,
+    server_key_attestation,
+  )?;
+  client::register_finish(client_registration, registration_response)
+    .map(ClientRegistrationFinishResult)
+    .map_err(to_js_error)
+}
+
+/// `clientRegisterFinish`, resolving to the same result. Arguments are
+/// checked, and the attestation verified, before it returns.
+#[wasm_bindgen(js_name = clientRegisterFinishAsync)]
+pub fn client_register_finish_async(
+  state: &[u8],
+  registration_response: &[u8],
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> Result<Promise, JsError> {
+  let (client_registration, registration_response) = parse_finish_arguments(
+    state,
+    registration_response,
+    server_key_attestation,
+  )?;
+  Ok(settled(
+    client::register_finish(client_registration, registration_response)
+      .map(ClientRegistrationFinishResult)
+      .map_err(to_js_error),
+  ))
+}
+
+#[wasm_bindgen(js_name = getRegistrationFinishMessageArray)]
+pub fn get_registration_finish_message_array(
+  result: &ClientRegistrationFinishResult,
+) -> Vec<u8> {
+  result.0.message.serialize()
+}
+
+#[wasm_bindgen(js_name = getRegistrationFinishExportKeyArray)]
+pub fn get_registration_finish_export_key_array(
+  result: &ClientRegistrationFinishResult,
+) -> Vec<u8> {
+  result.0.export_key.to_vec()
+}
+
+#[wasm_bindgen]
+pub struct ClientLoginStartResult {
+  state: ClientLoggingIn,
+  credential_request: Vec<u8>,
+}
+
+#[wasm_bindgen(js_name = clientLoginStart)]
+pub fn client_login_start(
+  password: &str,
+) -> Result<ClientLoginStartResult, JsError> {
+  let (state, credential_request) =
+    OpaqueClient::login(password.as_bytes()).map_err(to_js_error)?;
+  Ok(ClientLoginStartResult {
+    state,
+    credential_request,
+  })
+}
+
+#[wasm_bindgen(js_name = getLoginStartMessageArray)]
+pub fn get_login_start_message_array(
+  result: &ClientLoginStartResult,
+) -> Vec<u8> {
+  result.credential_request.clone()
+}
+
+/// Holds the password, so has to be kept as carefully
+#[wasm_bindgen(js_name = getLoginStartStateArray)]
+pub fn get_login_start_state_array(
+  result: &ClientLoginStartResult,
+) -> Result<Vec<u8>, JsError> {
+  result.state.serialize().map_err(to_js_error)
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct ClientLoginFinishResult {
+  #[wasm_bindgen(js_name = credentialFinalization)]
+  pub credential_finalization: Vec<u8>,
+  #[wasm_bindgen(js_name = sessionKey)]
+  pub session_key: Vec<u8>,
+  #[wasm_bindgen(js_name = exportKey)]
+  pub export_key: Vec<u8>,
+  #[wasm_bindgen(js_name = serverPublicKey)]
+  pub server_public_key: Vec<u8>,
+  #[wasm_bindgen(js_name = transcriptHash)]
+  pub transcript_hash: Vec<u8>,
+}
+
+impl From<ClientLoggedIn> for ClientLoginFinishResult {
+  fn from(logged_in: ClientLoggedIn) -> Self {
+    Self {
+      credential_finalization: logged_in.credential_finalization,
+      session_key: logged_in.session_key,
+      export_key: logged_in.export_key,
+      server_public_key: logged_in.server_public_key,
+      transcript_hash: logged_in.transcript_hash,
+    }
+  }
+}
+
+/// `state` is the array returned by `getLoginStartStateArray`, and `ksf`
+/// the KSF parameter set the server sent with the credential response.
+/// Resolves to a `ClientLoginFinishResult`; rejects if the password is
+/// wrong or the server isn't the one the user registered with. With
+/// `serverKeyAttestation`, also rejects unless its root key attested the
+/// server's key.
+#[wasm_bindgen(js_name = clientLoginFinish)]
+pub fn client_login_finish(
+  state: &[u8],
+  credential_response: &[u8],
+  ksf: u8,
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> Result<Promise, JsError> {
+  let client_logging_in =
+    ClientLoggingIn::deserialize(state).map_err(to_js_error)?;
+  Ok(settled(login_finish(
+    client_logging_in,
+    credential_response,
+    ksf,
+    server_key_attestation,
+  )))
+}
+
+/// What `clientLoginFinish` settles with, for Rust callers
+pub fn login_finish(
+  client_logging_in: ClientLoggingIn,
+  credential_response: &[u8],
+  ksf: u8,
+  server_key_attestation: Option<ServerKeyAttestation>,
+) -> Result<ClientLoginFinishResult, JsError> {
+  let logged_in = client_logging_in
+    .finish(credential_response, ksf)
+    .map_err(to_js_error)?;
+  if let Some(server_key_attestation) = server_key_attestation {
+    attestation::verify_server_key(
+      &server_key_attestation.root_public_key(),
+      &logged_in.server_public_key,
+      &server_key_attestation.attestation(),
+    )
+    .map_err(to_js_error)?;
+  }
+  Ok(logged_in.into())
+}
+
+#[wasm_bindgen(js_name = getInteropVectors)]
+pub fn get_interop_vectors() -> Result<String, JsError> {
+  conformance::interop_vectors_json().map_err(to_js_error)
+}
+
+#[wasm_bindgen(js_name = getHandshakeSnapshot)]
+pub fn get_handshake_snapshot(
+  seed: &[u8],
+  password: &str,
+) -> Result<String, JsError> {
+  let seed = seed
+    .try_into()
+    .map_err(|_| JsError::new("seed must be 32 bytes"))?;
+  conformance::handshake_snapshot_json(seed, password.as_bytes())
+    .map_err(to_js_error)
+}
+
+#[wasm_bindgen(js_name = getFormatDescriptions)]
+pub fn get_format_descriptions() -> String {
+  formats::formats_json()
+}
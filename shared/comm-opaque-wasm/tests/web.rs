@@ -0,0 +1,81 @@
+//! Run in Node with `wasm-pack test --node`. The tests also run natively
+//! under `cargo test`, which covers everything but the JavaScript glue.
+
+use comm_opaque::{
+  api::ClientLoggingIn, ksf::KSF_DEFAULT, rng::CommRng, Cipher,
+};
+use opaque_ke::{
+  ciphersuite::CipherSuite, CredentialFinalization, CredentialRequest,
+  RegistrationRequest, RegistrationUpload, ServerLogin,
+  ServerLoginStartParameters, ServerRegistration,
+};
+use opaque_wasm::*;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::wasm_bindgen_test as test;
+
+#[test]
+fn test_register_then_login() {
+  let server_keypair = Cipher::generate_random_keypair(&mut CommRng);
+
+  let registration = client_register_start("hunter2").unwrap();
+  let registration_request = RegistrationRequest::deserialize(
+    &get_registration_start_message_array(&registration),
+  )
+  .unwrap();
+  let server_registration = ServerRegistration::<Cipher>::start(
+    &mut CommRng,
+    registration_request,
+    server_keypair.public(),
+  )
+  .unwrap();
+  let registered = client_register_finish(
+    &get_registration_start_state_array(&registration),
+    &server_registration.message.serialize(),
+    None,
+  )
+  .unwrap();
+  let registration_upload = RegistrationUpload::deserialize(
+    &get_registration_finish_message_array(&registered),
+  )
+  .unwrap();
+  let password_file = server_registration
+    .state
+    .finish(registration_upload)
+    .unwrap();
+
+  let login = client_login_start("hunter2").unwrap();
+  let credential_request =
+    CredentialRequest::deserialize(&get_login_start_message_array(&login))
+      .unwrap();
+  let server_login = ServerLogin::start(
+    &mut CommRng,
+    password_file,
+    server_keypair.private(),
+    credential_request,
+    ServerLoginStartParameters::default(),
+  )
+  .unwrap();
+  let logged_in = login_finish(
+    ClientLoggingIn::deserialize(&get_login_start_state_array(&login).unwrap())
+      .unwrap(),
+    &server_login.message.serialize().unwrap(),
+    KSF_DEFAULT,
+    None,
+  )
+  .unwrap();
+  let credential_finalization =
+    CredentialFinalization::deserialize(&logged_in.credential_finalization)
+      .unwrap();
+  let server_finish =
+    server_login.state.finish(credential_finalization).unwrap();
+
+  assert_eq!(logged_in.session_key, server_finish.session_key);
+  assert_eq!(
+    logged_in.export_key,
+    get_registration_finish_export_key_array(&registered)
+  );
+  assert_eq!(
+    logged_in.server_public_key,
+    server_keypair.public().to_vec()
+  );
+}
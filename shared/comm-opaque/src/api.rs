@@ -0,0 +1,698 @@
+//! Registration and login for Rust callers, with the protocol state carried
+//! in types so each step can only be taken once and in order. Messages go
+//! in and out serialized, ready to be put on the wire.
+//!
+//! These wrap the same functions the Node addon calls (`client`,
+//! `transition`, ...), so policy, FIPS mode, metrics and replay protection
+//! apply just the same. Wrong-password and unknown-user errors are returned
+//! as they are; lockout and security events are left to the caller, which
+//! knows the user's identifier.
+//!
+//! The login states serialize, so the two halves of a login can run in
+//! different processes: the client's as
+//! "cCLI" | format (u8) | bytes(credential request)
+//!   | bytes(opaque-ke `ClientLogin`),
+//! the server's as
+//! "cSLI" | format (u8) | ksf (u8) | needs re-registration (u8)
+//!   | transcript hash (64) | bytes(opaque-ke `ServerLogin`).
+//!
+//! The server's state holds the MAC it expects from the client and the
+//! session key, so anything that can read it can complete the login.
+//! `ServerLoggingIn::seal` encrypts it for storage outside the process
+//! (Redis, say), under a `LoginStateKey` only the server holds, as
+//! "cSLS" | format (u8) | nonce (24) | XChaCha20-Poly1305 ciphertext of the
+//! serialized state, with the header as associated data.
+//!
+//! Both sides of a finished login can name it by its transcript hash, a
+//! SHA-512 over its public messages only (see `transcript_hash`), to bind
+//! tokens or channels to it.
+
+use std::sync::Arc;
+
+use chacha20poly1305::{
+  aead::{Aead, KeyInit, Payload},
+  XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use opaque_ke::rand::RngCore;
+use sha2::{Digest, Sha512};
+use zeroize::Zeroizing;
+
+use opaque_ke::{
+  ClientLogin, ClientLoginFinishParameters, ClientLoginStartParameters,
+  ClientRegistration, CredentialFinalization, CredentialRequest,
+  CredentialResponse, RegistrationRequest, RegistrationResponse,
+  RegistrationUpload, ServerLogin, ServerLoginStartResult, ServerRegistration,
+};
+
+use crate::{
+  client,
+  fips::{self, Primitive},
+  key_backend::ServerKeyBackend,
+  keystore::NONCE_LEN,
+  ksf::{registration_ksf, with_ksf},
+  policy::version_policy,
+  record::{PasswordRecord, CURRENT_SUITE_VERSION},
+  rng::CommRng,
+  serialization::{Decoder, Encoder},
+  server_setup::ServerSetup,
+  transition::{
+    server_login_finish, server_login_start, LoginOutcome, LoginRequest,
+    StoredCredentials,
+  },
+  Cipher, Error,
+};
+
+pub(crate) const CLIENT_LOGIN_MAGIC: &[u8] = b"cCLI";
+pub(crate) const SERVER_LOGIN_MAGIC: &[u8] = b"cSLI";
+/// 2 added the credential request to the client's state and the transcript
+/// hash to the server's; format 1 states are no longer read
+pub(crate) const LOGIN_STATE_FORMAT: u8 = 2;
+pub(crate) const SEALED_LOGIN_MAGIC: &[u8] = b"cSLS";
+pub(crate) const SEALED_LOGIN_FORMAT: u8 = 1;
+pub const LOGIN_STATE_KEY_LEN: usize = 32;
+
+const LOGIN_STATE_KEY_LABEL: &[u8] = b"comm-opaque login state key";
+
+const TRANSCRIPT_LABEL: &[u8] = b"comm-opaque transcript";
+
+/// SHA-512("comm-opaque transcript" ‖ KE1 ‖ KE2), each length-prefixed:
+/// a hash of the login's public messages, the credential request and
+/// response, which both sides compute the same. It is not opaque-ke's 3DH
+/// transcript, which also covers the key exchange's internal values and
+/// never leaves opaque-ke. Only meaningful once the login finished.
+pub fn transcript_hash(
+  credential_request: &[u8],
+  credential_response: &[u8],
+) -> Vec<u8> {
+  Sha512::digest(
+    &Encoder::new()
+      .bytes(TRANSCRIPT_LABEL)
+      .bytes(credential_request)
+      .bytes(credential_response)
+      .finish(),
+  )
+  .to_vec()
+}
+
+/// Checks the magic and format of a serialized login state and returns a
+/// decoder for the rest
+fn login_state_decoder<'a>(
+  input: &'a [u8],
+  magic: &[u8],
+) -> Result<Decoder<'a>, Error> {
+  let mut decoder = Decoder::new(input);
+  if decoder.fixed(magic.len())? != magic || decoder.u8()? != LOGIN_STATE_FORMAT
+  {
+    return Err(Error::Serialization);
+  }
+  Ok(decoder)
+}
+
+/// The client side of both protocols
+pub struct OpaqueClient;
+
+impl OpaqueClient {
+  /// Returns the registration request to send to the server. Fails with
+  /// `Error::PasswordPolicy` if `password` doesn't meet the password policy.
+  pub fn register(
+    password: &[u8],
+  ) -> Result<(ClientRegistering, Vec<u8>), Error> {
+    let start_result = client::register_start(password)?;
+    Ok((
+      ClientRegistering {
+        state: start_result.state,
+      },
+      start_result.message.serialize(),
+    ))
+  }
+
+  /// Returns the credential request to send to the server
+  pub fn login(password: &[u8]) -> Result<(ClientLoggingIn, Vec<u8>), Error> {
+    fips::require_approved(Primitive::Ristretto255Suite)?;
+    let start_result = ClientLogin::<Cipher>::start(
+      &mut CommRng,
+      password,
+      ClientLoginStartParameters::default(),
+    )?;
+    let credential_request = start_result.message.serialize()?;
+    Ok((
+      ClientLoggingIn {
+        state: start_result.state,
+        credential_request: credential_request.clone(),
+      },
+      credential_request,
+    ))
+  }
+}
+
+/// Waiting for the server's registration response
+pub struct ClientRegistering {
+  state: ClientRegistration<Cipher>,
+}
+
+impl ClientRegistering {
+  /// Returns the registration upload to send to the server
+  pub fn finish(self, registration_response: &[u8]) -> Result<Vec<u8>, Error> {
+    let finish_result = client::register_finish(
+      self.state,
+      RegistrationResponse::deserialize(registration_response)?,
+    )?;
+    Ok(finish_result.message.serialize())
+  }
+}
+
+/// Waiting for the server's credential response
+pub struct ClientLoggingIn {
+  state: ClientLogin<Cipher>,
+  /// Kept for the transcript hash
+  credential_request: Vec<u8>,
+}
+
+/// A login the client finished: the finalization still has to reach the
+/// server before it considers the user authenticated
+pub struct ClientLoggedIn {
+  pub credential_finalization: Vec<u8>,
+  pub session_key: Vec<u8>,
+  /// Stable across logins with the same password, for encrypting data the
+  /// client stores
+  pub export_key: Vec<u8>,
+  /// The static public key of the server the envelope was sealed for, for
+  /// clients that pin it
+  pub server_public_key: Vec<u8>,
+  /// See `transcript_hash`
+  pub transcript_hash: Vec<u8>,
+}
+
+impl ClientLoggingIn {
+  /// The state holds the password, so it has to be kept as carefully
+  pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+    Ok(
+      Encoder::new()
+        .fixed(CLIENT_LOGIN_MAGIC)
+        .u8(LOGIN_STATE_FORMAT)
+        .bytes(&self.credential_request)
+        .bytes(&self.state.serialize()?)
+        .finish(),
+    )
+  }
+
+  pub fn deserialize(input: &[u8]) -> Result<Self, Error> {
+    let mut decoder = login_state_decoder(input, CLIENT_LOGIN_MAGIC)?;
+    let credential_request = decoder.bytes()?.to_vec();
+    let state = ClientLogin::deserialize(decoder.bytes()?)?;
+    decoder.finish()?;
+    Ok(Self {
+      state,
+      credential_request,
+    })
+  }
+
+  /// `ksf` is the KSF parameter set the server said the record was
+  /// registered with. Fails if the password is wrong, or if the server
+  /// isn't the one the user registered with.
+  pub fn finish(
+    self,
+    credential_response: &[u8],
+    ksf: u8,
+  ) -> Result<ClientLoggedIn, Error> {
+    fips::require_approved(Primitive::Ristretto255Suite)?;
+    let transcript_hash =
+      transcript_hash(&self.credential_request, credential_response);
+    let credential_response =
+      CredentialResponse::deserialize(credential_response)?;
+    let finish_result = with_ksf(ksf, || {
+      self
+        .state
+        .finish(credential_response, ClientLoginFinishParameters::default())
+    })??;
+    Ok(ClientLoggedIn {
+      credential_finalization: finish_result.message.serialize()?,
+      session_key: finish_result.session_key,
+      export_key: finish_result.export_key.to_vec(),
+      server_public_key: finish_result.server_s_pk.to_arr().to_vec(),
+      transcript_hash,
+    })
+  }
+}
+
+/// The server side of both protocols. Cloning is cheap; clones share the
+/// key.
+#[derive(Clone)]
+pub struct OpaqueServer {
+  server_key: Arc<dyn ServerKeyBackend>,
+}
+
+impl OpaqueServer {
+  pub fn new(server_key: impl ServerKeyBackend + 'static) -> Self {
+    Self {
+      server_key: Arc::new(server_key),
+    }
+  }
+
+  pub fn from_setup(server_setup: &ServerSetup) -> Self {
+    Self::new(server_setup.keypair().clone())
+  }
+
+  pub fn server_key(&self) -> &dyn ServerKeyBackend {
+    self.server_key.as_ref()
+  }
+
+  /// Returns the registration response to send to the client. Fails with
+  /// `Error::VersionBelowMinimum` if the version policy no longer accepts
+  /// the suite new records are written with.
+  pub fn register(
+    &self,
+    registration_request: &[u8],
+  ) -> Result<(ServerRegistering, Vec<u8>), Error> {
+    fips::require_approved(Primitive::Ristretto255Suite)?;
+    version_policy().check_suite_version(CURRENT_SUITE_VERSION)?;
+    let start_result = ServerRegistration::<Cipher>::start(
+      &mut CommRng,
+      RegistrationRequest::deserialize(registration_request)?,
+      self.server_key.public_key(),
+    )?;
+    Ok((
+      ServerRegistering {
+        state: start_result.state,
+      },
+      start_result.message.serialize(),
+    ))
+  }
+
+  /// Starts a login against a stored record, returning the credential
+  /// response to send to the client along with the record's KSF parameter
+  /// set, which the client needs to finish
+  pub fn login(
+    &self,
+    record: PasswordRecord,
+    credential_request: &[u8],
+  ) -> Result<(ServerLoggingIn, Vec<u8>), Error> {
+    let outcome = server_login_start(
+      StoredCredentials {
+        record: Some(record),
+        legacy_hash: None,
+      },
+      LoginRequest::Opaque(Box::new(CredentialRequest::deserialize(
+        credential_request,
+      )?)),
+      self.server_key.as_ref(),
+    )?;
+    match outcome {
+      LoginOutcome::OpaqueStarted {
+        result,
+        ksf,
+        needs_reregistration,
+      } => ServerLoggingIn::from_start(
+        *result,
+        ksf,
+        needs_reregistration,
+        credential_request,
+      ),
+      // transition::server_login_start only migrates users without a
+      // record, so this would be a bug there
+      LoginOutcome::LegacyMigrated(_) => Err(Error::UnexpectedLegacyLogin),
+    }
+  }
+}
+
+/// Waiting for the client's registration upload
+pub struct ServerRegistering {
+  state: ServerRegistration<Cipher>,
+}
+
+impl ServerRegistering {
+  /// opaque-ke's `ServerRegistration` as it is, the same state `upgrade`
+  /// hands out for re-registrations
+  pub fn serialize(&self) -> Vec<u8> {
+    self.state.serialize()
+  }
+
+  pub fn deserialize(input: &[u8]) -> Result<Self, Error> {
+    Ok(Self {
+      state: ServerRegistration::deserialize(input)?,
+    })
+  }
+
+  /// Returns the record to store for the user, stamped with the KSF
+  /// parameter set chosen by `ksf::init_ksf`
+  pub fn finish(
+    self,
+    registration_upload: &[u8],
+  ) -> Result<PasswordRecord, Error> {
+    fips::require_approved(Primitive::Ristretto255Suite)?;
+    let password_file = self
+      .state
+      .finish(RegistrationUpload::deserialize(registration_upload)?)?;
+    Ok(PasswordRecord::with_ksf(password_file, registration_ksf()))
+  }
+}
+
+/// The key `ServerLoggingIn::seal` encrypts states under. Every process
+/// finishing logins another one started needs the same key.
+pub struct LoginStateKey(Zeroizing<[u8; LOGIN_STATE_KEY_LEN]>);
+
+impl LoginStateKey {
+  pub fn new(key: [u8; LOGIN_STATE_KEY_LEN]) -> Self {
+    Self(Zeroizing::new(key))
+  }
+
+  /// Derives the key from the server's private key, so processes sharing a
+  /// server setup share it too. Fails with `Error::KeyBackend` for backends
+  /// that don't expose the private key; those need a key of their own
+  /// (`new`).
+  pub fn derive(server_key: &dyn ServerKeyBackend) -> Result<Self, Error> {
+    let private_key = server_key.private_key().ok_or(Error::KeyBackend)?;
+    let mut key = Zeroizing::new([0; LOGIN_STATE_KEY_LEN]);
+    Hkdf::<Sha512>::new(None, &private_key.to_arr())
+      .expand(LOGIN_STATE_KEY_LABEL, key.as_mut())
+      .map_err(|_| Error::KeyBackend)?;
+    Ok(Self(key))
+  }
+
+  fn cipher(&self) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(self.0.as_ref().into())
+  }
+}
+
+/// Waiting for the client's credential finalization
+pub struct ServerLoggingIn {
+  state: ServerLogin<Cipher>,
+  ksf: u8,
+  needs_reregistration: bool,
+  transcript_hash: Vec<u8>,
+}
+
+impl ServerLoggingIn {
+  /// Wraps a login `transition::server_login_start` started from
+  /// `credential_request`, returning it with the credential response to
+  /// send to the client
+  pub fn from_start(
+    result: ServerLoginStartResult<Cipher>,
+    ksf: u8,
+    needs_reregistration: bool,
+    credential_request: &[u8],
+  ) -> Result<(Self, Vec<u8>), Error> {
+    let credential_response = result.message.serialize()?;
+    Ok((
+      Self {
+        state: result.state,
+        ksf,
+        needs_reregistration,
+        transcript_hash: transcript_hash(
+          credential_request,
+          &credential_response,
+        ),
+      },
+      credential_response,
+    ))
+  }
+
+  /// The state in the clear. It holds the expected client MAC and the
+  /// session key, so it is as secret as the session itself; use `seal` to
+  /// keep it anywhere but in the process's memory.
+  pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+    Ok(
+      Encoder::new()
+        .fixed(SERVER_LOGIN_MAGIC)
+        .u8(LOGIN_STATE_FORMAT)
+        .u8(self.ksf)
+        .u8(self.needs_reregistration.into())
+        .fixed(&self.transcript_hash)
+        .bytes(&self.state.serialize()?)
+        .finish(),
+    )
+  }
+
+  pub fn deserialize(input: &[u8]) -> Result<Self, Error> {
+    let mut decoder = login_state_decoder(input, SERVER_LOGIN_MAGIC)?;
+    let ksf = decoder.u8()?;
+    let needs_reregistration = match decoder.u8()? {
+      0 => false,
+      1 => true,
+      _ => return Err(Error::Serialization),
+    };
+    let transcript_hash = decoder.fixed(Sha512::output_size())?.to_vec();
+    let state = ServerLogin::deserialize(decoder.bytes()?)?;
+    decoder.finish()?;
+    Ok(Self {
+      state,
+      ksf,
+      needs_reregistration,
+      transcript_hash,
+    })
+  }
+
+  /// The serialized state encrypted under `key`, with a fresh nonce
+  pub fn seal(&self, key: &LoginStateKey) -> Result<Vec<u8>, Error> {
+    let mut nonce = [0; NONCE_LEN];
+    CommRng.fill_bytes(&mut nonce);
+    let header = Encoder::new()
+      .fixed(SEALED_LOGIN_MAGIC)
+      .u8(SEALED_LOGIN_FORMAT)
+      .fixed(&nonce)
+      .finish();
+    let plaintext = Zeroizing::new(self.serialize()?);
+    let ciphertext = key
+      .cipher()
+      .encrypt(
+        XNonce::from_slice(&nonce),
+        Payload {
+          msg: &plaintext,
+          aad: &header,
+        },
+      )
+      .map_err(|_| Error::Serialization)?;
+    Ok([header, ciphertext].concat())
+  }
+
+  /// Fails with `Error::Serialization` if `sealed` wasn't sealed under
+  /// `key`, or was modified
+  pub fn unseal(sealed: &[u8], key: &LoginStateKey) -> Result<Self, Error> {
+    let header_len = SEALED_LOGIN_MAGIC.len() + 1 + NONCE_LEN;
+    if sealed.len() < header_len {
+      return Err(Error::Serialization);
+    }
+    let (header, ciphertext) = sealed.split_at(header_len);
+    let mut decoder = Decoder::new(header);
+    if decoder.fixed(SEALED_LOGIN_MAGIC.len())? != SEALED_LOGIN_MAGIC
+      || decoder.u8()? != SEALED_LOGIN_FORMAT
+    {
+      return Err(Error::Serialization);
+    }
+    let nonce = decoder.fixed(NONCE_LEN)?;
+    let plaintext = Zeroizing::new(
+      key
+        .cipher()
+        .decrypt(
+          XNonce::from_slice(nonce),
+          Payload {
+            msg: ciphertext,
+            aad: header,
+          },
+        )
+        .map_err(|_| Error::Serialization)?,
+    );
+    Self::deserialize(&plaintext)
+  }
+
+  /// The KSF parameter set to send to the client with the credential
+  /// response
+  pub fn ksf(&self) -> u8 {
+    self.ksf
+  }
+
+  /// Whether the user should be asked to register again once the login
+  /// completes (see `upgrade`)
+  pub fn needs_reregistration(&self) -> bool {
+    self.needs_reregistration
+  }
+
+  /// See `transcript_hash`. Only identifies the login once `finish`
+  /// succeeds.
+  pub fn transcript_hash(&self) -> &[u8] {
+    &self.transcript_hash
+  }
+
+  /// Returns the session key. Fails if the client didn't prove knowledge of
+  /// the password.
+  pub fn finish(
+    self,
+    credential_finalization: &[u8],
+  ) -> Result<Vec<u8>, Error> {
+    server_login_finish(
+      self.state,
+      CredentialFinalization::deserialize(credential_finalization)?,
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::key_backend::ExternalKey;
+  use opaque_ke::keypair::Key;
+
+  const PASSWORD: &[u8] = b"hunter2";
+
+  #[test]
+  fn test_register_and_log_in() {
+    let server_setup = ServerSetup::generate();
+    let server = OpaqueServer::from_setup(&server_setup);
+
+    let (client_registering, registration_request) =
+      OpaqueClient::register(PASSWORD).unwrap();
+    let (server_registering, registration_response) =
+      server.register(&registration_request).unwrap();
+    let registration_upload =
+      client_registering.finish(&registration_response).unwrap();
+    let record = server_registering.finish(&registration_upload).unwrap();
+    let stored = record.serialize();
+
+    // The same record logs in through an external key backend
+    let keypair = server_setup.keypair().clone();
+    let external = OpaqueServer::new(
+      ExternalKey::new(
+        &server_setup.keypair().public().to_arr(),
+        Box::new(move |peer_public_key| {
+          let peer_public_key =
+            Key::from_bytes(peer_public_key).map_err(|_| Error::KeyBackend)?;
+          Ok(keypair.diffie_hellman(&peer_public_key)?.to_vec())
+        }),
+      )
+      .unwrap(),
+    );
+    for server in [server, external] {
+      let (client_logging_in, credential_request) =
+        OpaqueClient::login(PASSWORD).unwrap();
+      let (server_logging_in, credential_response) = server
+        .login(
+          PasswordRecord::deserialize(&stored).unwrap(),
+          &credential_request,
+        )
+        .unwrap();
+      assert!(!server_logging_in.needs_reregistration());
+      let logged_in = client_logging_in
+        .finish(&credential_response, server_logging_in.ksf())
+        .unwrap();
+      let session_key = server_logging_in
+        .finish(&logged_in.credential_finalization)
+        .unwrap();
+      assert_eq!(session_key, logged_in.session_key);
+      assert_eq!(
+        logged_in.server_public_key,
+        server_setup.keypair().public().to_arr().to_vec()
+      );
+    }
+  }
+
+  #[test]
+  fn test_login_states_round_trip() {
+    let server = OpaqueServer::from_setup(&ServerSetup::generate());
+    let (client_registering, registration_request) =
+      OpaqueClient::register(PASSWORD).unwrap();
+    let (server_registering, registration_response) =
+      server.register(&registration_request).unwrap();
+    let server_registering =
+      ServerRegistering::deserialize(&server_registering.serialize()).unwrap();
+    let record = server_registering
+      .finish(&client_registering.finish(&registration_response).unwrap())
+      .unwrap();
+
+    let (client_logging_in, credential_request) =
+      OpaqueClient::login(PASSWORD).unwrap();
+    let client_state = client_logging_in.serialize().unwrap();
+    let (server_logging_in, credential_response) =
+      server.login(record, &credential_request).unwrap();
+    let server_state = server_logging_in.serialize().unwrap();
+    drop((client_logging_in, server_logging_in));
+
+    let server_logging_in =
+      ServerLoggingIn::deserialize(&server_state).unwrap();
+    assert_eq!(server_logging_in.serialize().unwrap(), server_state);
+    let server_transcript_hash = server_logging_in.transcript_hash().to_vec();
+    let logged_in = ClientLoggingIn::deserialize(&client_state)
+      .unwrap()
+      .finish(&credential_response, server_logging_in.ksf())
+      .unwrap();
+    let session_key = server_logging_in
+      .finish(&logged_in.credential_finalization)
+      .unwrap();
+    assert_eq!(session_key, logged_in.session_key);
+    assert_eq!(logged_in.transcript_hash, server_transcript_hash);
+    assert_eq!(
+      server_transcript_hash,
+      transcript_hash(&credential_request, &credential_response)
+    );
+
+    assert!(ServerLoggingIn::deserialize(&client_state).is_err());
+    assert!(ClientLoggingIn::deserialize(&server_state).is_err());
+    let truncated = &server_state[..server_state.len() - 1];
+    assert!(ServerLoggingIn::deserialize(truncated).is_err());
+    let mut format_1 = server_state.clone();
+    format_1[SERVER_LOGIN_MAGIC.len()] = 1;
+    assert!(ServerLoggingIn::deserialize(&format_1).is_err());
+  }
+
+  #[test]
+  fn test_sealed_server_states() {
+    let server_setup = ServerSetup::generate();
+    let server = OpaqueServer::from_setup(&server_setup);
+    let (client_registering, registration_request) =
+      OpaqueClient::register(PASSWORD).unwrap();
+    let (server_registering, registration_response) =
+      server.register(&registration_request).unwrap();
+    let record = server_registering
+      .finish(&client_registering.finish(&registration_response).unwrap())
+      .unwrap();
+
+    let (client_logging_in, credential_request) =
+      OpaqueClient::login(PASSWORD).unwrap();
+    let (server_logging_in, credential_response) =
+      server.login(record, &credential_request).unwrap();
+    let key = LoginStateKey::derive(server.server_key()).unwrap();
+    let sealed = server_logging_in.seal(&key).unwrap();
+    let plain = server_logging_in.serialize().unwrap();
+    assert!(!sealed
+      .windows(plain.len() - 5)
+      .any(|window| window == &plain[5..]));
+    assert_ne!(server_logging_in.seal(&key).unwrap(), sealed);
+
+    let other =
+      LoginStateKey::derive(ServerSetup::generate().keypair()).unwrap();
+    assert!(ServerLoggingIn::unseal(&sealed, &other).is_err());
+    let mut modified = sealed.clone();
+    modified[5] ^= 1;
+    assert!(ServerLoggingIn::unseal(&modified, &key).is_err());
+    assert!(ServerLoggingIn::unseal(&plain, &key).is_err());
+
+    let logged_in = client_logging_in
+      .finish(&credential_response, server_logging_in.ksf())
+      .unwrap();
+    let session_key = ServerLoggingIn::unseal(&sealed, &key)
+      .unwrap()
+      .finish(&logged_in.credential_finalization)
+      .unwrap();
+    assert_eq!(session_key, logged_in.session_key);
+  }
+
+  #[test]
+  fn test_wrong_password_fails_on_the_client() {
+    let server = OpaqueServer::from_setup(&ServerSetup::generate());
+    let (client_registering, registration_request) =
+      OpaqueClient::register(PASSWORD).unwrap();
+    let (server_registering, registration_response) =
+      server.register(&registration_request).unwrap();
+    let record = server_registering
+      .finish(&client_registering.finish(&registration_response).unwrap())
+      .unwrap();
+
+    let (client_logging_in, credential_request) =
+      OpaqueClient::login(b"hunter3").unwrap();
+    let (server_logging_in, credential_response) =
+      server.login(record, &credential_request).unwrap();
+    assert!(client_logging_in
+      .finish(&credential_response, server_logging_in.ksf())
+      .is_err());
+  }
+}
@@ -0,0 +1,164 @@
+//! Attestations of the server's OPAQUE public key, so a client can tell that
+//! the key it's registering against belongs to the deployment and not to
+//! whoever answered the request.
+//!
+//! A deployment keeps an Ed25519 root key offline and ships its public half
+//! with the client. The server publishes an attestation, the root key's
+//! signature over its OPAQUE public key, next to its registration
+//! responses; the client checks it with `verify_registration_response`
+//! before finishing the registration. Rotating the server key only takes a
+//! new attestation, signed with the same root.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use opaque_ke::{rand::RngCore, RegistrationResponse};
+
+use crate::{rng::CommRng, serialization::Encoder, Cipher, Error};
+
+pub const ROOT_KEY_LEN: usize = 32;
+pub const ATTESTATION_LEN: usize = 64;
+
+/// Ristretto255 points are 32 bytes
+const SERVER_KEY_LEN: usize = 32;
+const ATTESTATION_LABEL: &[u8] = b"comm-opaque server key attestation";
+
+/// A new root private key (an Ed25519 seed)
+pub fn generate_root_key() -> [u8; ROOT_KEY_LEN] {
+  let mut root_private_key = [0; ROOT_KEY_LEN];
+  CommRng.fill_bytes(&mut root_private_key);
+  root_private_key
+}
+
+fn signing_key(root_private_key: &[u8]) -> Result<SigningKey, Error> {
+  let seed = root_private_key
+    .try_into()
+    .map_err(|_| Error::InvalidAttestationKey)?;
+  Ok(SigningKey::from_bytes(seed))
+}
+
+pub fn root_public_key(
+  root_private_key: &[u8],
+) -> Result<[u8; ROOT_KEY_LEN], Error> {
+  Ok(signing_key(root_private_key)?.verifying_key().to_bytes())
+}
+
+/// The attested message is labelled, so an attestation can't be mistaken
+/// for anything else the root key signs
+fn attested_message(server_public_key: &[u8]) -> Vec<u8> {
+  Encoder::new()
+    .bytes(ATTESTATION_LABEL)
+    .bytes(server_public_key)
+    .finish()
+}
+
+pub fn attest_server_key(
+  root_private_key: &[u8],
+  server_public_key: &[u8],
+) -> Result<[u8; ATTESTATION_LEN], Error> {
+  let signature =
+    signing_key(root_private_key)?.sign(&attested_message(server_public_key));
+  Ok(signature.to_bytes())
+}
+
+/// Fails with `Error::InvalidAttestation` unless `attestation` is the root
+/// key's signature over `server_public_key`
+pub fn verify_server_key(
+  root_public_key: &[u8],
+  server_public_key: &[u8],
+  attestation: &[u8],
+) -> Result<(), Error> {
+  let root_public_key = root_public_key
+    .try_into()
+    .ok()
+    .and_then(|bytes| VerifyingKey::from_bytes(bytes).ok())
+    .ok_or(Error::InvalidAttestationKey)?;
+  let signature = Signature::from_slice(attestation)
+    .map_err(|_| Error::InvalidAttestation)?;
+  root_public_key
+    .verify_strict(&attested_message(server_public_key), &signature)
+    .map_err(|_| Error::InvalidAttestation)
+}
+
+/// The server public key a registration response carries, which the client
+/// will seal into its envelope
+pub fn registration_response_server_key(
+  registration_response: &RegistrationResponse<Cipher>,
+) -> Vec<u8> {
+  let serialized = registration_response.serialize();
+  serialized[serialized.len() - SERVER_KEY_LEN..].to_vec()
+}
+
+/// Checks that `registration_response` came from a server key the root key
+/// attested
+pub fn verify_registration_response(
+  root_public_key: &[u8],
+  registration_response: &RegistrationResponse<Cipher>,
+  attestation: &[u8],
+) -> Result<(), Error> {
+  verify_server_key(
+    root_public_key,
+    &registration_response_server_key(registration_response),
+    attestation,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use opaque_ke::{
+    ciphersuite::CipherSuite, rand::rngs::OsRng, ClientRegistration,
+    ServerRegistration,
+  };
+
+  #[test]
+  fn test_attested_registration_response() {
+    let root_private_key = generate_root_key();
+    let root = root_public_key(&root_private_key).unwrap();
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let server_public_key = server_keypair.public().to_arr().to_vec();
+    let attestation =
+      attest_server_key(&root_private_key, &server_public_key).unwrap();
+
+    let client_start_result =
+      ClientRegistration::<Cipher>::start(&mut OsRng, b"hunter2").unwrap();
+    let registration_response = ServerRegistration::<Cipher>::start(
+      &mut OsRng,
+      client_start_result.message,
+      server_keypair.public(),
+    )
+    .unwrap()
+    .message;
+    assert!(verify_registration_response(
+      &root,
+      &registration_response,
+      &attestation
+    )
+    .is_ok());
+
+    let other_root = root_public_key(&generate_root_key()).unwrap();
+    let other_server_public_key = Cipher::generate_random_keypair(&mut OsRng)
+      .public()
+      .to_arr()
+      .to_vec();
+    for (root, server_public_key) in [
+      (root, &other_server_public_key),
+      (other_root, &server_public_key),
+    ] {
+      assert!(matches!(
+        verify_server_key(&root, server_public_key, &attestation),
+        Err(Error::InvalidAttestation)
+      ));
+    }
+  }
+
+  #[test]
+  fn test_malformed_keys_rejected() {
+    assert!(matches!(
+      attest_server_key(&[0; 31], &[0; 32]),
+      Err(Error::InvalidAttestationKey)
+    ));
+    assert!(matches!(
+      verify_server_key(&[0; 31], &[0; 32], &[0; ATTESTATION_LEN]),
+      Err(Error::InvalidAttestationKey)
+    ));
+  }
+}
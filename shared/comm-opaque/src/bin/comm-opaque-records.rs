@@ -0,0 +1,106 @@
+//! Operational tool for migrating exported password records.
+//!
+//! Records are read one per line, hex-encoded (e.g. the output of
+//! `SELECT HEX(...)`). `scan` reports which container formats and
+//! suite/KSF versions are present; `upgrade` rewrites every record in the
+//! current container format without touching the password files themselves.
+
+use comm_opaque::record::{upgrade_record_format, PasswordRecord};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage:
+  comm-opaque-records scan <records file>
+  comm-opaque-records upgrade <records file> <output file>";
+
+fn scan(input: &str) -> io::Result<bool> {
+  let mut versions = BTreeMap::new();
+  let mut invalid_lines = Vec::new();
+  for (index, line) in input.lines().enumerate() {
+    let record = hex::decode(line.trim())
+      .ok()
+      .and_then(|bytes| PasswordRecord::deserialize(&bytes).ok());
+    match record {
+      Some(record) => {
+        *versions
+          .entry((
+            record.format,
+            record.suite_version.suite,
+            record.suite_version.ksf,
+          ))
+          .or_insert(0usize) += 1;
+      }
+      None => invalid_lines.push(index + 1),
+    }
+  }
+  println!("format\tsuite\tksf\tcount");
+  for ((format, suite, ksf), count) in versions {
+    println!("{}\t{}\t{}\t{}", format, suite, ksf, count);
+  }
+  report_invalid_lines(&invalid_lines);
+  Ok(invalid_lines.is_empty())
+}
+
+fn upgrade(input: &str, output_path: &str) -> io::Result<bool> {
+  let mut output = BufWriter::new(File::create(output_path)?);
+  let mut upgraded = 0usize;
+  let mut invalid_lines = Vec::new();
+  for (index, line) in input.lines().enumerate() {
+    let line = line.trim();
+    match hex::decode(line)
+      .ok()
+      .and_then(|bytes| upgrade_record_format(&bytes).ok())
+    {
+      Some(record) => {
+        writeln!(output, "{}", hex::encode_upper(record))?;
+        upgraded += 1;
+      }
+      None => {
+        // Keep the line so the output stays aligned with the input
+        writeln!(output, "{}", line)?;
+        invalid_lines.push(index + 1);
+      }
+    }
+  }
+  output.flush()?;
+  println!("Upgraded {} records into {}", upgraded, output_path);
+  report_invalid_lines(&invalid_lines);
+  Ok(invalid_lines.is_empty())
+}
+
+fn report_invalid_lines(invalid_lines: &[usize]) {
+  if !invalid_lines.is_empty() {
+    eprintln!(
+      "{} lines could not be parsed as records: {:?}",
+      invalid_lines.len(),
+      invalid_lines
+    );
+  }
+}
+
+fn main() -> ExitCode {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let result = match args.as_slice() {
+    [command, input_path] if command == "scan" => {
+      fs::read_to_string(input_path).and_then(|input| scan(&input))
+    }
+    [command, input_path, output_path] if command == "upgrade" => {
+      fs::read_to_string(input_path)
+        .and_then(|input| upgrade(&input, output_path))
+    }
+    _ => {
+      eprintln!("{}", USAGE);
+      return ExitCode::from(2);
+    }
+  };
+  match result {
+    Ok(true) => ExitCode::SUCCESS,
+    Ok(false) => ExitCode::FAILURE,
+    Err(e) => {
+      eprintln!("{}", e);
+      ExitCode::FAILURE
+    }
+  }
+}
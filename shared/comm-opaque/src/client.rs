@@ -0,0 +1,69 @@
+use opaque_ke::{
+  ClientRegistration, ClientRegistrationFinishParameters,
+  ClientRegistrationFinishResult, ClientRegistrationStartResult,
+  RegistrationResponse,
+};
+
+use crate::{
+  fips::{self, Primitive},
+  ksf::{registration_ksf, with_ksf},
+  policy,
+  rng::CommRng,
+  Cipher, Error,
+};
+
+/// Fails with `Error::PasswordPolicy` if `password` doesn't meet the
+/// current password policy
+pub fn register_start(
+  password: &[u8],
+) -> Result<ClientRegistrationStartResult<Cipher>, Error> {
+  fips::require_approved(Primitive::Ristretto255Suite)?;
+  policy::password_policy().check(password)?;
+  Ok(ClientRegistration::<Cipher>::start(&mut CommRng, password)?)
+}
+
+/// Runs the KSF with the parameter set chosen by `ksf::init_ksf`, which the
+/// server records the password file under
+pub fn register_finish(
+  client_registration: ClientRegistration<Cipher>,
+  registration_response: RegistrationResponse<Cipher>,
+) -> Result<ClientRegistrationFinishResult<Cipher>, Error> {
+  fips::require_approved(Primitive::Ristretto255Suite)?;
+  Ok(with_ksf(registration_ksf(), || {
+    client_registration.finish(
+      &mut CommRng,
+      registration_response,
+      ClientRegistrationFinishParameters::default(),
+    )
+  })??)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use opaque_ke::{
+    ciphersuite::CipherSuite, rand::rngs::OsRng, ServerRegistration,
+  };
+
+  #[test]
+  fn test_registration_with_serialized_state() {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let client_start_result = register_start(b"hunter2").unwrap();
+    let client_state = client_start_result.state.serialize();
+    let server_start_result = ServerRegistration::<Cipher>::start(
+      &mut OsRng,
+      client_start_result.message,
+      server_keypair.public(),
+    )
+    .unwrap();
+    let client_finish_result = register_finish(
+      ClientRegistration::deserialize(&client_state).unwrap(),
+      server_start_result.message,
+    )
+    .unwrap();
+    assert!(server_start_result
+      .state
+      .finish(client_finish_result.message)
+      .is_ok());
+  }
+}
@@ -0,0 +1,186 @@
+//! An opt-in container for storing serialized records and states
+//! compressed:
+//!
+//! "cCMP" | format (u8) | method (u8) | length (u32) | checksum (32)
+//!   | bytes(payload)
+//!
+//! `length` and `checksum` (SHA-256) are those of the uncompressed data,
+//! which is checked against both when the container is opened, and
+//! `decompress` refuses to produce more than its caller's limit, so a
+//! corrupted or hostile container can't exhaust memory. Method 0 stores the
+//! data as it is, for inputs that don't shrink; method 1 is a standard LZ4
+//! block (not frame), so any LZ4 implementation can read and write it; this
+//! crate uses lz4_flex's. Format 1 used a different method 1 and is no
+//! longer read.
+//!
+//! Keys, envelopes and MACs don't compress; what does is what repeats
+//! between them, such as framing and the fields batch exports share.
+
+use std::borrow::Cow;
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+  serialization::{Decoder, Encoder},
+  Error,
+};
+
+pub(crate) const COMPRESSED_MAGIC: &[u8] = b"cCMP";
+pub(crate) const COMPRESSED_FORMAT: u8 = 2;
+/// What `decompress_if_compressed` accepts; well above any record or state
+/// this crate writes
+pub const DEFAULT_MAX_DECOMPRESSED_LEN: usize = 1 << 20;
+
+const STORED: u8 = 0;
+const LZ4: u8 = 1;
+
+/// Fails with `Error::DecompressedTooLarge` if `data` is too long for the
+/// container's u32 length, 4 GiB or more
+pub fn compress(data: &[u8]) -> Result<Vec<u8>, Error> {
+  let length = u32::try_from(data.len())
+    .map_err(|_| Error::DecompressedTooLarge(u32::MAX as usize))?;
+  let compressed = lz4_flex::block::compress(data);
+  let (method, payload) = if compressed.len() < data.len() {
+    (LZ4, compressed.as_slice())
+  } else {
+    (STORED, data)
+  };
+  Ok(
+    Encoder::new()
+      .fixed(COMPRESSED_MAGIC)
+      .u8(COMPRESSED_FORMAT)
+      .u8(method)
+      .u32(length)
+      .fixed(&Sha256::digest(data))
+      .bytes(payload)
+      .finish(),
+  )
+}
+
+pub fn is_compressed(data: &[u8]) -> bool {
+  data.starts_with(COMPRESSED_MAGIC)
+}
+
+/// Fails with `Error::DecompressedTooLarge` if the data is longer than
+/// `max_len`, and with `Error::InvalidCompressed` if the container is
+/// malformed or its contents don't match their checksum
+pub fn decompress(container: &[u8], max_len: usize) -> Result<Vec<u8>, Error> {
+  let mut decoder = Decoder::new(container);
+  let invalid = |_| Error::InvalidCompressed;
+  let magic = decoder.fixed(COMPRESSED_MAGIC.len()).map_err(invalid)?;
+  let format = decoder.u8().map_err(invalid)?;
+  if magic != COMPRESSED_MAGIC || format != COMPRESSED_FORMAT {
+    return Err(Error::InvalidCompressed);
+  }
+  let method = decoder.u8().map_err(invalid)?;
+  let length = decoder.u32().map_err(invalid)? as usize;
+  if length > max_len {
+    return Err(Error::DecompressedTooLarge(max_len));
+  }
+  let checksum = decoder.fixed(Sha256::output_size()).map_err(invalid)?;
+  let payload = decoder.bytes().map_err(invalid)?;
+  decoder.finish().map_err(invalid)?;
+  let data = match method {
+    STORED => payload.to_vec(),
+    LZ4 => lz4_decompress(payload, length)?,
+    _ => return Err(Error::InvalidCompressed),
+  };
+  if data.len() != length || Sha256::digest(&data).as_slice() != checksum {
+    return Err(Error::InvalidCompressed);
+  }
+  Ok(data)
+}
+
+/// For stores holding both compressed and plain values: decompresses
+/// `data` if it is a container, up to `DEFAULT_MAX_DECOMPRESSED_LEN`, and
+/// returns anything else unchanged
+pub fn decompress_if_compressed(data: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+  if is_compressed(data) {
+    decompress(data, DEFAULT_MAX_DECOMPRESSED_LEN).map(Cow::Owned)
+  } else {
+    Ok(Cow::Borrowed(data))
+  }
+}
+
+/// lz4_flex's safe decoder checks every offset and length against the
+/// input and the output buffer, so it can't write more than `length` bytes,
+/// whatever the payload says
+fn lz4_decompress(payload: &[u8], length: usize) -> Result<Vec<u8>, Error> {
+  let mut output = vec![0; length];
+  let written = lz4_flex::block::decompress_into(payload, &mut output)
+    .map_err(|_| Error::InvalidCompressed)?;
+  output.truncate(written);
+  Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::rng::CommRng;
+  use opaque_ke::rand::RngCore;
+
+  #[test]
+  fn test_round_trip() {
+    let mut random = vec![0; 300];
+    CommRng.fill_bytes(&mut random);
+    let repetitive: Vec<u8> =
+      [b"record".as_slice(), &random[..40]].concat().repeat(20);
+    for data in [&b""[..], b"abc", &random, &repetitive] {
+      let container = compress(data).unwrap();
+      assert!(is_compressed(&container));
+      assert_eq!(decompress(&container, data.len()).unwrap(), data);
+    }
+    assert!(compress(&repetitive).unwrap().len() < repetitive.len() / 4);
+    assert_eq!(compress(&random).unwrap().len(), random.len() + 46);
+    assert_eq!(decompress_if_compressed(&random).unwrap(), &random[..]);
+  }
+
+  #[test]
+  fn test_rejects_oversized_and_corrupted() {
+    let data = b"abcdabcdabcdabcdabcd".repeat(10);
+    let container = compress(&data).unwrap();
+    assert!(matches!(
+      decompress(&container, data.len() - 1),
+      Err(Error::DecompressedTooLarge(_))
+    ));
+    for index in [5, 10, container.len() - 1] {
+      let mut corrupted = container.clone();
+      corrupted[index] ^= 1;
+      assert!(decompress(&corrupted, data.len()).is_err(), "{}", index);
+    }
+    assert!(decompress(&container[..container.len() - 1], usize::MAX).is_err());
+    // A match reaching back before the start of the output
+    let bomb = Encoder::new()
+      .fixed(COMPRESSED_MAGIC)
+      .u8(COMPRESSED_FORMAT)
+      .u8(LZ4)
+      .u32(100)
+      .fixed(&[0; 32])
+      .bytes(&[0, 1, 0])
+      .finish();
+    assert!(matches!(
+      decompress(&bomb, 100),
+      Err(Error::InvalidCompressed)
+    ));
+  }
+
+  #[test]
+  fn test_reads_and_writes_lz4() {
+    let data = [
+      b"password record, password record, password record: ".as_slice(),
+      &(0..16).collect::<Vec<u8>>().repeat(3),
+      b"end",
+    ]
+    .concat();
+    // From the reference implementation's LZ4_compress_default
+    let reference = hex::decode(
+      "ff0270617373776f7264207265636f72642c2011000dff033a200001020304050607\
+       08090a0b0c0d0e0f10000b500e0f656e64",
+    )
+    .unwrap();
+    assert_eq!(lz4_decompress(&reference, data.len()).unwrap(), data);
+    let compressed = lz4_flex::block::compress(&data);
+    assert_eq!(lz4_decompress(&compressed, data.len()).unwrap(), data);
+    assert!(lz4_decompress(&reference, data.len() - 1).is_err());
+  }
+}
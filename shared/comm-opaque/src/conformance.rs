@@ -0,0 +1,398 @@
+//! Known-answer fixtures for the full OPAQUE exchange with `Cipher`.
+//!
+//! Every crate that speaks OPAQUE with `Cipher` (the keyserver addon, the
+//! native client library, the identity service) resolves its own copies of
+//! opaque-ke, argon2 and curve25519-dalek. Running `verify_all` from each of
+//! them catches any dependency drift that would make their messages or
+//! derived keys disagree.
+
+use std::collections::BTreeMap;
+
+use opaque_ke::{
+  ciphersuite::CipherSuite, ClientLogin, ClientLoginFinishParameters,
+  ClientLoginStartParameters, ClientRegistration,
+  ClientRegistrationFinishParameters, ServerLogin, ServerLoginStartParameters,
+  ServerRegistration,
+};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{record::PasswordRecord, Cipher, Error};
+
+pub struct Fixture {
+  pub name: &'static str,
+  pub seed: [u8; 32],
+  pub password: &'static [u8],
+  /// Hex-encoded SHA-256 of each transcript entry, in `Transcript` order
+  pub expected: [&'static str; TRANSCRIPT_LEN],
+}
+
+pub const TRANSCRIPT_LEN: usize = 9;
+
+/// Names of the transcript entries, in order
+pub const ENTRY_NAMES: [&str; TRANSCRIPT_LEN] = [
+  "registration_request",
+  "registration_response",
+  "registration_upload",
+  "registration_export_key",
+  "record",
+  "credential_request",
+  "credential_response",
+  "credential_finalization",
+  "session_key",
+];
+
+/// Everything observable from a single registration followed by a login
+pub struct Transcript {
+  pub entries: [(&'static str, Vec<u8>); TRANSCRIPT_LEN],
+}
+
+impl Transcript {
+  pub fn fingerprints(&self) -> [String; TRANSCRIPT_LEN] {
+    self
+      .entries
+      .clone()
+      .map(|(_, bytes)| hex::encode(Sha256::digest(&bytes)))
+  }
+}
+
+pub const FIXTURES: &[Fixture] = &[
+  Fixture {
+    name: "ascii password",
+    seed: [0; 32],
+    password: b"hunter2",
+    expected: [
+      "6a3ecb68fa8d1eabdd74a410b9ec24f4365d3e91d97e97e3eb9e0a513b7e3151",
+      "8f7554dd6600c2c61eca7ab8219051d56b83d4857e354f4e97b7e324a2eee427",
+      "f0827ca48863ddab1c96fbecb2d6c103582405dc0d5a2101f81b096b130779e7",
+      "634788cf67c0662d24cc29199752334d8dd09745ff953d07ee7fe8e7435990c0",
+      "4afd7c8e8b5c5eda2e4da01410cd34d7f4e0e9f0fbd0b4dc529205c06440e2a4",
+      "31e01bfb78e647f1832e62791d89b20b5f53b4bcc35fec59241efdb39bbd9c85",
+      "fd480cade4b7e1f8e94a4e1b0029e891b8fa87ea72ee7160af572cd48d73c404",
+      "364bc3b1f5f6022204d05ccc876b893f269ad480c4201ecf06780d1ef22c087c",
+      "42b1ee15062c7cbc54f1205de0256dc15cc377f4528c8105e739c7d6fdc3ba1f",
+    ],
+  },
+  Fixture {
+    name: "unicode password",
+    seed: [7; 32],
+    password: "pässwörd 🔑".as_bytes(),
+    expected: [
+      "8fce7a30c4d8240b63fb9496f10097f4ccf1da317aaa7d12afe73b1fd52342e5",
+      "2e54c634596d4d01c466590abbd8edee0d42bd280772f7ea3955bbe7aedcbfeb",
+      "062ed7fa5bc75e623bf0b3e9e19feed9088fd4d91f4f314e147cf89b4b314948",
+      "253b78057096f3e5359e8762d4b763f6e6410cbb0db38b7fbd5e47e83e577727",
+      "05a6a6901e10c0b85a254d6afcf9e24ffffc768ce88db9218eedffb29ab72f06",
+      "b09e327534e2205aea34082ac58f577887f7b9a1458baeac5a9caf8c847d5d57",
+      "3ae7e23a3c83074ee6f4a9c85db5c5836b0751842d6fe000c9a61797c4e93b56",
+      "0b92ff825696c743c91a8bee775456f4935b2afecc2fc5527a7be96663fe0a81",
+      "0f6b079de27992878803918b5faf8b11703b966a8bcaf4d64ebd7405eb6f97c8",
+    ],
+  },
+];
+
+/// Runs registration and login for the fixture with an RNG seeded from
+/// `fixture.seed`
+pub fn run_fixture(fixture: &Fixture) -> Result<Transcript, Error> {
+  run_handshake(fixture.seed, fixture.password)
+}
+
+/// Runs registration and login for `password` with an RNG seeded from
+/// `seed`. The same arguments always give the same transcript.
+pub fn run_handshake(
+  seed: [u8; 32],
+  password: &[u8],
+) -> Result<Transcript, Error> {
+  let mut rng = ChaCha20Rng::from_seed(seed);
+  let server_keypair = Cipher::generate_random_keypair(&mut rng);
+
+  let client_registration_start_result =
+    ClientRegistration::<Cipher>::start(&mut rng, password)?;
+  let registration_request =
+    client_registration_start_result.message.serialize();
+  let server_registration_start_result = ServerRegistration::<Cipher>::start(
+    &mut rng,
+    client_registration_start_result.message,
+    server_keypair.public(),
+  )?;
+  let registration_response =
+    server_registration_start_result.message.serialize();
+  let client_registration_finish_result =
+    client_registration_start_result.state.finish(
+      &mut rng,
+      server_registration_start_result.message,
+      ClientRegistrationFinishParameters::default(),
+    )?;
+  let registration_upload =
+    client_registration_finish_result.message.serialize();
+  let password_file = server_registration_start_result
+    .state
+    .finish(client_registration_finish_result.message)?;
+  let record = PasswordRecord::new(password_file).serialize();
+
+  let client_login_start_result = ClientLogin::<Cipher>::start(
+    &mut rng,
+    password,
+    ClientLoginStartParameters::default(),
+  )?;
+  let credential_request = client_login_start_result.message.serialize()?;
+  let server_login_start_result = ServerLogin::start(
+    &mut rng,
+    PasswordRecord::deserialize(&record)?.password_file,
+    server_keypair.private(),
+    client_login_start_result.message,
+    ServerLoginStartParameters::default(),
+  )?;
+  let credential_response = server_login_start_result.message.serialize()?;
+  let client_login_finish_result = client_login_start_result.state.finish(
+    server_login_start_result.message,
+    ClientLoginFinishParameters::default(),
+  )?;
+  let credential_finalization =
+    client_login_finish_result.message.serialize()?;
+  let server_login_finish_result = server_login_start_result
+    .state
+    .finish(client_login_finish_result.message)?;
+  if server_login_finish_result.session_key
+    != client_login_finish_result.session_key
+  {
+    return Err(Error::InvalidCredentials);
+  }
+
+  Ok(Transcript {
+    entries: [
+      ("registration_request", registration_request),
+      ("registration_response", registration_response),
+      ("registration_upload", registration_upload),
+      (
+        "registration_export_key",
+        client_registration_finish_result.export_key.to_vec(),
+      ),
+      ("record", record),
+      ("credential_request", credential_request),
+      ("credential_response", credential_response),
+      ("credential_finalization", credential_finalization),
+      ("session_key", client_login_finish_result.session_key),
+    ],
+  })
+}
+
+/// Returns a description of every transcript entry that doesn't match the
+/// fixtures. An empty result means this build is byte-compatible with every
+/// other build that passes.
+pub fn verify_all() -> Result<Vec<String>, Error> {
+  let mut mismatches = Vec::new();
+  for fixture in FIXTURES {
+    let transcript = run_fixture(fixture)?;
+    let fingerprints = transcript.fingerprints();
+    for (index, (name, _)) in transcript.entries.iter().enumerate() {
+      if fingerprints[index] != fixture.expected[index] {
+        mismatches.push(format!(
+          "{}: {} is {}, expected {}",
+          fixture.name, name, fingerprints[index], fixture.expected[index]
+        ));
+      }
+    }
+  }
+  Ok(mismatches)
+}
+
+/// A snapshot of the handshake `run_handshake` gives for `seed` and
+/// `password`, as JSON: every transcript entry in order, with its length and
+/// SHA-256, but not its contents. Any change to a message format or to how
+/// a key is derived changes the snapshot, so it suits snapshot tests; keys
+/// only appear as hashes.
+pub fn handshake_snapshot_json(
+  seed: [u8; 32],
+  password: &[u8],
+) -> Result<String, Error> {
+  let transcript = run_handshake(seed, password)?;
+  let entries: Vec<serde_json::Value> = transcript
+    .entries
+    .iter()
+    .zip(transcript.fingerprints())
+    .map(|((name, bytes), fingerprint)| {
+      json!({ "name": name, "length": bytes.len(), "sha256": fingerprint })
+    })
+    .collect();
+  let snapshot = json!({ "seed": hex::encode(seed), "entries": entries });
+  Ok(
+    serde_json::to_string_pretty(&snapshot).expect("JSON value serializes")
+      + "\n",
+  )
+}
+
+/// The fingerprint of every transcript entry (each message type, the record
+/// and the derived keys) this build of the crate was released with. A
+/// deployment stores these, and passes them to `assert_wire_compatibility`
+/// after upgrading dependencies.
+pub fn wire_fingerprints() -> BTreeMap<&'static str, &'static str> {
+  let fixture = &FIXTURES[0];
+  ENTRY_NAMES.into_iter().zip(fixture.expected).collect()
+}
+
+/// Fails with `Error::WireIncompatible`, naming the entries that differ,
+/// unless this build produces every fingerprint in `expected` and
+/// `expected` has a fingerprint for every entry. Names this build doesn't
+/// know about count as differing, as do entries missing from `expected`.
+pub fn assert_wire_compatibility(
+  expected: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+  let transcript = run_fixture(&FIXTURES[0])?;
+  let current: BTreeMap<&str, String> = transcript
+    .entries
+    .iter()
+    .map(|(name, _)| *name)
+    .zip(transcript.fingerprints())
+    .collect();
+  let changed: Vec<&str> = expected
+    .iter()
+    .filter(|(name, fingerprint)| {
+      current.get(name.as_str()) != Some(*fingerprint)
+    })
+    .map(|(name, _)| name.as_str())
+    .chain(
+      ENTRY_NAMES
+        .into_iter()
+        .filter(|name| !expected.contains_key(*name)),
+    )
+    .collect();
+  if !changed.is_empty() {
+    return Err(Error::WireIncompatible(changed.join(", ")));
+  }
+  Ok(())
+}
+
+/// Full transcripts of every fixture as JSON, all byte strings hex-encoded.
+/// These are checked in as `test-vectors/opaque.json` for other
+/// implementations (e.g. the web client's WASM build) to test against.
+pub fn interop_vectors_json() -> Result<String, Error> {
+  let mut fixtures = Vec::new();
+  for fixture in FIXTURES {
+    let transcript = run_fixture(fixture)?;
+    let entries: serde_json::Map<String, serde_json::Value> = transcript
+      .entries
+      .iter()
+      .map(|(name, bytes)| (name.to_string(), hex::encode(bytes).into()))
+      .collect();
+    fixtures.push(json!({
+      "name": fixture.name,
+      "seed": hex::encode(fixture.seed),
+      "password": hex::encode(fixture.password),
+      "entries": entries,
+    }));
+  }
+  let vectors = json!({ "fixtures": fixtures });
+  Ok(
+    serde_json::to_string_pretty(&vectors).expect("JSON value serializes")
+      + "\n",
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const VECTORS_PATH: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/test-vectors/opaque.json");
+
+  #[test]
+  fn test_fixtures() {
+    let mismatches = verify_all().unwrap();
+    assert!(mismatches.is_empty(), "{:#?}", mismatches);
+  }
+
+  #[test]
+  fn test_snapshot_matches_fixture() {
+    let fixture = &FIXTURES[0];
+    let snapshot: serde_json::Value = serde_json::from_str(
+      &handshake_snapshot_json(fixture.seed, fixture.password).unwrap(),
+    )
+    .unwrap();
+    let entries = snapshot["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), TRANSCRIPT_LEN);
+    for (entry, expected) in entries.iter().zip(fixture.expected) {
+      assert_eq!(entry["sha256"], expected);
+    }
+    assert_eq!(entries[0]["name"], "registration_request");
+    assert_eq!(entries[0]["length"], 32);
+  }
+
+  #[test]
+  fn test_wire_compatibility() {
+    let mut expected: BTreeMap<String, String> = wire_fingerprints()
+      .into_iter()
+      .map(|(name, fingerprint)| (name.to_string(), fingerprint.to_string()))
+      .collect();
+    assert!(assert_wire_compatibility(&expected).is_ok());
+    let transcript = run_fixture(&FIXTURES[0]).unwrap();
+    assert_eq!(transcript.entries.map(|(name, _)| name), ENTRY_NAMES);
+
+    expected.insert("credential_response".to_string(), "00".to_string());
+    expected.insert("unknown".to_string(), "00".to_string());
+    match assert_wire_compatibility(&expected) {
+      Err(Error::WireIncompatible(changed)) => {
+        assert_eq!(changed, "credential_response, unknown")
+      }
+      _ => panic!("expected the changed entries to be named"),
+    }
+
+    expected.remove("unknown");
+    expected.remove("session_key");
+    expected.remove("record");
+    match assert_wire_compatibility(&expected) {
+      Err(Error::WireIncompatible(changed)) => {
+        assert_eq!(changed, "credential_response, record, session_key")
+      }
+      _ => panic!("expected the missing entries to be named"),
+    }
+  }
+
+  /// Run with `UPDATE_INTEROP_VECTORS=1 cargo test test_interop_vectors` to
+  /// rewrite the checked-in vectors after an intentional format change
+  #[test]
+  fn test_interop_vectors() {
+    let generated = interop_vectors_json().unwrap();
+    if std::env::var_os("UPDATE_INTEROP_VECTORS").is_some() {
+      std::fs::write(VECTORS_PATH, &generated).unwrap();
+    }
+    let checked_in: serde_json::Value =
+      serde_json::from_str(&std::fs::read_to_string(VECTORS_PATH).unwrap())
+        .unwrap();
+    let generated: serde_json::Value =
+      serde_json::from_str(&generated).unwrap();
+    assert_eq!(checked_in, generated);
+  }
+
+  /// Replays the checked-in vectors from their seeds, the same way another
+  /// implementation consuming them would
+  #[test]
+  fn test_consume_interop_vectors() {
+    let vectors: serde_json::Value =
+      serde_json::from_str(&std::fs::read_to_string(VECTORS_PATH).unwrap())
+        .unwrap();
+    let vectors = vectors["fixtures"].as_array().unwrap();
+    assert_eq!(vectors.len(), FIXTURES.len());
+    for vector in vectors {
+      let seed = hex::decode(vector["seed"].as_str().unwrap()).unwrap();
+      let password = hex::decode(vector["password"].as_str().unwrap()).unwrap();
+      let fixture = FIXTURES
+        .iter()
+        .find(|fixture| fixture.name == vector["name"])
+        .unwrap();
+      assert_eq!(&fixture.seed[..], &seed[..]);
+      assert_eq!(fixture.password, &password[..]);
+      let transcript = run_fixture(fixture).unwrap();
+      for (name, bytes) in transcript.entries.iter() {
+        assert_eq!(
+          vector["entries"][name].as_str().unwrap(),
+          hex::encode(bytes),
+          "{}: {}",
+          fixture.name,
+          name
+        );
+      }
+    }
+  }
+}
@@ -0,0 +1,197 @@
+//! Keys derived from what a login produces: subkeys of the session key for
+//! each purpose a session needs one for, tokens naming the session, and
+//! the backup key from the export key (which is stable across the user's
+//! logins, so the backup key is too).
+//!
+//! Each derivation is HKDF-SHA512 over its root key with
+//! bytes(label) | bytes(purpose) as the info. The free functions use labels
+//! naming this crate; a service that shares root material with another
+//! derives through a `Deriver` with labels of its own, so the two never
+//! derive the same key: the identity service and the keyserver both see a
+//! user's export key, for example, and shouldn't be able to open each
+//! other's backups. Changing a label changes every key derived with it, so
+//! the labels are fixed when the `Deriver` is made and passed wherever keys
+//! are derived, rather than set for the whole process.
+
+use std::borrow::Cow;
+
+use hkdf::Hkdf;
+use sha2::Sha512;
+
+use crate::{serialization::Encoder, Error};
+
+/// What backup keys and tokens are, in bytes
+pub const DERIVED_KEY_LEN: usize = 32;
+/// HKDF-SHA512's limit
+pub const MAX_SUBKEY_LEN: usize = 255 * 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivationLabels {
+  pub session_subkey: Cow<'static, str>,
+  pub backup_key: Cow<'static, str>,
+  pub token: Cow<'static, str>,
+}
+
+impl DerivationLabels {
+  pub const DEFAULT: Self = Self {
+    session_subkey: Cow::Borrowed("comm-opaque session subkey"),
+    backup_key: Cow::Borrowed("comm-opaque backup key"),
+    token: Cow::Borrowed("comm-opaque token"),
+  };
+
+  /// Labels have to be non-empty and distinct, or two kinds of key could
+  /// come out the same
+  fn validate(&self) -> Result<(), Error> {
+    let labels = [&self.session_subkey, &self.backup_key, &self.token];
+    if labels.iter().any(|label| label.is_empty()) {
+      return Err(Error::InvalidKeyDerivation("empty label"));
+    }
+    if labels[0] == labels[1]
+      || labels[0] == labels[2]
+      || labels[1] == labels[2]
+    {
+      return Err(Error::InvalidKeyDerivation("labels are not distinct"));
+    }
+    Ok(())
+  }
+}
+
+impl Default for DerivationLabels {
+  fn default() -> Self {
+    Self::DEFAULT
+  }
+}
+
+/// Derives every kind of key under one set of labels
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Deriver {
+  labels: DerivationLabels,
+}
+
+/// What the free functions derive with
+const DEFAULT_DERIVER: Deriver = Deriver {
+  labels: DerivationLabels::DEFAULT,
+};
+
+impl Deriver {
+  /// Fails with `Error::InvalidKeyDerivation` if any label is empty or two
+  /// are the same
+  pub fn new(labels: DerivationLabels) -> Result<Self, Error> {
+    labels.validate()?;
+    Ok(Self { labels })
+  }
+
+  pub fn labels(&self) -> &DerivationLabels {
+    &self.labels
+  }
+
+  /// See `derive_session_subkey`
+  pub fn session_subkey(
+    &self,
+    session_key: &[u8],
+    purpose: &str,
+    len: usize,
+  ) -> Result<Vec<u8>, Error> {
+    derive(session_key, &self.labels.session_subkey, purpose, len)
+  }
+
+  /// See `derive_backup_key`
+  pub fn backup_key(&self, export_key: &[u8]) -> Result<Vec<u8>, Error> {
+    derive(export_key, &self.labels.backup_key, "", DERIVED_KEY_LEN)
+  }
+
+  /// See `derive_token`
+  pub fn token(
+    &self,
+    session_key: &[u8],
+    purpose: &str,
+  ) -> Result<Vec<u8>, Error> {
+    derive(session_key, &self.labels.token, purpose, DERIVED_KEY_LEN)
+  }
+}
+
+fn derive(
+  root_key: &[u8],
+  label: &str,
+  purpose: &str,
+  len: usize,
+) -> Result<Vec<u8>, Error> {
+  if len == 0 || len > MAX_SUBKEY_LEN {
+    return Err(Error::InvalidKeyDerivation("unsupported key length"));
+  }
+  let info = Encoder::new()
+    .bytes(label.as_bytes())
+    .bytes(purpose.as_bytes())
+    .finish();
+  let mut okm = vec![0; len];
+  Hkdf::<Sha512>::new(None, root_key)
+    .expand(&info, &mut okm)
+    .map_err(|_| Error::InvalidKeyDerivation("unsupported key length"))?;
+  Ok(okm)
+}
+
+/// A `len`-byte key for `purpose` (such as "message encryption"), which
+/// only the two ends of the session can derive
+pub fn derive_session_subkey(
+  session_key: &[u8],
+  purpose: &str,
+  len: usize,
+) -> Result<Vec<u8>, Error> {
+  DEFAULT_DERIVER.session_subkey(session_key, purpose, len)
+}
+
+/// The key the client's backups are encrypted with
+pub fn derive_backup_key(export_key: &[u8]) -> Result<Vec<u8>, Error> {
+  DEFAULT_DERIVER.backup_key(export_key)
+}
+
+/// A value both ends can present to `purpose` (such as a service the client
+/// connects to next) as proof of the session, without revealing the
+/// session key
+pub fn derive_token(
+  session_key: &[u8],
+  purpose: &str,
+) -> Result<Vec<u8>, Error> {
+  DEFAULT_DERIVER.token(session_key, purpose)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_labels_separate_keys() {
+    let root = [7; 64];
+    let subkey = derive_session_subkey(&root, "", DERIVED_KEY_LEN).unwrap();
+    let token = derive_token(&root, "").unwrap();
+    let backup_key = derive_backup_key(&root).unwrap();
+    assert_ne!(subkey, token);
+    assert_ne!(subkey, backup_key);
+    assert_ne!(token, backup_key);
+    assert_ne!(token, derive_token(&root, "other").unwrap());
+    assert_eq!(derive_session_subkey(&root, "", 80).unwrap().len(), 80);
+    assert!(derive_session_subkey(&root, "", 0).is_err());
+    assert!(derive_session_subkey(&root, "", MAX_SUBKEY_LEN + 1).is_err());
+
+    let custom = DerivationLabels {
+      token: Cow::Borrowed("identity service token"),
+      ..DerivationLabels::DEFAULT
+    };
+    let deriver = Deriver::new(custom.clone()).unwrap();
+    assert_ne!(deriver.token(&root, "").unwrap(), token);
+    assert_eq!(deriver.backup_key(&root).unwrap(), backup_key);
+    assert_eq!(Deriver::default().token(&root, "").unwrap(), token);
+
+    let duplicate = DerivationLabels {
+      backup_key: custom.token.clone(),
+      ..custom.clone()
+    };
+    assert!(Deriver::new(duplicate).is_err());
+    let empty = DerivationLabels {
+      token: Cow::Borrowed(""),
+      ..custom
+    };
+    assert!(Deriver::new(empty).is_err());
+    assert!(DerivationLabels::DEFAULT.validate().is_ok());
+  }
+}
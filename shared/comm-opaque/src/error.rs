@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use opaque_ke::errors::ProtocolError;
+
+use crate::{legacy::BcryptFailure, policy::PolicyViolation};
+
+/// Neither `Display` nor `Debug` includes passwords, hashes, envelopes or
+/// keys, so errors are safe to log and to pass on to JavaScript. opaque-ke's
+/// errors only name what failed; bcrypt's can quote the hash, so they are
+/// reduced to a `BcryptFailure` first.
+#[derive(
+  Debug, derive_more::Display, derive_more::From, derive_more::Error,
+)]
+pub enum Error {
+  #[display(...)]
+  Protocol(ProtocolError),
+  #[display(fmt = "{}", _0)]
+  Bcrypt(#[error(not(source))] BcryptFailure),
+  #[display(fmt = "no credentials found for user")]
+  CredentialsNotFound,
+  #[display(fmt = "invalid credentials")]
+  InvalidCredentials,
+  #[display(fmt = "user has no OPAQUE registration")]
+  OpaqueRegistrationNotFound,
+  #[display(
+    fmt = "legacy login not allowed for users with OPAQUE registration"
+  )]
+  LegacyLoginNotAllowed,
+  #[display(fmt = "invalid password record")]
+  InvalidRecord,
+  #[display(fmt = "re-registration message not bound to this session")]
+  InvalidUpgradeTag,
+  #[display(fmt = "invalid message tag")]
+  InvalidMessageTag,
+  #[display(fmt = "unsupported wire version {}", _0)]
+  UnsupportedWireVersion(#[error(not(source))] u16),
+  #[display(fmt = "version below the minimum accepted version")]
+  VersionBelowMinimum,
+  #[display(fmt = "malformed serialized data")]
+  Serialization,
+  #[display(fmt = "invalid server setup")]
+  InvalidServerSetup,
+  #[display(fmt = "unsupported KSF parameter set {}", _0)]
+  UnsupportedKsf(#[error(not(source))] u8),
+  #[display(fmt = "{}", _0)]
+  PasswordPolicy(#[error(not(source))] PolicyViolation),
+  #[display(
+    fmt = "too many failed login attempts, retry in {} seconds",
+    "_0.as_secs() + 1"
+  )]
+  LockedOut(#[error(not(source))] Duration),
+  #[display(fmt = "{} is not FIPS-approved", _0)]
+  NotFipsApproved(#[error(not(source))] &'static str),
+  #[display(fmt = "invalid attestation root key")]
+  InvalidAttestationKey,
+  #[display(fmt = "server key attestation does not verify")]
+  InvalidAttestation,
+  #[display(fmt = "message was already received")]
+  ReplayedMessage,
+  #[display(fmt = "malformed keystore")]
+  InvalidKeystore,
+  #[display(fmt = "wrong keystore passphrase, or the keystore was modified")]
+  KeystoreDecryption,
+  #[display(fmt = "server key backend failed")]
+  KeyBackend,
+  #[display(fmt = "wire format changed for {}", _0)]
+  WireIncompatible(#[error(not(source))] String),
+  #[display(fmt = "malformed or corrupted compressed data")]
+  InvalidCompressed,
+  #[display(fmt = "decompressed data exceeds {} bytes", _0)]
+  DecompressedTooLarge(#[error(not(source))] usize),
+  #[display(fmt = "invalid key derivation: {}", _0)]
+  #[from(ignore)]
+  InvalidKeyDerivation(#[error(not(source))] &'static str),
+  #[display(fmt = "login took the legacy path for a user with a record")]
+  UnexpectedLegacyLogin,
+}
+
+impl From<bcrypt::BcryptError> for Error {
+  fn from(e: bcrypt::BcryptError) -> Self {
+    Error::Bcrypt(e.into())
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+  /// A protocol message was malformed, replayed or didn't verify
+  Protocol,
+  /// The user doesn't exist or didn't prove knowledge of the password
+  Credentials,
+  /// Refused by the password, version or wire policy
+  Policy,
+  /// Stored or received data doesn't parse
+  Format,
+  /// The server's keys or settings are missing or wrong
+  Configuration,
+  RateLimit,
+  /// An external service the server depends on failed
+  Backend,
+}
+
+impl ErrorCategory {
+  pub fn name(self) -> &'static str {
+    match self {
+      ErrorCategory::Protocol => "protocol",
+      ErrorCategory::Credentials => "credentials",
+      ErrorCategory::Policy => "policy",
+      ErrorCategory::Format => "format",
+      ErrorCategory::Configuration => "configuration",
+      ErrorCategory::RateLimit => "rate_limit",
+      ErrorCategory::Backend => "backend",
+    }
+  }
+}
+
+pub struct ErrorInfo {
+  pub code: &'static str,
+  pub category: ErrorCategory,
+  /// Whether the same call can succeed later without the caller changing
+  /// anything
+  pub retriable: bool,
+}
+
+const fn info(
+  code: &'static str,
+  category: ErrorCategory,
+  retriable: bool,
+) -> ErrorInfo {
+  ErrorInfo {
+    code,
+    category,
+    retriable,
+  }
+}
+
+/// One entry per `Error` variant, in declaration order
+pub const ERROR_CATALOG: &[ErrorInfo] = &[
+  info("PROTOCOL_ERROR", ErrorCategory::Protocol, false),
+  info("BCRYPT_ERROR", ErrorCategory::Format, false),
+  info("CREDENTIALS_NOT_FOUND", ErrorCategory::Credentials, false),
+  info("INVALID_CREDENTIALS", ErrorCategory::Credentials, false),
+  info(
+    "OPAQUE_REGISTRATION_NOT_FOUND",
+    ErrorCategory::Credentials,
+    false,
+  ),
+  info(
+    "LEGACY_LOGIN_NOT_ALLOWED",
+    ErrorCategory::Credentials,
+    false,
+  ),
+  info("INVALID_RECORD", ErrorCategory::Format, false),
+  info("INVALID_UPGRADE_TAG", ErrorCategory::Protocol, false),
+  info("INVALID_MESSAGE_TAG", ErrorCategory::Format, false),
+  info("UNSUPPORTED_WIRE_VERSION", ErrorCategory::Policy, false),
+  info("VERSION_BELOW_MINIMUM", ErrorCategory::Policy, false),
+  info("SERIALIZATION", ErrorCategory::Format, false),
+  info("INVALID_SERVER_SETUP", ErrorCategory::Configuration, false),
+  info("UNSUPPORTED_KSF", ErrorCategory::Configuration, false),
+  info("PASSWORD_POLICY", ErrorCategory::Policy, false),
+  info("LOCKED_OUT", ErrorCategory::RateLimit, true),
+  info("NOT_FIPS_APPROVED", ErrorCategory::Configuration, false),
+  info(
+    "INVALID_ATTESTATION_KEY",
+    ErrorCategory::Configuration,
+    false,
+  ),
+  info("INVALID_ATTESTATION", ErrorCategory::Protocol, false),
+  info("REPLAYED_MESSAGE", ErrorCategory::Protocol, false),
+  info("INVALID_KEYSTORE", ErrorCategory::Format, false),
+  info("KEYSTORE_DECRYPTION", ErrorCategory::Configuration, false),
+  info("KEY_BACKEND", ErrorCategory::Backend, true),
+  info("WIRE_INCOMPATIBLE", ErrorCategory::Configuration, false),
+  info("INVALID_COMPRESSED", ErrorCategory::Format, false),
+  info("DECOMPRESSED_TOO_LARGE", ErrorCategory::Format, false),
+  info(
+    "INVALID_KEY_DERIVATION",
+    ErrorCategory::Configuration,
+    false,
+  ),
+  info("UNEXPECTED_LEGACY_LOGIN", ErrorCategory::Protocol, false),
+];
+
+impl Error {
+  /// This error's entry in `ERROR_CATALOG`
+  pub fn info(&self) -> &'static ErrorInfo {
+    let index = match self {
+      Error::Protocol(_) => 0,
+      Error::Bcrypt(_) => 1,
+      Error::CredentialsNotFound => 2,
+      Error::InvalidCredentials => 3,
+      Error::OpaqueRegistrationNotFound => 4,
+      Error::LegacyLoginNotAllowed => 5,
+      Error::InvalidRecord => 6,
+      Error::InvalidUpgradeTag => 7,
+      Error::InvalidMessageTag => 8,
+      Error::UnsupportedWireVersion(_) => 9,
+      Error::VersionBelowMinimum => 10,
+      Error::Serialization => 11,
+      Error::InvalidServerSetup => 12,
+      Error::UnsupportedKsf(_) => 13,
+      Error::PasswordPolicy(_) => 14,
+      Error::LockedOut(_) => 15,
+      Error::NotFipsApproved(_) => 16,
+      Error::InvalidAttestationKey => 17,
+      Error::InvalidAttestation => 18,
+      Error::ReplayedMessage => 19,
+      Error::InvalidKeystore => 20,
+      Error::KeystoreDecryption => 21,
+      Error::KeyBackend => 22,
+      Error::WireIncompatible(_) => 23,
+      Error::InvalidCompressed => 24,
+      Error::DecompressedTooLarge(_) => 25,
+      Error::InvalidKeyDerivation(_) => 26,
+      Error::UnexpectedLegacyLogin => 27,
+    };
+    &ERROR_CATALOG[index]
+  }
+
+  /// A stable identifier for the kind of error, unlike the message
+  pub fn code(&self) -> &'static str {
+    self.info().code
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_every_catalog_entry_is_used() {
+    let errors = [
+      Error::Protocol(ProtocolError::ServerError),
+      Error::Bcrypt(BcryptFailure::MalformedHash),
+      Error::CredentialsNotFound,
+      Error::InvalidCredentials,
+      Error::OpaqueRegistrationNotFound,
+      Error::LegacyLoginNotAllowed,
+      Error::InvalidRecord,
+      Error::InvalidUpgradeTag,
+      Error::InvalidMessageTag,
+      Error::UnsupportedWireVersion(2),
+      Error::VersionBelowMinimum,
+      Error::Serialization,
+      Error::InvalidServerSetup,
+      Error::UnsupportedKsf(9),
+      Error::PasswordPolicy(PolicyViolation::TooShort),
+      Error::LockedOut(Duration::from_secs(1)),
+      Error::NotFipsApproved("bcrypt"),
+      Error::InvalidAttestationKey,
+      Error::InvalidAttestation,
+      Error::ReplayedMessage,
+      Error::InvalidKeystore,
+      Error::KeystoreDecryption,
+      Error::KeyBackend,
+      Error::WireIncompatible("record".to_string()),
+      Error::InvalidCompressed,
+      Error::DecompressedTooLarge(1024),
+      Error::InvalidKeyDerivation("empty label"),
+      Error::UnexpectedLegacyLogin,
+    ];
+    assert_eq!(errors.len(), ERROR_CATALOG.len());
+    for (error, info) in errors.iter().zip(ERROR_CATALOG) {
+      assert_eq!(error.code(), info.code);
+    }
+  }
+}
@@ -0,0 +1,180 @@
+//! Structured events for abuse detection. The server login paths emit them
+//! as they happen; a single sink, set with `set_event_sink`, receives all of
+//! them, from whichever thread the operation ran on. Events carry the
+//! tenant, and the credential identifier when the caller supplied one,
+//! never the password or anything derived from it.
+
+use std::{sync::RwLock, time::Duration};
+
+use crate::{lockout::is_authentication_failure, Error};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoginMethod {
+  Opaque,
+  Legacy,
+}
+
+impl LoginMethod {
+  pub fn name(self) -> &'static str {
+    match self {
+      LoginMethod::Opaque => "opaque",
+      LoginMethod::Legacy => "legacy",
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegistrationSource {
+  Registration,
+  LegacyMigration,
+  Reregistration,
+}
+
+impl RegistrationSource {
+  pub fn name(self) -> &'static str {
+    match self {
+      RegistrationSource::Registration => "registration",
+      RegistrationSource::LegacyMigration => "legacy_migration",
+      RegistrationSource::Reregistration => "reregistration",
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureReason {
+  InvalidCredentials,
+  UnknownUser,
+  LockedOut,
+  /// The record or message is older than the version policy allows
+  VersionRejected,
+  /// The credential request was a replay of an earlier one
+  Replayed,
+  /// Anything else: malformed messages, protocol errors
+  InvalidRequest,
+}
+
+impl FailureReason {
+  /// `None` for `OpaqueRegistrationNotFound`, which tells the client to
+  /// retry with a legacy login rather than failing it
+  pub fn classify(error: &Error) -> Option<Self> {
+    Some(match error {
+      Error::OpaqueRegistrationNotFound => return None,
+      e if is_authentication_failure(e) => FailureReason::InvalidCredentials,
+      Error::CredentialsNotFound => FailureReason::UnknownUser,
+      Error::LockedOut(_) => FailureReason::LockedOut,
+      Error::VersionBelowMinimum => FailureReason::VersionRejected,
+      Error::ReplayedMessage => FailureReason::Replayed,
+      _ => FailureReason::InvalidRequest,
+    })
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      FailureReason::InvalidCredentials => "invalid_credentials",
+      FailureReason::UnknownUser => "unknown_user",
+      FailureReason::LockedOut => "locked_out",
+      FailureReason::VersionRejected => "version_rejected",
+      FailureReason::Replayed => "replayed",
+      FailureReason::InvalidRequest => "invalid_request",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SecurityEvent {
+  RegistrationCreated {
+    tenant: String,
+    credential_identifier: Option<String>,
+    source: RegistrationSource,
+  },
+  LoginSucceeded {
+    tenant: String,
+    credential_identifier: Option<String>,
+    method: LoginMethod,
+  },
+  LoginFailed {
+    tenant: String,
+    credential_identifier: Option<String>,
+    method: LoginMethod,
+    reason: FailureReason,
+  },
+  LockoutTriggered {
+    tenant: String,
+    credential_identifier: String,
+    duration: Duration,
+  },
+}
+
+pub type EventSink = Box<dyn Fn(&SecurityEvent) + Send + Sync>;
+
+static EVENT_SINK: RwLock<Option<EventSink>> = RwLock::new(None);
+
+/// Replaces the current sink; `None` stops emitting events
+pub fn set_event_sink(sink: Option<EventSink>) {
+  *EVENT_SINK.write().unwrap_or_else(|e| e.into_inner()) = sink;
+}
+
+/// Called on the emitting thread, so the sink shouldn't block
+pub fn emit(event: SecurityEvent) {
+  if let Some(sink) = EVENT_SINK
+    .read()
+    .unwrap_or_else(|e| e.into_inner())
+    .as_ref()
+  {
+    sink(&event);
+  }
+}
+
+/// Emits `LoginSucceeded` or `LoginFailed` for the result of a step that
+/// authenticates the user
+pub fn emit_login<T>(
+  tenant: &str,
+  credential_identifier: Option<&str>,
+  method: LoginMethod,
+  result: &Result<T, Error>,
+) {
+  let tenant = tenant.to_string();
+  let credential_identifier = credential_identifier.map(str::to_string);
+  match result {
+    Ok(_) => emit(SecurityEvent::LoginSucceeded {
+      tenant,
+      credential_identifier,
+      method,
+    }),
+    Err(e) => {
+      if let Some(reason) = FailureReason::classify(e) {
+        emit(SecurityEvent::LoginFailed {
+          tenant,
+          credential_identifier,
+          method,
+          reason,
+        });
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_classify() {
+    assert_eq!(
+      FailureReason::classify(&Error::InvalidCredentials),
+      Some(FailureReason::InvalidCredentials)
+    );
+    assert_eq!(
+      FailureReason::classify(&Error::LockedOut(Duration::from_secs(1))),
+      Some(FailureReason::LockedOut)
+    );
+    assert_eq!(
+      FailureReason::classify(&Error::InvalidMessageTag),
+      Some(FailureReason::InvalidRequest)
+    );
+    assert_eq!(
+      FailureReason::classify(&Error::OpaqueRegistrationNotFound),
+      None
+    );
+  }
+}
@@ -0,0 +1,169 @@
+//! FIPS mode, for deployments that may only use FIPS-approvable primitives.
+//!
+//! Once `enable_fips_mode` is called, every function in this crate that goes
+//! through `Cipher` (Ristretto255, SHA-512, Argon2id), verifies a bcrypt
+//! hash or reads or writes a keystore fails with `Error::NotFipsApproved`
+//! instead of running. Such deployments use `FipsCipher` with opaque-ke
+//! directly: P-256, SHA-256 (also for HKDF and HMAC) and PBKDF2-HMAC-SHA-256
+//! as the KSF. FIPS mode can't be turned off again in the same process.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use digest::{generic_array::GenericArray, Digest};
+use hmac::Hmac;
+use opaque_ke::{
+  ciphersuite::CipherSuite, errors::InternalPakeError, hash::Hash,
+  slow_hash::SlowHash,
+};
+
+use crate::Error;
+
+pub use crate::p256_group::{P256Point, P256Scalar};
+
+/// OWASP's recommendation for PBKDF2-HMAC-SHA-256
+pub const PBKDF2_ITERATIONS: u32 = 600_000;
+
+pub struct FipsCipher;
+
+impl CipherSuite for FipsCipher {
+  type Group = P256Point;
+  type KeyExchange = opaque_ke::key_exchange::tripledh::TripleDH;
+  type Hash = sha2::Sha256;
+  type SlowHash = Pbkdf2Wrapper;
+}
+
+/// PBKDF2-HMAC-SHA-256 with `PBKDF2_ITERATIONS`. Like `ArgonWrapper`, it
+/// uses a fixed salt: its input is the OPRF output, which is already unique
+/// to the user.
+pub struct Pbkdf2Wrapper;
+
+impl<D: Hash> SlowHash<D> for Pbkdf2Wrapper {
+  fn hash(
+    input: GenericArray<u8, <D as Digest>::OutputSize>,
+  ) -> Result<Vec<u8>, InternalPakeError> {
+    let mut output = vec![0u8; <D as Digest>::output_size()];
+    pbkdf2::pbkdf2::<Hmac<sha2::Sha256>>(
+      &input,
+      &[0; 16],
+      PBKDF2_ITERATIONS,
+      &mut output,
+    );
+    Ok(output)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Primitive {
+  /// `Cipher`: Ristretto255, SHA-512 and Argon2id
+  Ristretto255Suite,
+  Bcrypt,
+  /// `keystore`: Argon2id and XChaCha20-Poly1305
+  Keystore,
+}
+
+impl Primitive {
+  pub fn name(self) -> &'static str {
+    match self {
+      Primitive::Ristretto255Suite => "the Ristretto255 OPAQUE suite",
+      Primitive::Bcrypt => "bcrypt",
+      Primitive::Keystore => "the keystore format",
+    }
+  }
+}
+
+static FIPS_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn enable_fips_mode() {
+  FIPS_MODE.store(true, Ordering::Release);
+}
+
+pub fn fips_mode() -> bool {
+  FIPS_MODE.load(Ordering::Acquire)
+}
+
+fn check(fips_mode: bool, primitive: Primitive) -> Result<(), Error> {
+  if fips_mode {
+    return Err(Error::NotFipsApproved(primitive.name()));
+  }
+  Ok(())
+}
+
+/// Called at the top of every function that uses `primitive`
+pub(crate) fn require_approved(primitive: Primitive) -> Result<(), Error> {
+  check(fips_mode(), primitive)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use opaque_ke::{
+    rand::rngs::OsRng, ClientLogin, ClientLoginFinishParameters,
+    ClientLoginStartParameters, ClientRegistration,
+    ClientRegistrationFinishParameters, ServerLogin,
+    ServerLoginStartParameters, ServerRegistration,
+  };
+
+  #[test]
+  fn test_check() {
+    assert!(check(false, Primitive::Bcrypt).is_ok());
+    assert!(matches!(
+      check(true, Primitive::Bcrypt),
+      Err(Error::NotFipsApproved("bcrypt"))
+    ));
+  }
+
+  #[test]
+  fn test_fips_cipher_registration_and_login() {
+    let password = b"hunter2";
+    let server_keypair = FipsCipher::generate_random_keypair(&mut OsRng);
+    let client_start =
+      ClientRegistration::<FipsCipher>::start(&mut OsRng, password).unwrap();
+    let server_start = ServerRegistration::<FipsCipher>::start(
+      &mut OsRng,
+      client_start.message,
+      server_keypair.public(),
+    )
+    .unwrap();
+    let client_finish = client_start
+      .state
+      .finish(
+        &mut OsRng,
+        server_start.message,
+        ClientRegistrationFinishParameters::default(),
+      )
+      .unwrap();
+    let password_file =
+      server_start.state.finish(client_finish.message).unwrap();
+
+    let client_login_start = ClientLogin::<FipsCipher>::start(
+      &mut OsRng,
+      password,
+      ClientLoginStartParameters::default(),
+    )
+    .unwrap();
+    let server_login_start = ServerLogin::start(
+      &mut OsRng,
+      password_file,
+      server_keypair.private(),
+      client_login_start.message,
+      ServerLoginStartParameters::default(),
+    )
+    .unwrap();
+    let client_login_finish = client_login_start
+      .state
+      .finish(
+        server_login_start.message,
+        ClientLoginFinishParameters::default(),
+      )
+      .unwrap();
+    let server_login_finish = server_login_start
+      .state
+      .finish(client_login_finish.message)
+      .unwrap();
+    assert_eq!(
+      client_login_finish.session_key,
+      server_login_finish.session_key
+    );
+    assert_eq!(client_login_finish.export_key, client_finish.export_key);
+  }
+}
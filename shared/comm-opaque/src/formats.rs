@@ -0,0 +1,481 @@
+//! Machine-readable descriptions of every binary format this crate produces,
+//! so services that only store or forward these values can still validate
+//! them. `formats_json` renders the descriptions as JSON; the output is
+//! checked in as `test-vectors/formats.json`, and a test keeps both in step
+//! with what the code actually writes.
+//!
+//! Field types follow `serialization`: integers are big-endian, `bytes`
+//! fields carry a u32 length prefix, and `rest` runs to the end of the input.
+//! opaque-ke messages are described by their length only; their internal
+//! layout belongs to opaque-ke 1.2.
+
+use serde_json::{json, Value};
+
+use crate::{
+  api::{
+    CLIENT_LOGIN_MAGIC, LOGIN_STATE_FORMAT, SEALED_LOGIN_FORMAT,
+    SEALED_LOGIN_MAGIC, SERVER_LOGIN_MAGIC,
+  },
+  attestation::ATTESTATION_LEN,
+  compression::{COMPRESSED_FORMAT, COMPRESSED_MAGIC},
+  keystore::{KEYSTORE_FORMAT, KEYSTORE_MAGIC, NONCE_LEN, SALT_LEN},
+  record::{CURRENT_RECORD_FORMAT, RECORD_MAGIC},
+  server_setup::{SEALED_FORMAT, SEALED_MAGIC},
+  sizes::{
+    CREDENTIAL_FINALIZATION_LEN, CREDENTIAL_REQUEST_LEN,
+    CREDENTIAL_RESPONSE_LEN, PRIVATE_KEY_LEN, REGISTRATION_REQUEST_LEN,
+    REGISTRATION_RESPONSE_LEN, REGISTRATION_UPLOAD_LEN, TRANSCRIPT_HASH_LEN,
+  },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldType {
+  /// A fixed byte string, identifying the format
+  Magic(&'static [u8]),
+  /// A u8 that always has the given value in this version of the format
+  Format(u8),
+  U8,
+  U16,
+  /// Exactly this many bytes, without a length prefix
+  Fixed(usize),
+  /// A u32 length prefix, then that many bytes, along with the length the
+  /// contents always have, where they have one
+  Bytes(Option<usize>),
+  /// Everything up to the end of the input; always the last field
+  Rest,
+}
+
+pub struct Field {
+  pub name: &'static str,
+  pub field_type: FieldType,
+  pub description: &'static str,
+}
+
+pub struct Format {
+  pub name: &'static str,
+  pub description: &'static str,
+  /// Whether the bytes are hex-encoded wherever they are stored
+  pub hex: bool,
+  pub fields: &'static [Field],
+}
+
+const fn field(
+  name: &'static str,
+  field_type: FieldType,
+  description: &'static str,
+) -> Field {
+  Field {
+    name,
+    field_type,
+    description,
+  }
+}
+
+macro_rules! opaque_message {
+  ($name:literal, $description:literal, $len:expr) => {
+    Format {
+      name: $name,
+      description: $description,
+      hex: false,
+      fields: &[field(
+        "message",
+        FieldType::Fixed($len),
+        "opaque-ke encoding",
+      )],
+    }
+  };
+}
+
+pub const FORMATS: &[Format] = &[
+  Format {
+    name: "password_record",
+    description: "A user's OPAQUE password file as stored by the server",
+    hex: false,
+    fields: &[
+      field("magic", FieldType::Magic(RECORD_MAGIC), ""),
+      field("format", FieldType::Format(CURRENT_RECORD_FORMAT), ""),
+      field("suite", FieldType::U8, "ciphersuite of the password file"),
+      field(
+        "ksf",
+        FieldType::U8,
+        "KSF parameter set of the password file",
+      ),
+      field(
+        "password_file",
+        FieldType::Bytes(None),
+        "opaque-ke ServerRegistration",
+      ),
+    ],
+  },
+  Format {
+    name: "sealed_server_setup",
+    description: "The server's static keypair, checksummed but not \
+                  encrypted",
+    hex: true,
+    fields: &[
+      field("magic", FieldType::Magic(SEALED_MAGIC), ""),
+      field("format", FieldType::Format(SEALED_FORMAT), ""),
+      field(
+        "private_key",
+        FieldType::Bytes(Some(PRIVATE_KEY_LEN)),
+        "Ristretto255 scalar",
+      ),
+      field(
+        "checksum",
+        FieldType::Fixed(32),
+        "SHA-256 of all preceding bytes",
+      ),
+    ],
+  },
+  Format {
+    name: "keystore",
+    description: "The server's keypair and pepper, encrypted under a \
+                  passphrase",
+    hex: false,
+    fields: &[
+      field("magic", FieldType::Magic(KEYSTORE_MAGIC), ""),
+      field("format", FieldType::Format(KEYSTORE_FORMAT), ""),
+      field("ksf", FieldType::U8, "Argon2id parameter set of the key"),
+      field("salt", FieldType::Fixed(SALT_LEN), "Argon2id salt"),
+      field(
+        "nonce",
+        FieldType::Fixed(NONCE_LEN),
+        "XChaCha20-Poly1305 nonce",
+      ),
+      field(
+        "ciphertext",
+        FieldType::Rest,
+        "XChaCha20-Poly1305 of bytes(private_key) | bytes(pepper), with \
+         every preceding byte as associated data",
+      ),
+    ],
+  },
+  Format {
+    name: "server_key_attestation",
+    description: "The root key's signature over a server public key",
+    hex: false,
+    fields: &[field(
+      "signature",
+      FieldType::Fixed(ATTESTATION_LEN),
+      "Ed25519 over bytes(\"comm-opaque server key attestation\") | \
+       bytes(server_public_key)",
+    )],
+  },
+  Format {
+    name: "tagged_message",
+    description: "A protocol message with its wire version and type",
+    hex: false,
+    fields: &[
+      field("version", FieldType::U16, "wire version"),
+      field(
+        "message_type",
+        FieldType::U8,
+        "1 to 6: registration request, response and upload, then \
+         credential request, response and finalization",
+      ),
+      field("payload", FieldType::Rest, "the message"),
+    ],
+  },
+  Format {
+    name: "reregistration_tag",
+    description: "Binds a re-registration message to the login session",
+    hex: false,
+    fields: &[field(
+      "tag",
+      FieldType::Fixed(64),
+      "HMAC-SHA512 over bytes(label) | bytes(message)",
+    )],
+  },
+  Format {
+    name: "client_login_state",
+    description: "A client's login between start and finish",
+    hex: false,
+    fields: &[
+      field("magic", FieldType::Magic(CLIENT_LOGIN_MAGIC), ""),
+      field("format", FieldType::Format(LOGIN_STATE_FORMAT), ""),
+      field(
+        "credential_request",
+        FieldType::Bytes(Some(CREDENTIAL_REQUEST_LEN)),
+        "kept for the transcript hash",
+      ),
+      field(
+        "state",
+        FieldType::Bytes(None),
+        "opaque-ke ClientLogin, which contains the password",
+      ),
+    ],
+  },
+  Format {
+    name: "server_login_state",
+    description: "A server's login between start and finish, in the \
+                  clear. Secret: it holds the expected client MAC and the \
+                  session key.",
+    hex: false,
+    fields: &[
+      field("magic", FieldType::Magic(SERVER_LOGIN_MAGIC), ""),
+      field("format", FieldType::Format(LOGIN_STATE_FORMAT), ""),
+      field("ksf", FieldType::U8, "KSF parameter set of the record"),
+      field(
+        "needs_reregistration",
+        FieldType::U8,
+        "1 if the record is outdated, otherwise 0",
+      ),
+      field(
+        "transcript_hash",
+        FieldType::Fixed(TRANSCRIPT_HASH_LEN),
+        "SHA-512 of a label and the length-prefixed credential request \
+         and response, the public messages only",
+      ),
+      field("state", FieldType::Bytes(None), "opaque-ke ServerLogin"),
+    ],
+  },
+  Format {
+    name: "sealed_server_login_state",
+    description: "A server_login_state encrypted under a key the server \
+                  holds, for storing it outside the process",
+    hex: false,
+    fields: &[
+      field("magic", FieldType::Magic(SEALED_LOGIN_MAGIC), ""),
+      field("format", FieldType::Format(SEALED_LOGIN_FORMAT), ""),
+      field(
+        "nonce",
+        FieldType::Fixed(NONCE_LEN),
+        "XChaCha20-Poly1305 nonce",
+      ),
+      field(
+        "ciphertext",
+        FieldType::Rest,
+        "XChaCha20-Poly1305 of the server_login_state, with every \
+         preceding byte as associated data",
+      ),
+    ],
+  },
+  Format {
+    name: "compressed",
+    description: "Any of the other formats, compressed for storage",
+    hex: false,
+    fields: &[
+      field("magic", FieldType::Magic(COMPRESSED_MAGIC), ""),
+      field("format", FieldType::Format(COMPRESSED_FORMAT), ""),
+      field("method", FieldType::U8, "0: stored, 1: LZ4 block"),
+      field("length", FieldType::Fixed(4), "u32 length of the data"),
+      field("checksum", FieldType::Fixed(32), "SHA-256 of the data"),
+      field("payload", FieldType::Bytes(None), "the data, compressed"),
+    ],
+  },
+  opaque_message!(
+    "registration_request",
+    "Client to server",
+    REGISTRATION_REQUEST_LEN
+  ),
+  opaque_message!(
+    "registration_response",
+    "Server to client",
+    REGISTRATION_RESPONSE_LEN
+  ),
+  opaque_message!(
+    "registration_upload",
+    "Client to server",
+    REGISTRATION_UPLOAD_LEN
+  ),
+  opaque_message!(
+    "credential_request",
+    "Client to server",
+    CREDENTIAL_REQUEST_LEN
+  ),
+  opaque_message!(
+    "credential_response",
+    "Server to client",
+    CREDENTIAL_RESPONSE_LEN
+  ),
+  opaque_message!(
+    "credential_finalization",
+    "Client to server",
+    CREDENTIAL_FINALIZATION_LEN
+  ),
+];
+
+fn field_type_json(field_type: FieldType) -> Value {
+  match field_type {
+    FieldType::Magic(magic) => json!({
+      "type": "magic",
+      "length": magic.len(),
+      "value": hex::encode(magic),
+    }),
+    FieldType::Format(value) => json!({ "type": "u8", "value": value }),
+    FieldType::U8 => json!({ "type": "u8" }),
+    FieldType::U16 => json!({ "type": "u16" }),
+    FieldType::Fixed(len) => json!({ "type": "fixed", "length": len }),
+    FieldType::Bytes(None) => json!({ "type": "bytes" }),
+    FieldType::Bytes(Some(len)) => json!({ "type": "bytes", "length": len }),
+    FieldType::Rest => json!({ "type": "rest" }),
+  }
+}
+
+pub fn formats_json() -> String {
+  let formats: serde_json::Map<String, Value> = FORMATS
+    .iter()
+    .map(|format| {
+      let fields: Vec<Value> = format
+        .fields
+        .iter()
+        .map(|field| {
+          let mut value = field_type_json(field.field_type);
+          value["name"] = field.name.into();
+          if !field.description.is_empty() {
+            value["description"] = field.description.into();
+          }
+          value
+        })
+        .collect();
+      let description = json!({
+        "description": format.description,
+        "encoding": if format.hex { "hex" } else { "binary" },
+        "fields": fields,
+      });
+      (format.name.to_string(), description)
+    })
+    .collect();
+  serde_json::to_string_pretty(&json!({ "formats": formats }))
+    .expect("JSON value serializes")
+    + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    api::LoginStateKey, attestation, compression, keystore::Keystore,
+    ksf::KSF_DEFAULT, serialization::Decoder, server_setup::ServerSetup,
+    upgrade, version, OpaqueClient, OpaqueServer,
+  };
+
+  const FORMATS_PATH: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/test-vectors/formats.json");
+
+  /// Whether `input` is exactly one value of `format`
+  fn matches_format(format: &Format, input: &[u8]) -> bool {
+    let mut decoder = Decoder::new(input);
+    for field in format.fields {
+      let matches = match field.field_type {
+        FieldType::Magic(magic) => {
+          decoder.fixed(magic.len()).map(|bytes| bytes == magic)
+        }
+        FieldType::Format(value) => decoder.u8().map(|byte| byte == value),
+        FieldType::U8 => decoder.u8().map(|_| true),
+        FieldType::U16 => decoder.u16().map(|_| true),
+        FieldType::Fixed(len) => decoder.fixed(len).map(|_| true),
+        FieldType::Bytes(len) => decoder
+          .bytes()
+          .map(|bytes| len.is_none_or(|len| bytes.len() == len)),
+        FieldType::Rest => return true,
+      };
+      if !matches.unwrap_or(false) {
+        return false;
+      }
+    }
+    decoder.finish().is_ok()
+  }
+
+  fn format(name: &str) -> &'static Format {
+    FORMATS.iter().find(|format| format.name == name).unwrap()
+  }
+
+  /// Run with `UPDATE_FORMATS=1 cargo test test_checked_in_formats` to
+  /// rewrite the checked-in descriptions after a format change
+  #[test]
+  fn test_checked_in_formats() {
+    let generated = formats_json();
+    if std::env::var_os("UPDATE_FORMATS").is_some() {
+      std::fs::write(FORMATS_PATH, &generated).unwrap();
+    }
+    assert_eq!(std::fs::read_to_string(FORMATS_PATH).unwrap(), generated);
+  }
+
+  #[test]
+  fn test_formats_match_encodings() {
+    let server_setup = ServerSetup::generate();
+    let server = OpaqueServer::from_setup(&server_setup);
+    let (client_registering, registration_request) =
+      OpaqueClient::register(b"hunter2").unwrap();
+    let (server_registering, registration_response) =
+      server.register(&registration_request).unwrap();
+    let registration_upload =
+      client_registering.finish(&registration_response).unwrap();
+    let record = server_registering.finish(&registration_upload).unwrap();
+    let serialized_record = record.serialize();
+    let compressed_record = compression::compress(&serialized_record).unwrap();
+    let (client_logging_in, credential_request) =
+      OpaqueClient::login(b"hunter2").unwrap();
+    let (server_logging_in, credential_response) =
+      server.login(record, &credential_request).unwrap();
+    let client_login_state = client_logging_in.serialize().unwrap();
+    let server_login_state = server_logging_in.serialize().unwrap();
+    let sealed_server_login_state = server_logging_in
+      .seal(&LoginStateKey::derive(server_setup.keypair()).unwrap())
+      .unwrap();
+    let credential_finalization = client_logging_in
+      .finish(&credential_response, server_logging_in.ksf())
+      .unwrap()
+      .credential_finalization;
+
+    let server_public_key = server_setup.keypair().public().to_arr();
+    let samples = [
+      ("password_record", serialized_record),
+      (
+        "sealed_server_setup",
+        hex::decode(server_setup.seal()).unwrap(),
+      ),
+      (
+        "keystore",
+        Keystore::with_new_pepper(server_setup.clone())
+          .seal_with_ksf(b"correct horse", KSF_DEFAULT)
+          .unwrap(),
+      ),
+      (
+        "server_key_attestation",
+        attestation::attest_server_key(
+          &attestation::generate_root_key(),
+          &server_public_key,
+        )
+        .unwrap()
+        .to_vec(),
+      ),
+      (
+        "tagged_message",
+        version::tag_message(
+          version::CURRENT_WIRE_VERSION,
+          version::MessageType::CredentialRequest,
+          &credential_request,
+        ),
+      ),
+      (
+        "reregistration_tag",
+        upgrade::client_reregistration_start(
+          &upgrade::UpgradeSession::new(b"session key"),
+          b"hunter2",
+        )
+        .unwrap()
+        .1,
+      ),
+      ("client_login_state", client_login_state),
+      ("server_login_state", server_login_state),
+      ("sealed_server_login_state", sealed_server_login_state),
+      ("compressed", compressed_record),
+      ("registration_request", registration_request),
+      ("registration_response", registration_response),
+      ("registration_upload", registration_upload),
+      ("credential_request", credential_request),
+      ("credential_response", credential_response),
+      ("credential_finalization", credential_finalization),
+    ];
+    for (name, sample) in &samples {
+      let format = format(name);
+      assert!(matches_format(format, sample), "{}", name);
+      let truncated = &sample[..sample.len() - 1];
+      if format.fields.last().unwrap().field_type != FieldType::Rest {
+        assert!(!matches_format(format, truncated), "{}", name);
+      }
+    }
+  }
+}
@@ -0,0 +1,399 @@
+//! The server's static private key behind a trait, so deployments can keep
+//! it in a KMS or an HSM instead of in this process's memory.
+//!
+//! OPAQUE uses the private key for a single operation: in every login start,
+//! 3DH multiplies the client's ephemeral public key by it. Registration and
+//! legacy migration only need the public key. opaque-ke 1.x takes the key
+//! itself, though, with no hook for doing the multiplication elsewhere, so
+//! for a backend that won't hand it out, `login_start` redoes
+//! `ServerLogin::start` (with the default parameters) around the backend's
+//! Diffie-Hellman and parses the result back into opaque-ke's types. Every
+//! length is checked before slicing, so a malformed input is an error rather
+//! than a panic. opaque-ke is pinned to the version this was checked against,
+//! and tests check that both give the same bytes for the same randomness,
+//! and what those bytes are.
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use digest::{generic_array::GenericArray, Digest};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac, NewMac};
+use opaque_ke::{
+  ciphersuite::CipherSuite,
+  errors::{InternalPakeError, PakeError, ProtocolError},
+  group::Group,
+  keypair::{Key, KeyPair},
+  rand::{CryptoRng, RngCore},
+  CredentialRequest, CredentialResponse, ServerLogin,
+  ServerLoginStartParameters, ServerLoginStartResult, ServerRegistration,
+};
+use sha2::Sha512;
+use zeroize::Zeroizing;
+
+use crate::{Cipher, Error};
+
+pub trait ServerKeyBackend: Send + Sync {
+  fn public_key(&self) -> &Key;
+
+  /// `peer_public_key` multiplied by the private key
+  fn diffie_hellman(&self, peer_public_key: &Key) -> Result<Key, Error>;
+
+  /// The private key, for backends that keep it in memory anyway; logins
+  /// then go through opaque-ke directly
+  fn private_key(&self) -> Option<&Key> {
+    None
+  }
+}
+
+impl ServerKeyBackend for KeyPair<RistrettoPoint> {
+  fn public_key(&self) -> &Key {
+    self.public()
+  }
+
+  fn diffie_hellman(&self, peer_public_key: &Key) -> Result<Key, Error> {
+    diffie_hellman(peer_public_key, self.private())
+  }
+
+  fn private_key(&self) -> Option<&Key> {
+    Some(self.private())
+  }
+}
+
+fn diffie_hellman(public_key: &Key, private_key: &Key) -> Result<Key, Error> {
+  if public_key.len() != ELEMENT_LEN || private_key.len() != ELEMENT_LEN {
+    return Err(protocol_error(PakeError::SerializationError));
+  }
+  let point = RistrettoPoint::from_element_slice(&public_key.to_arr())
+    .map_err(protocol_error)?;
+  let shared = point.mult_by_slice(&private_key.to_arr());
+  Key::from_bytes(&shared.to_arr()).map_err(protocol_error)
+}
+
+fn protocol_error(e: impl Into<ProtocolError>) -> Error {
+  Error::Protocol(e.into())
+}
+
+/// Computes `diffie_hellman` for an `ExternalKey`. It is passed the peer's
+/// public key and returns the product, both as 32-byte Ristretto255
+/// encodings; this is where a KMS or PKCS#11 client plugs in. It runs on
+/// the thread doing the login, so it may block.
+pub type DiffieHellmanFn =
+  Box<dyn Fn(&[u8]) -> Result<Vec<u8>, Error> + Send + Sync>;
+
+/// A private key held outside this process
+pub struct ExternalKey {
+  public_key: Key,
+  diffie_hellman: DiffieHellmanFn,
+}
+
+impl ExternalKey {
+  pub fn new(
+    public_key: &[u8],
+    diffie_hellman: DiffieHellmanFn,
+  ) -> Result<Self, Error> {
+    let public_key =
+      Key::from_bytes(public_key).map_err(|_| Error::InvalidServerSetup)?;
+    RistrettoPoint::from_element_slice(&public_key.to_arr())
+      .map_err(|_| Error::InvalidServerSetup)?;
+    Ok(Self {
+      public_key,
+      diffie_hellman,
+    })
+  }
+}
+
+impl ServerKeyBackend for ExternalKey {
+  fn public_key(&self) -> &Key {
+    &self.public_key
+  }
+
+  fn diffie_hellman(&self, peer_public_key: &Key) -> Result<Key, Error> {
+    let shared = (self.diffie_hellman)(peer_public_key)?;
+    Key::from_bytes(&shared).map_err(|_| Error::KeyBackend)
+  }
+}
+
+const ELEMENT_LEN: usize = 32;
+const NONCE_LEN: usize = 32;
+/// `InnerEnvelopeMode::Base`, the mode of envelopes made without custom
+/// identifiers
+const ENVELOPE_MODE_BASE: u8 = 1;
+
+/// `ServerLogin::start` with the default parameters
+pub(crate) fn login_start<R: RngCore + CryptoRng>(
+  rng: &mut R,
+  password_file: ServerRegistration<Cipher>,
+  server_key: &dyn ServerKeyBackend,
+  credential_request: CredentialRequest<Cipher>,
+) -> Result<ServerLoginStartResult<Cipher>, Error> {
+  if let Some(private_key) = server_key.private_key() {
+    return Ok(ServerLogin::start(
+      rng,
+      password_file,
+      private_key,
+      credential_request,
+      ServerLoginStartParameters::default(),
+    )?);
+  }
+
+  // oprf_key | client_s_pk | envelope (whose first byte is its mode)
+  let password_file = password_file.serialize();
+  let (oprf_key, rest) = split(&password_file, ELEMENT_LEN)?;
+  let (client_s_pk, envelope) = split(rest, ELEMENT_LEN)?;
+  if envelope.first() != Some(&ENVELOPE_MODE_BASE) {
+    return Err(protocol_error(
+      InternalPakeError::IncompatibleEnvelopeModeError,
+    ));
+  }
+  let client_s_pk = Key::from_bytes(client_s_pk).map_err(protocol_error)?;
+
+  // alpha | client_nonce | info (u16 length prefix) | client_e_pk
+  let credential_request = credential_request.serialize()?;
+  let (alpha, rest) = split(&credential_request, ELEMENT_LEN)?;
+  let (_client_nonce, rest) = split(rest, NONCE_LEN)?;
+  let (info_len, rest) = split(rest, 2)?;
+  let (info, client_e_pk) = split(
+    rest,
+    u16::from_be_bytes([info_len[0], info_len[1]]) as usize,
+  )?;
+  let info = info.to_vec();
+  let client_e_pk = Key::from_bytes(client_e_pk).map_err(protocol_error)?;
+
+  let alpha =
+    RistrettoPoint::from_element_slice(GenericArray::from_slice(alpha))
+      .map_err(protocol_error)?;
+  let oprf_key =
+    RistrettoPoint::from_scalar_slice(GenericArray::from_slice(oprf_key))
+      .map_err(protocol_error)?;
+  let beta = (alpha * oprf_key).to_arr();
+  let server_s_pk = server_key.public_key();
+  if server_s_pk.len() != ELEMENT_LEN {
+    return Err(Error::KeyBackend);
+  }
+  let response_without_ke = [&beta[..], &server_s_pk[..], envelope].concat();
+
+  let server_e_kp = Cipher::generate_random_keypair(rng);
+  let mut server_nonce = [0; NONCE_LEN];
+  rng.fill_bytes(&mut server_nonce);
+
+  let mut transcript = Sha512::new()
+    .chain(b"3DH")
+    .chain(length_prefixed(&client_s_pk, 2)?)
+    .chain(&credential_request)
+    .chain(length_prefixed(server_s_pk, 2)?)
+    .chain(&response_without_ke)
+    .chain(server_nonce)
+    .chain(&server_e_kp.public()[..]);
+  let ikm = Zeroizing::new(
+    [
+      &diffie_hellman(&client_e_pk, server_e_kp.private())?[..],
+      &server_key.diffie_hellman(&client_e_pk)?[..],
+      &diffie_hellman(&client_s_pk, server_e_kp.private())?[..],
+    ]
+    .concat(),
+  );
+  let keys = derive_3dh_keys(&ikm, &transcript.clone().finalize())?;
+
+  // No info is sent to the client, so its encryption is empty
+  let e_info = length_prefixed(&[], 2)?;
+  transcript.update(&e_info);
+  let mut mac = Hmac::<Sha512>::new_from_slice(&keys.km2)
+    .map_err(|_| protocol_error(InternalPakeError::HmacError))?;
+  mac.update(&transcript.clone().finalize());
+  let mac = mac.finalize().into_bytes();
+  transcript.update(mac);
+
+  let state = Zeroizing::new(
+    [&keys.km3[..], &transcript.finalize(), &keys.session_key].concat(),
+  );
+  let response = [
+    &response_without_ke[..],
+    &server_nonce,
+    &server_e_kp.public()[..],
+    &e_info,
+    &mac,
+  ]
+  .concat();
+  Ok(ServerLoginStartResult {
+    message: CredentialResponse::deserialize(&response)?,
+    state: ServerLogin::deserialize(&state)?,
+    plain_info: info,
+  })
+}
+
+struct TripleDhKeys {
+  session_key: Zeroizing<Vec<u8>>,
+  km2: Zeroizing<Vec<u8>>,
+  km3: Zeroizing<Vec<u8>>,
+}
+
+fn derive_3dh_keys(
+  ikm: &[u8],
+  hashed_transcript: &[u8],
+) -> Result<TripleDhKeys, Error> {
+  let extracted = Hkdf::<Sha512>::new(None, ikm);
+  let handshake_secret =
+    expand_label(&extracted, b"handshake secret", hashed_transcript)?;
+  let session_key =
+    expand_label(&extracted, b"session secret", hashed_transcript)?;
+  let handshake = Hkdf::<Sha512>::from_prk(&handshake_secret)
+    .map_err(|_| protocol_error(InternalPakeError::HkdfError))?;
+  Ok(TripleDhKeys {
+    session_key,
+    km2: expand_label(&handshake, b"server mac", b"")?,
+    km3: expand_label(&handshake, b"client mac", b"")?,
+  })
+}
+
+/// HKDF-Expand-Label from the OPAQUE draft, always to the hash length
+fn expand_label(
+  hkdf: &Hkdf<Sha512>,
+  label: &[u8],
+  context: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+  let mut okm = Zeroizing::new(vec![0; Sha512::output_size()]);
+  let hkdf_label = [
+    &(okm.len() as u16).to_be_bytes()[..],
+    &length_prefixed(&[b"OPAQUE ", label].concat(), 1)?,
+    &length_prefixed(context, 1)?,
+  ]
+  .concat();
+  hkdf
+    .expand(&hkdf_label, &mut okm)
+    .map_err(|_| protocol_error(InternalPakeError::HkdfError))?;
+  Ok(okm)
+}
+
+/// Splits off the first `len` bytes, failing rather than panicking if there
+/// aren't that many
+fn split(bytes: &[u8], len: usize) -> Result<(&[u8], &[u8]), Error> {
+  if bytes.len() < len {
+    return Err(protocol_error(PakeError::SerializationError));
+  }
+  Ok(bytes.split_at(len))
+}
+
+fn length_prefixed(input: &[u8], prefix_len: usize) -> Result<Vec<u8>, Error> {
+  let len = input.len().to_be_bytes();
+  if input.len() >> (8 * prefix_len) != 0 {
+    return Err(protocol_error(PakeError::SerializationError));
+  }
+  Ok([&len[len.len() - prefix_len..], input].concat())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use opaque_ke::{
+    rand::SeedableRng, ClientLogin, ClientLoginFinishParameters,
+    ClientLoginStartParameters, ClientRegistration,
+    ClientRegistrationFinishParameters,
+  };
+  use rand_chacha::ChaCha20Rng;
+
+  fn external(keypair: &KeyPair<RistrettoPoint>) -> ExternalKey {
+    let private_key = keypair.private().clone();
+    ExternalKey::new(
+      keypair.public(),
+      Box::new(move |peer| {
+        let peer = Key::from_bytes(peer).map_err(|_| Error::KeyBackend)?;
+        Ok(diffie_hellman(&peer, &private_key)?.to_vec())
+      }),
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn test_external_key_matches_opaque_ke() {
+    let mut rng = ChaCha20Rng::seed_from_u64(0);
+    let server_keypair = Cipher::generate_random_keypair(&mut rng);
+    let client_start_result =
+      ClientRegistration::<Cipher>::start(&mut rng, b"hunter2").unwrap();
+    let server_start_result = ServerRegistration::<Cipher>::start(
+      &mut rng,
+      client_start_result.message,
+      server_keypair.public(),
+    )
+    .unwrap();
+    let client_finish_result = client_start_result
+      .state
+      .finish(
+        &mut rng,
+        server_start_result.message,
+        ClientRegistrationFinishParameters::default(),
+      )
+      .unwrap();
+    let password_file = server_start_result
+      .state
+      .finish(client_finish_result.message)
+      .unwrap()
+      .serialize();
+    let client_login_start = ClientLogin::<Cipher>::start(
+      &mut rng,
+      b"hunter2",
+      ClientLoginStartParameters::WithInfo(b"info".to_vec()),
+    )
+    .unwrap();
+    let credential_request = client_login_start.message.serialize().unwrap();
+
+    let start = |server_key: &dyn ServerKeyBackend| {
+      login_start(
+        &mut ChaCha20Rng::seed_from_u64(1),
+        ServerRegistration::deserialize(&password_file).unwrap(),
+        server_key,
+        CredentialRequest::deserialize(&credential_request).unwrap(),
+      )
+      .unwrap()
+    };
+    let in_memory = start(&server_keypair);
+    let external = start(&external(&server_keypair));
+    assert_eq!(
+      external.message.serialize().unwrap(),
+      in_memory.message.serialize().unwrap()
+    );
+    assert_eq!(
+      external.state.serialize().unwrap(),
+      in_memory.state.serialize().unwrap()
+    );
+    assert_eq!(external.plain_info, b"info");
+    // Pinned, so a change to opaque-ke's 3DH fails here even though the
+    // comparison above would still pass if it changed the same way
+    let digest = Sha512::new()
+      .chain(external.message.serialize().unwrap())
+      .chain(external.state.serialize().unwrap())
+      .finalize();
+    assert_eq!(
+      hex::encode(digest),
+      "907c39e8ccac86f752c69ab017eb564d2965409798b8ef3fdf877b9ca9502d3d\
+       31ef608065baf3bab39c0db61b14fc8a57f39ea84d165b1f527e666124fcac94"
+    );
+
+    let client_finish_result = client_login_start
+      .state
+      .finish(external.message, ClientLoginFinishParameters::default())
+      .unwrap();
+    let server_finish_result =
+      external.state.finish(client_finish_result.message).unwrap();
+    assert_eq!(
+      client_finish_result.session_key,
+      server_finish_result.session_key
+    );
+  }
+
+  #[test]
+  fn test_malformed_backend_output_is_an_error() {
+    let mut rng = ChaCha20Rng::seed_from_u64(0);
+    let server_keypair = Cipher::generate_random_keypair(&mut rng);
+    let truncating = ExternalKey::new(
+      server_keypair.public(),
+      Box::new(|peer| Ok(peer[..31].to_vec())),
+    )
+    .unwrap();
+    let peer = Cipher::generate_random_keypair(&mut rng);
+    assert!(matches!(
+      truncating.diffie_hellman(peer.public()),
+      Err(Error::KeyBackend)
+    ));
+    assert!(split(&[0; 4], 5).is_err());
+    assert_eq!(split(&[0; 4], 4).unwrap().1, &[] as &[u8]);
+  }
+}
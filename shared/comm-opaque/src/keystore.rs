@@ -0,0 +1,245 @@
+//! Passphrase-protected keystore file for the server's secrets: the OPAQUE
+//! server keypair and a pepper, a random secret the server can mix into
+//! anything it stores alongside user records.
+//!
+//! File format, version 1:
+//!
+//! ```text
+//! "cOKS" | format (u8) | ksf (u8) | salt (16) | nonce (24) | ciphertext
+//! ```
+//!
+//! The key is Argon2id of the passphrase with the KSF parameter set `ksf`
+//! (see `ksf::ksf_params`) and the salt. It encrypts the private key and
+//! pepper with XChaCha20-Poly1305, with everything before the ciphertext as
+//! associated data, so a header can't be edited (to weaker Argon2
+//! parameters, say) without the file failing to open. Salt and nonce are
+//! drawn fresh every time a file is written.
+
+use chacha20poly1305::{
+  aead::{Aead, KeyInit, Payload},
+  XChaCha20Poly1305, XNonce,
+};
+use opaque_ke::rand::RngCore;
+use zeroize::Zeroizing;
+
+use crate::{
+  fips::{self, Primitive},
+  ksf::{self, ksf_params, KsfParams, KSF_LARGE},
+  rng::CommRng,
+  serialization::{Decoder, Encoder},
+  server_setup::ServerSetup,
+  Error,
+};
+
+pub const PEPPER_LEN: usize = 32;
+/// Argon2 parameter set new keystore files are written with: 64 MiB,
+/// 3 passes. Opening a file only happens at startup, so it can afford more
+/// than a login.
+pub const KEYSTORE_KSF: u8 = KSF_LARGE;
+
+pub(crate) const KEYSTORE_MAGIC: &[u8; 4] = b"cOKS";
+pub(crate) const KEYSTORE_FORMAT: u8 = 1;
+pub(crate) const SALT_LEN: usize = 16;
+pub(crate) const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = KEYSTORE_MAGIC.len() + 2 + SALT_LEN + NONCE_LEN;
+const KEY_LEN: usize = 32;
+
+#[derive(Clone)]
+pub struct Keystore {
+  server_setup: ServerSetup,
+  pepper: Zeroizing<[u8; PEPPER_LEN]>,
+}
+
+impl Keystore {
+  /// A new server keypair and pepper
+  pub fn generate() -> Self {
+    Self::with_new_pepper(ServerSetup::generate())
+  }
+
+  pub fn with_new_pepper(server_setup: ServerSetup) -> Self {
+    let mut pepper = [0; PEPPER_LEN];
+    CommRng.fill_bytes(&mut pepper);
+    Self::new(server_setup, pepper)
+  }
+
+  pub fn new(server_setup: ServerSetup, pepper: [u8; PEPPER_LEN]) -> Self {
+    Self {
+      server_setup,
+      pepper: Zeroizing::new(pepper),
+    }
+  }
+
+  pub fn server_setup(&self) -> &ServerSetup {
+    &self.server_setup
+  }
+
+  pub fn pepper(&self) -> &[u8; PEPPER_LEN] {
+    &self.pepper
+  }
+
+  /// Encrypts the keystore under `passphrase`, returning the file contents
+  pub fn seal(&self, passphrase: &[u8]) -> Result<Vec<u8>, Error> {
+    self.seal_with_ksf(passphrase, KEYSTORE_KSF)
+  }
+
+  pub(crate) fn seal_with_ksf(
+    &self,
+    passphrase: &[u8],
+    ksf: u8,
+  ) -> Result<Vec<u8>, Error> {
+    fips::require_approved(Primitive::Keystore)?;
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    CommRng.fill_bytes(&mut salt);
+    CommRng.fill_bytes(&mut nonce);
+    let header = Encoder::new()
+      .fixed(KEYSTORE_MAGIC)
+      .u8(KEYSTORE_FORMAT)
+      .u8(ksf)
+      .fixed(&salt)
+      .fixed(&nonce)
+      .finish();
+    let plaintext = Zeroizing::new(
+      Encoder::new()
+        .bytes(&self.server_setup.keypair().private().to_arr())
+        .bytes(&self.pepper[..])
+        .finish(),
+    );
+    let key = derive_key(passphrase, ksf_params(ksf)?, &salt)?;
+    let ciphertext = XChaCha20Poly1305::new(key.as_ref().into())
+      .encrypt(
+        XNonce::from_slice(&nonce),
+        Payload {
+          msg: &plaintext,
+          aad: &header,
+        },
+      )
+      .map_err(|_| Error::InvalidKeystore)?;
+    Ok([header, ciphertext].concat())
+  }
+
+  /// Fails with `Error::KeystoreDecryption` if the passphrase is wrong or
+  /// the file was modified
+  pub fn open(file: &[u8], passphrase: &[u8]) -> Result<Self, Error> {
+    fips::require_approved(Primitive::Keystore)?;
+    if file.len() < HEADER_LEN {
+      return Err(Error::InvalidKeystore);
+    }
+    let (header, ciphertext) = file.split_at(HEADER_LEN);
+    let mut decoder = Decoder::new(header);
+    if decoder.fixed(KEYSTORE_MAGIC.len())? != KEYSTORE_MAGIC
+      || decoder.u8()? != KEYSTORE_FORMAT
+    {
+      return Err(Error::InvalidKeystore);
+    }
+    let ksf = decoder.u8()?;
+    let salt = decoder.fixed(SALT_LEN)?;
+    let nonce = decoder.fixed(NONCE_LEN)?;
+    decoder.finish()?;
+    let key = derive_key(passphrase, ksf_params(ksf)?, salt)?;
+    let plaintext = Zeroizing::new(
+      XChaCha20Poly1305::new(key.as_ref().into())
+        .decrypt(
+          XNonce::from_slice(nonce),
+          Payload {
+            msg: ciphertext,
+            aad: header,
+          },
+        )
+        .map_err(|_| Error::KeystoreDecryption)?,
+    );
+    let mut decoder = Decoder::new(&plaintext);
+    let server_setup = ServerSetup::from_private_key(decoder.bytes()?)?;
+    let pepper = decoder
+      .bytes()?
+      .try_into()
+      .map_err(|_| Error::InvalidKeystore)?;
+    decoder.finish()?;
+    Ok(Self::new(server_setup, pepper))
+  }
+}
+
+/// Re-encrypts a keystore file under `new_passphrase`, with a fresh salt
+/// and nonce and the current `KEYSTORE_KSF`. The secrets themselves don't
+/// change: records registered against the server key stay valid.
+pub fn rotate_passphrase(
+  file: &[u8],
+  old_passphrase: &[u8],
+  new_passphrase: &[u8],
+) -> Result<Vec<u8>, Error> {
+  rotate_with_ksf(file, old_passphrase, new_passphrase, KEYSTORE_KSF)
+}
+
+fn rotate_with_ksf(
+  file: &[u8],
+  old_passphrase: &[u8],
+  new_passphrase: &[u8],
+  ksf: u8,
+) -> Result<Vec<u8>, Error> {
+  Keystore::open(file, old_passphrase)?.seal_with_ksf(new_passphrase, ksf)
+}
+
+fn derive_key(
+  passphrase: &[u8],
+  params: KsfParams,
+  salt: &[u8],
+) -> Result<Zeroizing<[u8; KEY_LEN]>, Error> {
+  let mut key = Zeroizing::new([0; KEY_LEN]);
+  ksf::argon2id(params, passphrase, salt, key.as_mut())
+    .map_err(|_| Error::InvalidKeystore)?;
+  Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ksf::KSF_DEFAULT;
+
+  #[test]
+  fn test_seal_open_and_rotate() {
+    let keystore = Keystore::generate();
+    let file = keystore
+      .seal_with_ksf(b"correct horse", KSF_DEFAULT)
+      .unwrap();
+    let opened = Keystore::open(&file, b"correct horse").unwrap();
+    assert_eq!(
+      opened.server_setup().fingerprint(),
+      keystore.server_setup().fingerprint()
+    );
+    assert_eq!(opened.pepper(), keystore.pepper());
+    assert!(matches!(
+      Keystore::open(&file, b"battery staple"),
+      Err(Error::KeystoreDecryption)
+    ));
+
+    let rotated =
+      rotate_with_ksf(&file, b"correct horse", b"battery staple", KSF_DEFAULT)
+        .unwrap();
+    assert_eq!(
+      Keystore::open(&rotated, b"battery staple")
+        .unwrap()
+        .pepper(),
+      keystore.pepper()
+    );
+    assert!(Keystore::open(&rotated, b"correct horse").is_err());
+  }
+
+  #[test]
+  fn test_tampered_file_rejected() {
+    let file = Keystore::generate()
+      .seal_with_ksf(b"correct horse", KSF_DEFAULT)
+      .unwrap();
+    // Flip a bit in the salt, in the ciphertext, and truncate
+    let mut tampered_salt = file.clone();
+    tampered_salt[7] ^= 1;
+    let mut tampered_ciphertext = file.clone();
+    *tampered_ciphertext.last_mut().unwrap() ^= 1;
+    for tampered in [tampered_salt, tampered_ciphertext] {
+      assert!(matches!(
+        Keystore::open(&tampered, b"correct horse"),
+        Err(Error::KeystoreDecryption)
+      ));
+    }
+    assert!(Keystore::open(&file[..10], b"correct horse").is_err());
+  }
+}
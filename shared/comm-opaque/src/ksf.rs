@@ -0,0 +1,254 @@
+//! Argon2 parameter sets for the key stretching function, and the choice of
+//! which one new registrations on this host use.
+//!
+//! Each parameter set has an id, which is the `ksf` half of a record's
+//! `SuiteVersion`. Logging in has to run the KSF with the same parameters
+//! registration used, so a record's id never changes once it is written.
+//!
+//! `init_ksf` picks the set registrations and re-registrations run from then
+//! on (`KSF_DEFAULT`, the parameters `Cipher` has always used, until it is
+//! called). The client runs the KSF with it, and the server stamps the
+//! records it finishes with its own choice, so a client and the server it
+//! registers with have to pick the same set; records already stored keep
+//! theirs.
+//!
+//! Every Argon2 hash runs in its thread's arena, a buffer kept for the life
+//! of the thread instead of allocated for each hash, so a login-heavy pool
+//! doesn't keep freeing and reallocating blocks of several MiB.
+
+use std::{
+  cell::{Cell, RefCell},
+  fs,
+  sync::RwLock,
+  thread,
+};
+
+use argon2::{Algorithm, Argon2, Block, Params, Version};
+use zeroize::Zeroize;
+
+use crate::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KsfParams {
+  pub memory_kib: u32,
+  pub iterations: u32,
+  pub parallelism: u32,
+}
+
+/// Argon2id defaults: 4 MiB, 3 passes
+pub const KSF_DEFAULT: u8 = 1;
+/// 19 MiB, 2 passes
+pub const KSF_STANDARD: u8 = 2;
+/// 64 MiB, 3 passes
+pub const KSF_LARGE: u8 = 3;
+
+/// Lanes are computed one after the other, so parallelism is left at 1 and
+/// the cost of each set is set by memory and passes alone.
+pub fn ksf_params(ksf: u8) -> Result<KsfParams, Error> {
+  let (memory_kib, iterations) = match ksf {
+    KSF_DEFAULT => (4096, 3),
+    KSF_STANDARD => (19456, 2),
+    KSF_LARGE => (65536, 3),
+    _ => return Err(Error::UnsupportedKsf(ksf)),
+  };
+  Ok(KsfParams {
+    memory_kib,
+    iterations,
+    parallelism: 1,
+  })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlatformTier {
+  /// Under 1 GiB of memory or a single core
+  Constrained,
+  Standard,
+  /// At least 8 GiB of memory and 4 cores
+  Large,
+}
+
+impl PlatformTier {
+  pub fn ksf(self) -> u8 {
+    match self {
+      PlatformTier::Constrained => KSF_DEFAULT,
+      PlatformTier::Standard => KSF_STANDARD,
+      PlatformTier::Large => KSF_LARGE,
+    }
+  }
+
+  fn classify(memory_bytes: Option<u64>, cores: usize) -> Self {
+    const GIB: u64 = 1 << 30;
+    match memory_bytes {
+      _ if cores < 2 => PlatformTier::Constrained,
+      Some(memory) if memory < GIB => PlatformTier::Constrained,
+      Some(memory) if memory >= 8 * GIB && cores >= 4 => PlatformTier::Large,
+      _ => PlatformTier::Standard,
+    }
+  }
+}
+
+/// Physical memory, or the container's memory limit if that is lower.
+/// `None` where neither can be read (anywhere other than Linux).
+fn available_memory() -> Option<u64> {
+  let read_number = |path: &str| -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+  };
+  let total = fs::read_to_string("/proc/meminfo")
+    .ok()
+    .and_then(|meminfo| {
+      let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+      let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+      Some(kib * 1024)
+    });
+  // cgroup v2, then v1. "max" (no limit) doesn't parse and is skipped.
+  let limit = read_number("/sys/fs/cgroup/memory.max")
+    .or_else(|| read_number("/sys/fs/cgroup/memory/memory.limit_in_bytes"));
+  match (total, limit) {
+    (Some(total), Some(limit)) => Some(total.min(limit)),
+    (total, limit) => total.or(limit),
+  }
+}
+
+pub fn detect_platform_tier() -> PlatformTier {
+  let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+  PlatformTier::classify(available_memory(), cores)
+}
+
+static REGISTRATION_KSF: RwLock<u8> = RwLock::new(KSF_DEFAULT);
+
+/// Picks the parameter set for new registrations: `override_ksf` if given,
+/// otherwise the one for the detected platform tier. Returns the chosen id.
+pub fn init_ksf(override_ksf: Option<u8>) -> Result<u8, Error> {
+  let ksf = match override_ksf {
+    Some(ksf) => ksf,
+    None => detect_platform_tier().ksf(),
+  };
+  ksf_params(ksf)?;
+  *REGISTRATION_KSF.write().unwrap_or_else(|e| e.into_inner()) = ksf;
+  Ok(ksf)
+}
+
+pub fn registration_ksf() -> u8 {
+  *REGISTRATION_KSF.read().unwrap_or_else(|e| e.into_inner())
+}
+
+thread_local! {
+  static ACTIVE_KSF: Cell<u8> = const { Cell::new(KSF_DEFAULT) };
+}
+
+/// Runs `f` with every KSF evaluation on this thread using parameter set
+/// `ksf`. opaque-ke calls the KSF without any context, so this is how the
+/// parameters reach it.
+pub fn with_ksf<T>(ksf: u8, f: impl FnOnce() -> T) -> Result<T, Error> {
+  ksf_params(ksf)?;
+  let previous = ACTIVE_KSF.with(|active| active.replace(ksf));
+  struct Restore(u8);
+  impl Drop for Restore {
+    fn drop(&mut self) {
+      ACTIVE_KSF.with(|active| active.set(self.0));
+    }
+  }
+  let _restore = Restore(previous);
+  Ok(f())
+}
+
+pub(crate) fn active_params() -> KsfParams {
+  ksf_params(ACTIVE_KSF.with(Cell::get))
+    .expect("with_ksf only activates known parameter sets")
+}
+
+thread_local! {
+  static ARENA: RefCell<Vec<Block>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Argon2id of `password` with `params` into `output`, in this thread's
+/// arena. The arena grows to the largest parameter set the thread has used
+/// and is zeroed after every hash, so no password-derived memory outlives
+/// the call.
+pub(crate) fn argon2id(
+  params: KsfParams,
+  password: &[u8],
+  salt: &[u8],
+  output: &mut [u8],
+) -> Result<(), argon2::Error> {
+  let argon2 = Argon2::new(
+    Algorithm::Argon2id,
+    Version::V0x13,
+    Params::new(
+      params.memory_kib,
+      params.iterations,
+      params.parallelism,
+      None,
+    )?,
+  );
+  let block_count = argon2.params().block_count();
+  ARENA.with(|arena| {
+    let mut arena = arena.borrow_mut();
+    if arena.len() < block_count {
+      arena.resize(block_count, Block::default());
+    }
+    let memory = &mut arena[..block_count];
+    let result = argon2.hash_password_into_with_memory(
+      password,
+      salt,
+      output,
+      &mut *memory,
+    );
+    memory.iter_mut().for_each(Zeroize::zeroize);
+    result
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_classify() {
+    const GIB: u64 = 1 << 30;
+    let classify = PlatformTier::classify;
+    assert_eq!(classify(Some(512 << 20), 8), PlatformTier::Constrained);
+    assert_eq!(classify(Some(16 * GIB), 1), PlatformTier::Constrained);
+    assert_eq!(classify(Some(2 * GIB), 2), PlatformTier::Standard);
+    assert_eq!(classify(None, 8), PlatformTier::Standard);
+    assert_eq!(classify(Some(16 * GIB), 8), PlatformTier::Large);
+  }
+
+  #[test]
+  fn test_with_ksf_restores_previous() {
+    assert_eq!(active_params(), ksf_params(KSF_DEFAULT).unwrap());
+    with_ksf(KSF_LARGE, || {
+      assert_eq!(active_params(), ksf_params(KSF_LARGE).unwrap());
+    })
+    .unwrap();
+    assert_eq!(active_params(), ksf_params(KSF_DEFAULT).unwrap());
+    assert!(matches!(with_ksf(0, || ()), Err(Error::UnsupportedKsf(0))));
+  }
+
+  #[test]
+  fn test_argon2id_arena() {
+    let params = ksf_params(KSF_DEFAULT).unwrap();
+    let expected = {
+      let mut output = [0; 64];
+      Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(params.memory_kib, params.iterations, 1, None).unwrap(),
+      )
+      .hash_password_into(b"hunter2", &[0; 8], &mut output)
+      .unwrap();
+      output
+    };
+    for _ in 0..2 {
+      let mut output = [0; 64];
+      argon2id(params, b"hunter2", &[0; 8], &mut output).unwrap();
+      assert_eq!(output, expected);
+    }
+    ARENA.with(|arena| {
+      let arena = arena.borrow();
+      assert_eq!(arena.len(), params.memory_kib as usize);
+      let words = Block::SIZE / 8;
+      assert!(arena.iter().all(|block| (0..words).all(|i| block[i] == 0)));
+    });
+  }
+}
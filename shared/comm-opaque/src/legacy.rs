@@ -0,0 +1,233 @@
+use std::time::Instant;
+
+use opaque_ke::{
+  ClientRegistration, ClientRegistrationFinishParameters, ServerRegistration,
+};
+
+use crate::{
+  fips::{self, Primitive},
+  key_backend::ServerKeyBackend,
+  ksf::{registration_ksf, with_ksf},
+  metrics::{self, Operation},
+  policy::version_policy,
+  record::{PasswordRecord, SuiteVersion, CURRENT_SUITE_VERSION},
+  rng::CommRng,
+  Cipher, Error,
+};
+
+/// What went wrong in bcrypt, without the parts of the hash its own errors
+/// quote
+#[derive(Clone, Copy, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum BcryptFailure {
+  #[display(fmt = "malformed legacy hash")]
+  MalformedHash,
+  #[display(fmt = "bcrypt cost out of range")]
+  CostNotAllowed,
+  #[display(fmt = "bcrypt failed")]
+  Internal,
+}
+
+impl From<bcrypt::BcryptError> for BcryptFailure {
+  fn from(e: bcrypt::BcryptError) -> Self {
+    use bcrypt::BcryptError::*;
+    match e {
+      InvalidCost(_) | InvalidPrefix(_) | InvalidHash(_)
+      | InvalidSaltLen(_) | InvalidBase64(_) => BcryptFailure::MalformedHash,
+      CostNotAllowed(_) => BcryptFailure::CostNotAllowed,
+      Io(_) | Rand(_) => BcryptFailure::Internal,
+    }
+  }
+}
+
+/// Checks a password against a legacy bcrypt hash (`$2a$`, `$2b$` or `$2y$`
+/// as produced by twin-bcrypt on the keyserver).
+pub fn verify_legacy_password(
+  legacy_hash: &str,
+  password: &str,
+) -> Result<bool, Error> {
+  fips::require_approved(Primitive::Bcrypt)?;
+  metrics::time(Operation::LegacyVerify, || {
+    Ok(bcrypt::verify(password, legacy_hash)?)
+  })
+}
+
+/// Verifies a password against a legacy bcrypt hash and, if it matches,
+/// registers an OPAQUE credential for the same password on the user's behalf.
+///
+/// The returned record can be stored in place of the bcrypt hash; the client
+/// will be able to log in with OPAQUE from then on without noticing the
+/// migration. `Ok(None)` is returned if the password doesn't match. The
+/// record uses the KSF parameter set chosen by `ksf::init_ksf`.
+///
+/// Since both halves of the registration run here, the client's static
+/// keypair briefly exists in server memory. It is dropped (and zeroized by
+/// opaque-ke) before this function returns.
+///
+/// The attempt is recorded as `Operation::LegacyMigration`, and only counts
+/// as a success if a record was produced.
+pub fn migrate_legacy_password(
+  legacy_hash: &str,
+  password: &str,
+  server_key: &dyn ServerKeyBackend,
+) -> Result<Option<PasswordRecord>, Error> {
+  let start = Instant::now();
+  let result = migrate(legacy_hash, password, server_key);
+  metrics::record(
+    Operation::LegacyMigration,
+    start.elapsed(),
+    matches!(result, Ok(Some(_))),
+  );
+  result
+}
+
+/// `migrate_legacy_password` without the metrics, for callers that record
+/// the attempt as part of their own operation
+pub(crate) fn migrate(
+  legacy_hash: &str,
+  password: &str,
+  server_key: &dyn ServerKeyBackend,
+) -> Result<Option<PasswordRecord>, Error> {
+  fips::require_approved(Primitive::Ristretto255Suite)?;
+  fips::require_approved(Primitive::Bcrypt)?;
+  migrate_with_ksf(legacy_hash, password, server_key, registration_ksf())
+}
+
+fn migrate_with_ksf(
+  legacy_hash: &str,
+  password: &str,
+  server_key: &dyn ServerKeyBackend,
+  ksf: u8,
+) -> Result<Option<PasswordRecord>, Error> {
+  version_policy().check_suite_version(SuiteVersion {
+    ksf,
+    ..CURRENT_SUITE_VERSION
+  })?;
+  if !bcrypt::verify(password, legacy_hash)? {
+    return Ok(None);
+  }
+  let password_file =
+    with_ksf(ksf, || register_locally(password, server_key))??;
+  Ok(Some(PasswordRecord::with_ksf(password_file, ksf)))
+}
+
+fn register_locally(
+  password: &str,
+  server_key: &dyn ServerKeyBackend,
+) -> Result<ServerRegistration<Cipher>, Error> {
+  let mut rng = CommRng;
+  let client_start_result =
+    ClientRegistration::<Cipher>::start(&mut rng, password.as_bytes())?;
+  let server_start_result = ServerRegistration::<Cipher>::start(
+    &mut rng,
+    client_start_result.message,
+    server_key.public_key(),
+  )?;
+  let client_finish_result = client_start_result.state.finish(
+    &mut rng,
+    server_start_result.message,
+    ClientRegistrationFinishParameters::default(),
+  )?;
+  let password_file = server_start_result
+    .state
+    .finish(client_finish_result.message)?;
+  Ok(password_file)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ksf::{KSF_DEFAULT, KSF_STANDARD};
+  use opaque_ke::{
+    ciphersuite::CipherSuite, rand::rngs::OsRng, ClientLogin,
+    ClientLoginFinishParameters, ClientLoginStartParameters, ServerLogin,
+    ServerLoginStartParameters,
+  };
+
+  const PASSWORD: &str = "hunter2";
+
+  fn migrate_then_login(ksf: u8) -> u8 {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let legacy_hash = bcrypt::hash(PASSWORD, 4).unwrap();
+    let record = migrate_with_ksf(&legacy_hash, PASSWORD, &server_keypair, ksf)
+      .unwrap()
+      .expect("password should match legacy hash");
+
+    let client_start_result = ClientLogin::<Cipher>::start(
+      &mut OsRng,
+      PASSWORD.as_bytes(),
+      ClientLoginStartParameters::default(),
+    )
+    .unwrap();
+    let server_start_result = ServerLogin::start(
+      &mut OsRng,
+      record.password_file,
+      server_keypair.private(),
+      client_start_result.message,
+      ServerLoginStartParameters::default(),
+    )
+    .unwrap();
+    let client_finish_result = with_ksf(record.suite_version.ksf, || {
+      client_start_result.state.finish(
+        server_start_result.message,
+        ClientLoginFinishParameters::default(),
+      )
+    })
+    .unwrap()
+    .unwrap();
+    let server_finish_result = server_start_result
+      .state
+      .finish(client_finish_result.message)
+      .unwrap();
+    assert_eq!(
+      client_finish_result.session_key,
+      server_finish_result.session_key
+    );
+    record.suite_version.ksf
+  }
+
+  #[test]
+  fn test_migrated_credential_supports_opaque_login() {
+    assert_eq!(migrate_then_login(KSF_DEFAULT), KSF_DEFAULT);
+  }
+
+  #[test]
+  fn test_migration_records_ksf() {
+    assert_eq!(migrate_then_login(KSF_STANDARD), KSF_STANDARD);
+  }
+
+  #[test]
+  fn test_wrong_password_is_not_migrated() {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let legacy_hash = bcrypt::hash(PASSWORD, 4).unwrap();
+    let failures =
+      || metrics::snapshot()[Operation::LegacyMigration as usize].failures;
+    let failures_before = failures();
+    let result =
+      migrate_legacy_password(&legacy_hash, "hunter3", &server_keypair)
+        .unwrap();
+    assert!(result.is_none());
+    assert!(failures() > failures_before);
+  }
+
+  #[test]
+  fn test_malformed_legacy_hash_is_an_error() {
+    assert!(verify_legacy_password("not a hash", PASSWORD).is_err());
+  }
+
+  #[test]
+  fn test_errors_dont_quote_the_hash() {
+    let legacy_hash = bcrypt::hash(PASSWORD, 4).unwrap();
+    for damaged in [
+      format!("{}!", legacy_hash),
+      legacy_hash.replacen("$2b$", "$9x$", 1),
+      legacy_hash.replacen("$04$", "$zz$", 1),
+    ] {
+      let e = verify_legacy_password(&damaged, PASSWORD).unwrap_err();
+      let salt = &legacy_hash[7..29];
+      for message in [e.to_string(), format!("{:?}", e)] {
+        assert!(!message.contains(salt), "{}", message);
+        assert!(!message.contains(PASSWORD), "{}", message);
+      }
+    }
+  }
+}
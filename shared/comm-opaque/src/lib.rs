@@ -1,2 +1,39 @@
+//! Comm's OPAQUE protocol logic, shared by the keyserver's Node addon
+//! (`keyserver/addons/rust-node-addon`), the identity service and the native
+//! library. Nothing here depends on a binding: the addon only converts
+//! arguments and errors, so the protocol is tested by `cargo test` in this
+//! crate, and Rust callers can use `OpaqueClient` and `OpaqueServer`
+//! directly.
+
+pub mod api;
+pub mod attestation;
+pub mod client;
+pub mod compression;
+pub mod conformance;
+pub mod derivation;
+mod error;
+pub mod events;
+pub mod fips;
+pub mod formats;
+pub mod key_backend;
+pub mod keystore;
+pub mod ksf;
+pub mod legacy;
+pub mod lockout;
+pub mod metrics;
 mod opaque;
+mod p256_group;
+pub mod policy;
+pub mod record;
+pub mod replay;
+pub mod rng;
+pub mod rotation;
+pub mod serialization;
+pub mod server_setup;
+pub mod sizes;
+pub mod transition;
+pub mod upgrade;
+pub mod version;
+pub use crate::api::{OpaqueClient, OpaqueServer};
+pub use crate::error::{Error, ErrorCategory, ErrorInfo, ERROR_CATALOG};
 pub use crate::opaque::Cipher;
@@ -0,0 +1,367 @@
+//! Throttling of repeated failed logins for the same credential identifier
+//! (a username, usually). The first `free_attempts` failures cost nothing;
+//! after that each failure locks the identifier for twice as long as the
+//! last, up to `max_delay`, plus up to a quarter again of random jitter so
+//! clients retrying on a timer don't all come back at once. A successful
+//! login clears the identifier.
+//!
+//! Identifiers are tracked per tenant (the community whose server setup the
+//! login used), so the same username on two tenants is two users, and one
+//! tenant's failed logins never lock out another's.
+//!
+//! An OPAQUE client learns whether its guess was right from the credential
+//! response alone, before it sends anything else, so a login counts as a
+//! failure as soon as it starts (`record_login_start`) and only a successful
+//! finish clears it. A client that starts logins and never finishes them is
+//! throttled like one that fails them. Legacy logins, which check the
+//! password in a single step, go through `record_outcome` instead.
+//!
+//! Every server login path goes through `check` before doing any work, so
+//! lockouts are counted the same way whichever binding is used. While the
+//! policy `locks`, bindings refuse logins that don't name the identifier,
+//! since those couldn't be throttled.
+
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  sync::{Mutex, RwLock},
+  time::{Duration, Instant},
+};
+
+use opaque_ke::{
+  errors::{PakeError, ProtocolError},
+  rand::RngCore,
+};
+
+use crate::{
+  events::{self, SecurityEvent},
+  rng::CommRng,
+  Error,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockoutPolicy {
+  pub free_attempts: u32,
+  pub base_delay: Duration,
+  pub max_delay: Duration,
+}
+
+impl LockoutPolicy {
+  pub const DEFAULT: Self = Self {
+    free_attempts: 5,
+    base_delay: Duration::from_secs(1),
+    max_delay: Duration::from_secs(15 * 60),
+  };
+
+  /// Whether this policy ever locks an identifier. One with a zero delay
+  /// doesn't, which is how lockout is turned off.
+  pub fn locks(&self) -> bool {
+    !self.base_delay.is_zero() && !self.max_delay.is_zero()
+  }
+
+  /// Lock duration after `failures` consecutive failures, before jitter
+  fn delay(&self, failures: u32) -> Duration {
+    if failures <= self.free_attempts {
+      return Duration::ZERO;
+    }
+    let doublings = failures - self.free_attempts - 1;
+    self
+      .base_delay
+      .checked_mul(1 << doublings.min(31))
+      .unwrap_or(Duration::MAX)
+      .min(self.max_delay)
+  }
+}
+
+impl Default for LockoutPolicy {
+  fn default() -> Self {
+    Self::DEFAULT
+  }
+}
+
+/// Past this many identifiers, the ones that can be forgotten soonest are
+/// forgotten first. Identifiers that are still locked are never forgotten,
+/// so this is exceeded if every tracked identifier is locked.
+const MAX_TRACKED: usize = 100_000;
+
+struct Entry {
+  failures: u32,
+  last_failure: Instant,
+  locked_until: Instant,
+}
+
+impl Entry {
+  /// When the entry can be forgotten: when its lock expires, or at its last
+  /// failure if it isn't locked
+  fn expiry(&self) -> Instant {
+    self.last_failure.max(self.locked_until)
+  }
+}
+
+/// A tenant and a credential identifier on it
+type Key = (String, String);
+
+fn key(tenant: &str, identifier: &str) -> Key {
+  (tenant.to_string(), identifier.to_string())
+}
+
+struct LockoutTracker {
+  capacity: usize,
+  entries: BTreeMap<Key, Entry>,
+  /// Every tracked identifier, by its entry's expiry
+  by_expiry: BTreeSet<(Instant, Key)>,
+}
+
+impl LockoutTracker {
+  const fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      entries: BTreeMap::new(),
+      by_expiry: BTreeSet::new(),
+    }
+  }
+
+  fn retry_after(&self, key: &Key, now: Instant) -> Option<Duration> {
+    let entry = self.entries.get(key)?;
+    let remaining = entry.locked_until.saturating_duration_since(now);
+    (!remaining.is_zero()).then_some(remaining)
+  }
+
+  fn record_failure(
+    &mut self,
+    key: Key,
+    policy: &LockoutPolicy,
+    now: Instant,
+  ) -> Option<Duration> {
+    let mut entry = match self.remove(&key) {
+      Some(entry) => entry,
+      None => {
+        self.make_room(now);
+        Entry {
+          failures: 0,
+          last_failure: now,
+          locked_until: now,
+        }
+      }
+    };
+    entry.failures = entry.failures.saturating_add(1);
+    entry.last_failure = now;
+    let delay = policy.delay(entry.failures);
+    let locked_for = (!delay.is_zero()).then(|| {
+      let jitter_range = delay.as_millis() as u64 / 4 + 1;
+      let jitter = Duration::from_millis(CommRng.next_u64() % jitter_range);
+      entry.locked_until = now + delay + jitter;
+      delay + jitter
+    });
+    self.by_expiry.insert((entry.expiry(), key.clone()));
+    self.entries.insert(key, entry);
+    locked_for
+  }
+
+  fn remove(&mut self, key: &Key) -> Option<Entry> {
+    let entry = self.entries.remove(key)?;
+    self.by_expiry.remove(&(entry.expiry(), key.clone()));
+    Some(entry)
+  }
+
+  /// Forgets expired entries, soonest expired first, until there is room
+  /// for one more
+  fn make_room(&mut self, now: Instant) {
+    while self.entries.len() >= self.capacity {
+      match self.by_expiry.first() {
+        Some((expiry, _)) if *expiry <= now => (),
+        _ => return,
+      }
+      if let Some((_, key)) = self.by_expiry.pop_first() {
+        self.entries.remove(&key);
+      }
+    }
+  }
+}
+
+static LOCKOUT_POLICY: RwLock<LockoutPolicy> =
+  RwLock::new(LockoutPolicy::DEFAULT);
+static TRACKER: Mutex<LockoutTracker> =
+  Mutex::new(LockoutTracker::new(MAX_TRACKED));
+
+pub fn set_lockout_policy(policy: LockoutPolicy) {
+  *LOCKOUT_POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+pub fn lockout_policy() -> LockoutPolicy {
+  *LOCKOUT_POLICY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// How much longer `identifier` is locked for on `tenant`, if it is
+pub fn retry_after(tenant: &str, identifier: &str) -> Option<Duration> {
+  TRACKER
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+    .retry_after(&key(tenant, identifier), Instant::now())
+}
+
+/// Fails with `Error::LockedOut` while `identifier` is locked on `tenant`
+pub fn check(tenant: &str, identifier: &str) -> Result<(), Error> {
+  match retry_after(tenant, identifier) {
+    Some(remaining) => Err(Error::LockedOut(remaining)),
+    None => Ok(()),
+  }
+}
+
+/// Whether `error` means the password was wrong, as opposed to the request
+/// being malformed or the user not existing
+pub fn is_authentication_failure(error: &Error) -> bool {
+  matches!(
+    error,
+    Error::InvalidCredentials
+      | Error::Protocol(ProtocolError::VerificationError(
+        PakeError::InvalidLoginError
+      ))
+  )
+}
+
+/// Emits `LockoutTriggered` if this failure locks `identifier`
+pub fn record_failure(tenant: &str, identifier: &str) {
+  let locked_for = TRACKER
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+    .record_failure(key(tenant, identifier), &lockout_policy(), Instant::now());
+  if let Some(duration) = locked_for {
+    events::emit(SecurityEvent::LockoutTriggered {
+      tenant: tenant.to_string(),
+      credential_identifier: identifier.to_string(),
+      duration,
+    });
+  }
+}
+
+/// Counts a login that started (one whose credential response went to the
+/// client) as a failure, until a successful finish clears it
+pub fn record_login_start(tenant: &str, identifier: &str) {
+  record_failure(tenant, identifier)
+}
+
+pub fn record_success(tenant: &str, identifier: &str) {
+  TRACKER
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+    .remove(&key(tenant, identifier));
+}
+
+/// Records the result of a step that checks the password by itself (a
+/// legacy login): success clears `identifier`, an authentication failure
+/// counts against it, and any other error is left alone
+pub fn record_outcome<T>(
+  tenant: &str,
+  identifier: &str,
+  result: &Result<T, Error>,
+) {
+  match result {
+    Ok(_) => record_success(tenant, identifier),
+    Err(e) if is_authentication_failure(e) => {
+      record_failure(tenant, identifier)
+    }
+    Err(_) => (),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_delay() {
+    let policy = LockoutPolicy {
+      free_attempts: 2,
+      base_delay: Duration::from_secs(1),
+      max_delay: Duration::from_secs(10),
+    };
+    assert_eq!(policy.delay(2), Duration::ZERO);
+    assert_eq!(policy.delay(3), Duration::from_secs(1));
+    assert_eq!(policy.delay(4), Duration::from_secs(2));
+    assert_eq!(policy.delay(6), Duration::from_secs(8));
+    assert_eq!(policy.delay(7), Duration::from_secs(10));
+    assert_eq!(policy.delay(u32::MAX), Duration::from_secs(10));
+  }
+
+  #[test]
+  fn test_lockout_and_reset() {
+    let policy = LockoutPolicy {
+      free_attempts: 1,
+      base_delay: Duration::from_secs(4),
+      max_delay: Duration::from_secs(60),
+    };
+    let mut tracker = LockoutTracker::new(MAX_TRACKED);
+    let now = Instant::now();
+    let alice = key("a", "alice");
+    assert_eq!(tracker.record_failure(alice.clone(), &policy, now), None);
+    assert_eq!(tracker.retry_after(&alice, now), None);
+    let locked_for = tracker.record_failure(alice.clone(), &policy, now);
+    let remaining = tracker.retry_after(&alice, now).unwrap();
+    assert_eq!(locked_for, Some(remaining));
+    assert!(remaining >= Duration::from_secs(4));
+    assert!(remaining <= Duration::from_secs(5));
+    assert_eq!(tracker.retry_after(&key("a", "bob"), now), None);
+    assert_eq!(tracker.retry_after(&key("b", "alice"), now), None);
+    assert_eq!(tracker.retry_after(&alice, now + remaining), None);
+    tracker.remove(&alice);
+    tracker.record_failure(alice.clone(), &policy, now);
+    assert_eq!(tracker.retry_after(&alice, now), None);
+  }
+
+  #[test]
+  fn test_unfinished_logins_lock() {
+    let policy = LockoutPolicy {
+      free_attempts: 2,
+      base_delay: Duration::from_secs(60),
+      max_delay: Duration::from_secs(60),
+    };
+    set_lockout_policy(policy);
+    for _ in 0..3 {
+      check("unfinished", "mallory").unwrap();
+      record_login_start("unfinished", "mallory");
+    }
+    assert!(matches!(
+      check("unfinished", "mallory"),
+      Err(Error::LockedOut(_))
+    ));
+    record_login_start("unfinished", "alice");
+    record_success("unfinished", "alice");
+    record_login_start("unfinished", "alice");
+    record_login_start("unfinished", "alice");
+    check("unfinished", "alice").unwrap();
+    assert!(policy.locks());
+    assert!(!LockoutPolicy {
+      base_delay: Duration::ZERO,
+      ..policy
+    }
+    .locks());
+    set_lockout_policy(LockoutPolicy::DEFAULT);
+  }
+
+  #[test]
+  fn test_locked_identifiers_are_never_forgotten() {
+    let policy = LockoutPolicy {
+      free_attempts: 0,
+      base_delay: Duration::from_secs(4),
+      max_delay: Duration::from_secs(60),
+    };
+    let mut tracker = LockoutTracker::new(2);
+    let now = Instant::now();
+    let [alice, bob, mallory, carol] =
+      ["alice", "bob", "mallory", "carol"].map(|user| key("a", user));
+    tracker.record_failure(alice.clone(), &policy, now);
+    tracker.record_failure(bob.clone(), &policy, now);
+    tracker.record_failure(mallory, &policy, now);
+    assert_eq!(tracker.entries.len(), 3);
+    assert!(tracker.retry_after(&alice, now).is_some());
+    assert!(tracker.retry_after(&bob, now).is_some());
+
+    let later = now + Duration::from_secs(10);
+    tracker.record_failure(bob.clone(), &policy, later);
+    tracker.record_failure(carol.clone(), &policy, later);
+    assert_eq!(tracker.entries.len(), 2);
+    assert!(tracker.retry_after(&bob, later).is_some());
+    assert!(tracker.retry_after(&carol, later).is_some());
+    assert_eq!(tracker.by_expiry.len(), tracker.entries.len());
+  }
+}
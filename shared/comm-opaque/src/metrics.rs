@@ -0,0 +1,197 @@
+//! Counters and latency histograms for the server-side operations, cheap
+//! enough to leave on in the login path.
+//!
+//! Everything is a relaxed atomic, so recording never takes a lock. Each
+//! histogram is split into cache-line-aligned stripes, and each thread
+//! always writes to the same stripe, so threads hashing passwords in
+//! parallel don't contend on the same cache lines. Stripes are only summed
+//! when a snapshot is taken.
+
+use std::{
+  cell::Cell,
+  sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+  time::{Duration, Instant},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Operation {
+  LoginStart,
+  LoginFinish,
+  LegacyVerify,
+  LegacyMigration,
+  Reregistration,
+}
+
+pub const OPERATIONS: [Operation; 5] = [
+  Operation::LoginStart,
+  Operation::LoginFinish,
+  Operation::LegacyVerify,
+  Operation::LegacyMigration,
+  Operation::Reregistration,
+];
+
+impl Operation {
+  pub fn name(self) -> &'static str {
+    match self {
+      Operation::LoginStart => "login_start",
+      Operation::LoginFinish => "login_finish",
+      Operation::LegacyVerify => "legacy_verify",
+      Operation::LegacyMigration => "legacy_migration",
+      Operation::Reregistration => "reregistration",
+    }
+  }
+}
+
+/// Bucket `i` counts latencies below 2^i microseconds; the last bucket also
+/// counts everything slower (about 17 seconds and up).
+pub const LATENCY_BUCKETS: usize = 25;
+const STRIPES: usize = 8;
+
+#[repr(align(64))]
+struct Stripe {
+  buckets: [AtomicU64; LATENCY_BUCKETS],
+  total_micros: AtomicU64,
+}
+
+impl Stripe {
+  const fn new() -> Self {
+    Self {
+      buckets: [const { AtomicU64::new(0) }; LATENCY_BUCKETS],
+      total_micros: AtomicU64::new(0),
+    }
+  }
+}
+
+struct OperationStats {
+  failures: AtomicU64,
+  stripes: [Stripe; STRIPES],
+}
+
+impl OperationStats {
+  const fn new() -> Self {
+    Self {
+      failures: AtomicU64::new(0),
+      stripes: [const { Stripe::new() }; STRIPES],
+    }
+  }
+
+  fn record(&self, latency: Duration, succeeded: bool) {
+    if !succeeded {
+      self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+    let stripe = &self.stripes[stripe_index()];
+    stripe.buckets[bucket_index(latency)].fetch_add(1, Ordering::Relaxed);
+    stripe
+      .total_micros
+      .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+  }
+
+  fn snapshot(&self, operation: Operation) -> OperationSnapshot {
+    let mut latency_buckets = [0; LATENCY_BUCKETS];
+    let mut total_micros = 0;
+    for stripe in &self.stripes {
+      for (total, bucket) in latency_buckets.iter_mut().zip(&stripe.buckets) {
+        *total += bucket.load(Ordering::Relaxed);
+      }
+      total_micros += stripe.total_micros.load(Ordering::Relaxed);
+    }
+    OperationSnapshot {
+      operation,
+      count: latency_buckets.iter().sum(),
+      failures: self.failures.load(Ordering::Relaxed),
+      total_micros,
+      latency_buckets,
+    }
+  }
+}
+
+static STATS: [OperationStats; OPERATIONS.len()] =
+  [const { OperationStats::new() }; OPERATIONS.len()];
+
+fn stripe_index() -> usize {
+  static NEXT_STRIPE: AtomicUsize = AtomicUsize::new(0);
+  thread_local! {
+    static STRIPE: Cell<Option<usize>> = const { Cell::new(None) };
+  }
+  STRIPE.with(|stripe| {
+    stripe.get().unwrap_or_else(|| {
+      let index = NEXT_STRIPE.fetch_add(1, Ordering::Relaxed) % STRIPES;
+      stripe.set(Some(index));
+      index
+    })
+  })
+}
+
+fn bucket_index(latency: Duration) -> usize {
+  let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+  let index = (u64::BITS - micros.leading_zeros()) as usize;
+  index.min(LATENCY_BUCKETS - 1)
+}
+
+pub fn record(operation: Operation, latency: Duration, succeeded: bool) {
+  STATS[operation as usize].record(latency, succeeded);
+}
+
+/// Runs `f` and records how long it took and whether it returned an error
+pub fn time<T, E>(
+  operation: Operation,
+  f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+  let start = Instant::now();
+  let result = f();
+  record(operation, start.elapsed(), result.is_ok());
+  result
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OperationSnapshot {
+  pub operation: Operation,
+  pub count: u64,
+  pub failures: u64,
+  pub total_micros: u64,
+  pub latency_buckets: [u64; LATENCY_BUCKETS],
+}
+
+/// Totals for every operation since the process started. Concurrent
+/// recordings may or may not be included, so counts taken from the same
+/// snapshot can be off by the number of operations in flight.
+pub fn snapshot() -> Vec<OperationSnapshot> {
+  OPERATIONS
+    .iter()
+    .map(|&operation| STATS[operation as usize].snapshot(operation))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_bucket_index() {
+    assert_eq!(bucket_index(Duration::ZERO), 0);
+    assert_eq!(bucket_index(Duration::from_micros(1)), 1);
+    assert_eq!(bucket_index(Duration::from_micros(1023)), 10);
+    assert_eq!(bucket_index(Duration::from_micros(1024)), 11);
+    assert_eq!(bucket_index(Duration::MAX), LATENCY_BUCKETS - 1);
+  }
+
+  #[test]
+  fn test_concurrent_recording() {
+    let stats = OperationStats::new();
+    std::thread::scope(|scope| {
+      for _ in 0..4 {
+        scope.spawn(|| {
+          for i in 0..1000 {
+            stats.record(Duration::from_micros(3), i % 2 == 0);
+          }
+        });
+      }
+    });
+    let snapshot = stats.snapshot(Operation::LoginStart);
+    assert_eq!(snapshot.count, 4000);
+    assert_eq!(snapshot.failures, 2000);
+    assert_eq!(snapshot.latency_buckets[2], 4000);
+    assert_eq!(snapshot.total_micros, 12000);
+  }
+}
@@ -1,10 +1,11 @@
-use argon2::Argon2;
 use digest::{generic_array::GenericArray, Digest};
 use opaque_ke::{
   ciphersuite::CipherSuite, errors::InternalPakeError, hash::Hash,
   slow_hash::SlowHash,
 };
 
+use crate::ksf;
+
 pub struct Cipher;
 
 impl CipherSuite for Cipher {
@@ -14,17 +15,22 @@ impl CipherSuite for Cipher {
   type SlowHash = ArgonWrapper;
 }
 
-pub struct ArgonWrapper(Argon2<'static>);
+/// Argon2id with the parameter set made active by `ksf::with_ksf`, which is
+/// `ksf::KSF_DEFAULT` (the Argon2 defaults) unless a caller chose otherwise
+pub struct ArgonWrapper;
 
 impl<D: Hash> SlowHash<D> for ArgonWrapper {
   fn hash(
     input: GenericArray<u8, <D as Digest>::OutputSize>,
   ) -> Result<Vec<u8>, InternalPakeError> {
-    let params = Argon2::default();
     let mut output = vec![0u8; <D as Digest>::output_size()];
-    params
-      .hash_password_into(&input, &[0; argon2::MIN_SALT_LEN], &mut output)
-      .map_err(|_| InternalPakeError::SlowHashError)?;
+    ksf::argon2id(
+      ksf::active_params(),
+      &input,
+      &[0; argon2::MIN_SALT_LEN],
+      &mut output,
+    )
+    .map_err(|_| InternalPakeError::SlowHashError)?;
     Ok(output)
   }
 }
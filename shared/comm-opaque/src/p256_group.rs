@@ -0,0 +1,232 @@
+//! NIST P-256 for opaque-ke, which only ships Ristretto255. Passwords are
+//! mapped to the curve with P256_XMD:SHA-256_SSWU_RO_ from RFC 9380.
+//!
+//! Compressed SEC1 points would be 33 bytes, which opaque-ke 1.x can't carry:
+//! `Key::LEN` and the 3DH key exchange's key length are fixed at 32, in 1.3
+//! as in 1.2, and only opaque-ke 2 takes the lengths from the group. So
+//! points are serialized as the x-coordinate of their SEC1 encoding alone
+//! (the compact representation of RFC 6090) and parsed back as the point
+//! with even y. That loses only the sign of the
+//! point, and nothing in OPAQUE depends on it: points are never added, and
+//! since (-P)k = -(Pk), every output the protocol derives from a point is
+//! the same for P and -P.
+
+use std::ops::Mul;
+
+use digest::generic_array::{typenum::U32, GenericArray};
+use opaque_ke::{
+  errors::{InternalPakeError, ProtocolError},
+  group::Group,
+  hash::Hash,
+  map_to_curve::GroupWithMapToCurve,
+  rand::{CryptoRng, RngCore},
+};
+use p256::{
+  elliptic_curve::{
+    group::Group as _,
+    hash2curve::{ExpandMsgXmd, GroupDigest},
+    ops::Reduce,
+    sec1::{FromEncodedPoint, ToEncodedPoint},
+    subtle::ConstantTimeEq,
+    zeroize::Zeroize,
+  },
+  AffinePoint, EncodedPoint, NistP256, NonZeroScalar, ProjectivePoint, Scalar,
+  U256,
+};
+use sha2_v10::Sha256;
+
+/// Domain separation for `Group::hash_to_curve`, which opaque-ke only calls
+/// through `map_to_curve`; `map_to_curve` uses the DST it is given
+const HASH_TO_CURVE_DST: &[u8] = b"comm-opaque-P256_XMD:SHA-256_SSWU_RO_";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct P256Point(ProjectivePoint);
+
+/// opaque-ke borrows a scalar's bytes, so they are kept next to it
+#[derive(Clone)]
+pub struct P256Scalar {
+  scalar: Scalar,
+  bytes: GenericArray<u8, U32>,
+}
+
+impl P256Scalar {
+  fn new(scalar: Scalar) -> Self {
+    Self {
+      scalar,
+      bytes: scalar.to_bytes(),
+    }
+  }
+}
+
+impl Zeroize for P256Scalar {
+  fn zeroize(&mut self) {
+    self.scalar = Scalar::ZERO;
+    self.bytes[..].zeroize();
+  }
+}
+
+impl<'a> Mul<&'a P256Scalar> for P256Point {
+  type Output = Self;
+
+  fn mul(self, scalar: &'a P256Scalar) -> Self {
+    Self(self.0 * scalar.scalar)
+  }
+}
+
+fn reduce(bytes: &GenericArray<u8, U32>) -> Scalar {
+  <Scalar as Reduce<U256>>::reduce_bytes(bytes)
+}
+
+fn hash_to_point(
+  msg: &[u8],
+  dst: &[u8],
+) -> Result<P256Point, InternalPakeError> {
+  NistP256::hash_from_bytes::<ExpandMsgXmd<Sha256>>(&[msg], &[dst])
+    .map(P256Point)
+    .map_err(|_| InternalPakeError::HashToCurveError)
+}
+
+impl Group for P256Point {
+  type Scalar = P256Scalar;
+  type ScalarLen = U32;
+
+  fn from_scalar_slice(
+    scalar_bits: &GenericArray<u8, Self::ScalarLen>,
+  ) -> Result<Self::Scalar, InternalPakeError> {
+    Ok(P256Scalar::new(reduce(scalar_bits)))
+  }
+
+  fn random_nonzero_scalar<R: RngCore + CryptoRng>(
+    rng: &mut R,
+  ) -> Self::Scalar {
+    P256Scalar::new(*NonZeroScalar::random(rng))
+  }
+
+  fn scalar_as_bytes(
+    scalar: &Self::Scalar,
+  ) -> &GenericArray<u8, Self::ScalarLen> {
+    &scalar.bytes
+  }
+
+  fn scalar_invert(scalar: &Self::Scalar) -> Self::Scalar {
+    P256Scalar::new(scalar.scalar.invert().unwrap_or(Scalar::ZERO))
+  }
+
+  type ElemLen = U32;
+
+  fn from_element_slice(
+    element_bits: &GenericArray<u8, Self::ElemLen>,
+  ) -> Result<Self, InternalPakeError> {
+    let encoded =
+      EncodedPoint::from_bytes([&[0x02], &element_bits[..]].concat())
+        .map_err(|_| InternalPakeError::PointError)?;
+    Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&encoded))
+      .map(|point| Self(point.into()))
+      .ok_or(InternalPakeError::PointError)
+  }
+
+  /// The identity has no x-coordinate and is written as zeros. It only comes
+  /// out of a multiplication by a multiple of the group order, which the
+  /// protocol never does.
+  fn to_arr(&self) -> GenericArray<u8, Self::ElemLen> {
+    let mut arr = GenericArray::default();
+    let encoded = self.0.to_affine().to_encoded_point(true);
+    if let Some(x) = encoded.x() {
+      arr.copy_from_slice(x);
+    }
+    arr
+  }
+
+  type UniformBytesLen = U32;
+
+  fn hash_to_curve(
+    uniform_bytes: &GenericArray<u8, Self::UniformBytesLen>,
+  ) -> Self {
+    hash_to_point(uniform_bytes, HASH_TO_CURVE_DST)
+      .expect("hashing to P-256 only fails for oversized inputs")
+  }
+
+  fn base_point() -> Self {
+    Self(ProjectivePoint::GENERATOR)
+  }
+
+  fn mult_by_slice(&self, scalar: &GenericArray<u8, Self::ScalarLen>) -> Self {
+    Self(self.0 * reduce(scalar))
+  }
+
+  fn is_identity(&self) -> bool {
+    self.0.is_identity().into()
+  }
+
+  /// Compares serializations, so P and -P are equal
+  fn ct_equal(&self, other: &Self) -> bool {
+    self.to_arr()[..].ct_eq(&other.to_arr()[..]).into()
+  }
+}
+
+impl GroupWithMapToCurve for P256Point {
+  /// P-256 with SHA-256, from draft-irtf-cfrg-voprf
+  const SUITE_ID: usize = 0x0003;
+
+  /// Always hashes with SHA-256, as the suite requires
+  fn map_to_curve<H: Hash>(
+    msg: &[u8],
+    dst: &[u8],
+  ) -> Result<Self, ProtocolError> {
+    Ok(hash_to_point(msg, dst)?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use opaque_ke::rand::rngs::OsRng;
+
+  #[test]
+  fn test_element_round_trip() {
+    let scalar = P256Point::random_nonzero_scalar(&mut OsRng);
+    let point = P256Point::base_point() * &scalar;
+    let parsed = P256Point::from_element_slice(&point.to_arr()).unwrap();
+    assert!(parsed.ct_equal(&point));
+    assert!(P256Point(-point.0).ct_equal(&point));
+    let inverse = P256Point::scalar_invert(&scalar);
+    assert!((point * &inverse).ct_equal(&P256Point::base_point()));
+    // Not a field element
+    let too_large = GenericArray::clone_from_slice(&[0xff; 32]);
+    assert!(P256Point::from_element_slice(&too_large).is_err());
+  }
+
+  /// RFC 9380 appendix J.1.1
+  #[test]
+  fn test_hash_to_curve_vectors() {
+    let dst = b"QUUX-V01-CS02-with-P256_XMD:SHA-256_SSWU_RO_";
+    for (msg, x, y) in [
+      (
+        &b""[..],
+        "2c15230b26dbc6fc9a37051158c95b79656e17a1a920b11394ca91c44247d3e4",
+        "8a7a74985cc5c776cdfe4b1f19884970453912e9d31528c060be9ab5c43e8415",
+      ),
+      (
+        b"abc",
+        "0bb8b87485551aa43ed54f009230450b492fead5f1cc91658775dac4a3388a0f",
+        "5c41b3d0731a27a7b14bc0bf0ccded2d8751f83493404c84a88e71ffd424212e",
+      ),
+    ] {
+      let point = hash_to_point(msg, dst).unwrap().0.to_affine();
+      let encoded = point.to_encoded_point(false);
+      assert_eq!(hex::encode(encoded.x().unwrap()), x);
+      assert_eq!(hex::encode(encoded.y().unwrap()), y);
+    }
+  }
+
+  #[test]
+  fn test_serialization_is_sec1_x_coordinate() {
+    let generator = P256Point::base_point();
+    let sec1 = generator.0.to_affine().to_encoded_point(true);
+    assert_eq!(
+      hex::encode(sec1.as_bytes()),
+      "036b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296"
+    );
+    assert_eq!(&generator.to_arr()[..], &sec1.as_bytes()[1..]);
+  }
+}
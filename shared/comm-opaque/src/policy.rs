@@ -0,0 +1,257 @@
+//! Minimum versions the module accepts, and the rules new passwords have to
+//! meet. Once every client has moved to a newer wire format or every record
+//! has been re-registered, raising the minimum stops an attacker from
+//! forcing a fall back to the older mode.
+
+use std::sync::RwLock;
+
+use crate::{
+  record::{PasswordRecord, SuiteVersion, SUITE_V1},
+  Error,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionPolicy {
+  pub min_wire_version: u16,
+  pub min_suite_version: SuiteVersion,
+}
+
+impl VersionPolicy {
+  pub const DEFAULT: Self = Self {
+    min_wire_version: 1,
+    min_suite_version: SUITE_V1,
+  };
+
+  pub fn check_wire_version(&self, version: u16) -> Result<(), Error> {
+    if version < self.min_wire_version {
+      return Err(Error::VersionBelowMinimum);
+    }
+    Ok(())
+  }
+
+  pub fn check_record(&self, record: &PasswordRecord) -> Result<(), Error> {
+    self.check_suite_version(record.suite_version)
+  }
+
+  /// For records about to be written, checked before any work is done
+  pub fn check_suite_version(
+    &self,
+    suite_version: SuiteVersion,
+  ) -> Result<(), Error> {
+    if suite_version < self.min_suite_version {
+      return Err(Error::VersionBelowMinimum);
+    }
+    Ok(())
+  }
+}
+
+impl Default for VersionPolicy {
+  fn default() -> Self {
+    Self::DEFAULT
+  }
+}
+
+static VERSION_POLICY: RwLock<VersionPolicy> =
+  RwLock::new(VersionPolicy::DEFAULT);
+
+pub fn set_version_policy(policy: VersionPolicy) {
+  *VERSION_POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+pub fn version_policy() -> VersionPolicy {
+  *VERSION_POLICY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum PolicyViolation {
+  #[display(fmt = "password is too short")]
+  TooShort,
+  #[display(fmt = "password uses too few character classes")]
+  TooFewCharacterClasses,
+  #[display(fmt = "password is too easy to guess")]
+  TooPredictable,
+}
+
+/// Checked by `client::register_start`, so a frontend can't skip it. Login
+/// never checks it: existing passwords keep working after it is raised.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PasswordPolicy {
+  /// In characters, not bytes
+  pub min_length: usize,
+  /// Out of lowercase, uppercase, digits and everything else
+  pub min_character_classes: u8,
+  pub min_entropy_bits: u32,
+}
+
+impl PasswordPolicy {
+  /// Accepts every password
+  pub const DEFAULT: Self = Self {
+    min_length: 0,
+    min_character_classes: 0,
+    min_entropy_bits: 0,
+  };
+
+  pub fn check(&self, password: &[u8]) -> Result<(), PolicyViolation> {
+    let password = String::from_utf8_lossy(password);
+    if password.chars().count() < self.min_length {
+      return Err(PolicyViolation::TooShort);
+    }
+    if character_classes(&password).count_ones()
+      < u32::from(self.min_character_classes)
+    {
+      return Err(PolicyViolation::TooFewCharacterClasses);
+    }
+    if estimate_entropy_bits(&password) < f64::from(self.min_entropy_bits) {
+      return Err(PolicyViolation::TooPredictable);
+    }
+    Ok(())
+  }
+}
+
+impl Default for PasswordPolicy {
+  fn default() -> Self {
+    Self::DEFAULT
+  }
+}
+
+const LOWERCASE: u8 = 1;
+const UPPERCASE: u8 = 1 << 1;
+const DIGIT: u8 = 1 << 2;
+const OTHER: u8 = 1 << 3;
+
+fn character_class(c: char) -> u8 {
+  if c.is_lowercase() {
+    LOWERCASE
+  } else if c.is_uppercase() {
+    UPPERCASE
+  } else if c.is_numeric() {
+    DIGIT
+  } else {
+    OTHER
+  }
+}
+
+fn character_classes(password: &str) -> u8 {
+  password
+    .chars()
+    .map(character_class)
+    .fold(0, |classes, class| classes | class)
+}
+
+/// Length times the bits per character of the alphabet the classes in use
+/// add up to. A character repeating or continuing a run (`aaa`, `abc`,
+/// `321`) from the one before it adds nothing.
+fn estimate_entropy_bits(password: &str) -> f64 {
+  let classes = character_classes(password);
+  let alphabet: u32 =
+    [(LOWERCASE, 26), (UPPERCASE, 26), (DIGIT, 10), (OTHER, 33)]
+      .iter()
+      .filter(|(class, _)| classes & class != 0)
+      .map(|(_, size)| size)
+      .sum();
+  if alphabet == 0 {
+    return 0.0;
+  }
+  let mut previous: Option<(char, i64)> = None;
+  let mut counted = 0u32;
+  for c in password.chars() {
+    let step = previous.map(|(previous, _)| c as i64 - previous as i64);
+    let continues_run = match (step, previous) {
+      (Some(step), Some((_, previous_step))) => {
+        step == 0 || (step.abs() == 1 && step == previous_step)
+      }
+      _ => false,
+    };
+    if !continues_run {
+      counted += 1;
+    }
+    previous = Some((c, step.unwrap_or(0)));
+  }
+  f64::from(counted) * f64::from(alphabet).log2()
+}
+
+static PASSWORD_POLICY: RwLock<PasswordPolicy> =
+  RwLock::new(PasswordPolicy::DEFAULT);
+
+pub fn set_password_policy(policy: PasswordPolicy) {
+  *PASSWORD_POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+pub fn password_policy() -> PasswordPolicy {
+  *PASSWORD_POLICY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::record::CURRENT_RECORD_FORMAT;
+  use opaque_ke::ServerRegistration;
+
+  fn record_with_version(suite_version: SuiteVersion) -> PasswordRecord {
+    let mut oprf_key = [0u8; 32];
+    oprf_key[0] = 1;
+    PasswordRecord {
+      format: CURRENT_RECORD_FORMAT,
+      suite_version,
+      password_file: ServerRegistration::deserialize(&oprf_key).unwrap(),
+    }
+  }
+
+  #[test]
+  fn test_record_below_minimum() {
+    let policy = VersionPolicy {
+      min_suite_version: SuiteVersion { suite: 1, ksf: 2 },
+      ..VersionPolicy::DEFAULT
+    };
+    assert!(matches!(
+      policy.check_record(&record_with_version(SUITE_V1)),
+      Err(Error::VersionBelowMinimum)
+    ));
+    assert!(policy
+      .check_record(&record_with_version(SuiteVersion { suite: 2, ksf: 1 }))
+      .is_ok());
+    assert!(matches!(
+      policy.check_suite_version(SUITE_V1),
+      Err(Error::VersionBelowMinimum)
+    ));
+  }
+
+  #[test]
+  fn test_wire_version_below_minimum() {
+    let policy = VersionPolicy {
+      min_wire_version: 2,
+      ..VersionPolicy::DEFAULT
+    };
+    assert!(matches!(
+      policy.check_wire_version(1),
+      Err(Error::VersionBelowMinimum)
+    ));
+    assert!(policy.check_wire_version(2).is_ok());
+    assert!(policy.check_wire_version(3).is_ok());
+  }
+
+  #[test]
+  fn test_password_policy() {
+    let policy = PasswordPolicy {
+      min_length: 8,
+      min_character_classes: 2,
+      min_entropy_bits: 40,
+    };
+    assert_eq!(policy.check(b"Ab1"), Err(PolicyViolation::TooShort));
+    assert_eq!(
+      policy.check(b"correcthorse"),
+      Err(PolicyViolation::TooFewCharacterClasses)
+    );
+    assert_eq!(
+      policy.check(b"abcdefgh12"),
+      Err(PolicyViolation::TooPredictable)
+    );
+    assert!(policy.check(b"tr0ub4dor&3").is_ok());
+    // Characters, not bytes
+    assert_eq!(
+      policy.check("пароль1".as_bytes()),
+      Err(PolicyViolation::TooShort)
+    );
+    assert!(PasswordPolicy::DEFAULT.check(b"").is_ok());
+  }
+}
@@ -0,0 +1,174 @@
+use opaque_ke::ServerRegistration;
+
+use crate::{
+  serialization::{Decoder, Encoder},
+  Cipher, Error,
+};
+
+pub(crate) const RECORD_MAGIC: &[u8; 4] = b"cOPQ";
+
+/// Container format of a stored record. Bare records are serialized
+/// `ServerRegistration`s stored before records were versioned; format 1
+/// appended the password file to the header without a length prefix.
+pub const BARE_RECORD_FORMAT: u8 = 0;
+pub const CURRENT_RECORD_FORMAT: u8 = 2;
+
+/// Identifies the ciphersuite and key stretching function a password file
+/// was registered with. The client runs the KSF, so a record can only be
+/// moved to a newer version by registering again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SuiteVersion {
+  pub suite: u8,
+  pub ksf: u8,
+}
+
+/// Ristretto255, TripleDH, SHA-512 with Argon2 default parameters, which is
+/// what `Cipher` has always been
+pub const SUITE_V1: SuiteVersion = SuiteVersion { suite: 1, ksf: 1 };
+pub const CURRENT_SUITE_VERSION: SuiteVersion = SUITE_V1;
+
+/// A password file along with the versions it was created with, as stored by
+/// the server
+pub struct PasswordRecord {
+  pub format: u8,
+  pub suite_version: SuiteVersion,
+  pub password_file: ServerRegistration<Cipher>,
+}
+
+impl PasswordRecord {
+  /// Wraps a password file freshly registered with `Cipher`
+  pub fn new(password_file: ServerRegistration<Cipher>) -> Self {
+    Self {
+      format: CURRENT_RECORD_FORMAT,
+      suite_version: CURRENT_SUITE_VERSION,
+      password_file,
+    }
+  }
+
+  /// Wraps a password file registered with KSF parameter set `ksf` (see
+  /// `ksf::with_ksf`)
+  pub fn with_ksf(password_file: ServerRegistration<Cipher>, ksf: u8) -> Self {
+    Self {
+      suite_version: SuiteVersion {
+        ksf,
+        ..CURRENT_SUITE_VERSION
+      },
+      ..Self::new(password_file)
+    }
+  }
+
+  /// Whether the user should be asked to register again (ideally right after
+  /// logging in, see `upgrade`) to move to the current ciphersuite and KSF
+  pub fn needs_reregistration(&self) -> bool {
+    self.suite_version < CURRENT_SUITE_VERSION
+  }
+
+  /// Always writes the current container format
+  pub fn serialize(&self) -> Vec<u8> {
+    Encoder::new()
+      .fixed(RECORD_MAGIC)
+      .u8(CURRENT_RECORD_FORMAT)
+      .u8(self.suite_version.suite)
+      .u8(self.suite_version.ksf)
+      .bytes(&self.password_file.serialize())
+      .finish()
+  }
+
+  /// Accepts every container format, including bare password files
+  pub fn deserialize(input: &[u8]) -> Result<Self, Error> {
+    let (format, suite_version, password_file) = split_record(input)?;
+    Ok(Self {
+      format,
+      suite_version,
+      password_file: ServerRegistration::deserialize(password_file)?,
+    })
+  }
+}
+
+/// Splits a record of any container format into its format, suite version
+/// and serialized password file
+fn split_record(input: &[u8]) -> Result<(u8, SuiteVersion, &[u8]), Error> {
+  if !input.starts_with(RECORD_MAGIC) {
+    return Ok((BARE_RECORD_FORMAT, SUITE_V1, input));
+  }
+  let mut decoder = Decoder::new(&input[RECORD_MAGIC.len()..]);
+  let format = decoder.u8()?;
+  let suite_version = SuiteVersion {
+    suite: decoder.u8()?,
+    ksf: decoder.u8()?,
+  };
+  let password_file = match format {
+    1 => decoder.fixed(input.len() - RECORD_MAGIC.len() - 3)?,
+    CURRENT_RECORD_FORMAT => decoder.bytes()?,
+    _ => return Err(Error::InvalidRecord),
+  };
+  decoder.finish()?;
+  Ok((format, suite_version, password_file))
+}
+
+/// Rewrites a stored record (of any known container format) in the current
+/// container format. The password file itself is carried over byte for byte.
+pub fn upgrade_record_format(input: &[u8]) -> Result<Vec<u8>, Error> {
+  let output = PasswordRecord::deserialize(input)?.serialize();
+  if split_record(input)?.2 != split_record(&output)?.2 {
+    return Err(Error::InvalidRecord);
+  }
+  Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::legacy::migrate_legacy_password;
+  use opaque_ke::{ciphersuite::CipherSuite, rand::rngs::OsRng};
+
+  fn password_file() -> ServerRegistration<Cipher> {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let legacy_hash = bcrypt::hash("hunter2", 4).unwrap();
+    migrate_legacy_password(&legacy_hash, "hunter2", &server_keypair)
+      .unwrap()
+      .unwrap()
+      .password_file
+  }
+
+  #[test]
+  fn test_record_round_trip() {
+    let record = PasswordRecord::new(password_file());
+    let bytes = record.serialize();
+    let deserialized = PasswordRecord::deserialize(&bytes).unwrap();
+    assert_eq!(deserialized.format, CURRENT_RECORD_FORMAT);
+    assert_eq!(deserialized.suite_version, CURRENT_SUITE_VERSION);
+    assert_eq!(deserialized.serialize(), bytes);
+    assert!(!deserialized.needs_reregistration());
+  }
+
+  #[test]
+  fn test_bare_record_is_accepted() {
+    let bare = password_file().serialize();
+    let record = PasswordRecord::deserialize(&bare).unwrap();
+    assert_eq!(record.format, BARE_RECORD_FORMAT);
+    assert_eq!(record.suite_version, SUITE_V1);
+    assert_eq!(record.password_file.serialize(), bare);
+  }
+
+  #[test]
+  fn test_upgrade_record_format() {
+    let bare = password_file().serialize();
+    let format_1 = [&RECORD_MAGIC[..], &[1, 1, 1], &bare].concat();
+    for old in [bare.clone(), format_1] {
+      let upgraded = upgrade_record_format(&old).unwrap();
+      let record = PasswordRecord::deserialize(&upgraded).unwrap();
+      assert_eq!(record.format, CURRENT_RECORD_FORMAT);
+      assert_eq!(record.password_file.serialize(), bare);
+      assert_eq!(upgrade_record_format(&upgraded).unwrap(), upgraded);
+    }
+  }
+
+  #[test]
+  fn test_old_suite_needs_reregistration() {
+    let mut bytes = PasswordRecord::new(password_file()).serialize();
+    bytes[RECORD_MAGIC.len() + 2] = 0;
+    let record = PasswordRecord::deserialize(&bytes).unwrap();
+    assert!(record.needs_reregistration());
+  }
+}
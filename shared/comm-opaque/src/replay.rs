@@ -0,0 +1,174 @@
+//! Rejects OPAQUE credential requests the server has already answered. An
+//! honest client draws a new blind and nonce for every login, so the same
+//! request arriving twice means someone captured it and is replaying it.
+//!
+//! Only a hash of each request is kept, for `window` after it was first
+//! seen; once `capacity` requests are tracked, the oldest are forgotten
+//! early. A replay is refused outright, so an attacker can't use one to
+//! probe the server's response or to count towards the victim's lockout.
+//!
+//! Requests are hashed together with the public key of the server they
+//! were sent to. Every tenant has its own key, so a request is only a
+//! replay on the tenant that already answered it.
+//!
+//! The cache lives in this process's memory and nowhere else. Servers
+//! running several processes (a Node cluster, or keyservers behind a load
+//! balancer) each keep their own, so a request replayed to a process other
+//! than the one that answered it goes through, and a restart forgets every
+//! request seen so far. Where that matters, route a user's logins to one
+//! process, or reject replays in front of the server with a store every
+//! process shares.
+
+use std::{
+  collections::{BTreeMap, VecDeque},
+  sync::{Mutex, RwLock},
+  time::{Duration, Instant},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayPolicy {
+  /// How long a request is remembered; zero turns the cache off
+  pub window: Duration,
+  pub capacity: usize,
+}
+
+impl ReplayPolicy {
+  pub const DEFAULT: Self = Self {
+    window: Duration::from_secs(5 * 60),
+    capacity: 100_000,
+  };
+}
+
+impl Default for ReplayPolicy {
+  fn default() -> Self {
+    Self::DEFAULT
+  }
+}
+
+const MESSAGE_HASH_LABEL: &[u8] = b"comm-opaque replay cache";
+
+type MessageHash = [u8; 32];
+
+fn message_hash(server_public_key: &[u8], message: &[u8]) -> MessageHash {
+  Sha256::new()
+    .chain(MESSAGE_HASH_LABEL)
+    .chain(server_public_key)
+    .chain(message)
+    .finalize()
+    .into()
+}
+
+struct ReplayCache {
+  seen: BTreeMap<MessageHash, Instant>,
+  /// The same hashes in the order they were first seen, oldest first
+  order: VecDeque<(Instant, MessageHash)>,
+}
+
+impl ReplayCache {
+  const fn new() -> Self {
+    Self {
+      seen: BTreeMap::new(),
+      order: VecDeque::new(),
+    }
+  }
+
+  /// Whether `hash` is new; records it if so
+  fn insert(
+    &mut self,
+    hash: MessageHash,
+    policy: &ReplayPolicy,
+    now: Instant,
+  ) -> bool {
+    self.forget_expired(policy.window, now);
+    if self.seen.contains_key(&hash) {
+      return false;
+    }
+    while self.order.len() >= policy.capacity.max(1) {
+      self.forget_oldest();
+    }
+    self.seen.insert(hash, now);
+    self.order.push_back((now, hash));
+    true
+  }
+
+  fn forget_expired(&mut self, window: Duration, now: Instant) {
+    while let Some((first_seen, _)) = self.order.front() {
+      if now.saturating_duration_since(*first_seen) < window {
+        return;
+      }
+      self.forget_oldest();
+    }
+  }
+
+  fn forget_oldest(&mut self) {
+    if let Some((_, hash)) = self.order.pop_front() {
+      self.seen.remove(&hash);
+    }
+  }
+}
+
+static REPLAY_POLICY: RwLock<ReplayPolicy> = RwLock::new(ReplayPolicy::DEFAULT);
+static CACHE: Mutex<ReplayCache> = Mutex::new(ReplayCache::new());
+
+pub fn set_replay_policy(policy: ReplayPolicy) {
+  *REPLAY_POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+pub fn replay_policy() -> ReplayPolicy {
+  *REPLAY_POLICY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Fails with `Error::ReplayedMessage` if `message` was already checked for
+/// the server with `server_public_key` within the window; otherwise
+/// remembers it
+pub fn check(server_public_key: &[u8], message: &[u8]) -> Result<(), Error> {
+  let policy = replay_policy();
+  if policy.window.is_zero() {
+    return Ok(());
+  }
+  let is_new = CACHE.lock().unwrap_or_else(|e| e.into_inner()).insert(
+    message_hash(server_public_key, message),
+    &policy,
+    Instant::now(),
+  );
+  if !is_new {
+    return Err(Error::ReplayedMessage);
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_replays_rejected_within_window() {
+    let policy = ReplayPolicy {
+      window: Duration::from_secs(60),
+      capacity: 2,
+    };
+    let mut cache = ReplayCache::new();
+    let now = Instant::now();
+    let [a, b, c] =
+      [b"a", b"b", b"c"].map(|message| message_hash(b"", message));
+    assert!(cache.insert(a, &policy, now));
+    assert!(!cache.insert(a, &policy, now + Duration::from_secs(59)));
+    assert!(cache.insert(a, &policy, now + Duration::from_secs(60)));
+
+    let later = now + Duration::from_secs(61);
+    assert!(cache.insert(b, &policy, later));
+    assert!(cache.insert(c, &policy, later));
+    // `a` was forgotten early to make room for `c`
+    assert!(cache.insert(a, &policy, later));
+    assert!(!cache.insert(c, &policy, later));
+  }
+
+  #[test]
+  fn test_hash_depends_on_server() {
+    assert_ne!(message_hash(b"first", b"a"), message_hash(b"second", b"a"));
+  }
+}
@@ -0,0 +1,354 @@
+//! The random number generator every operation in this crate uses.
+//!
+//! Each thread has its own ChaCha20 generator seeded from the OS. A thread's
+//! generator is thrown away and seeded again:
+//! - after it has produced `RESEED_AFTER_BYTES`
+//! - when the process id changes, so a forked child never repeats its
+//!   parent's output (an exec'd process starts with no generators at all)
+//! - after `force_reseed`, for example once a process restores from a VM
+//!   snapshot
+//!
+//! Builds with the `mock-rng` feature can instead serve draws from a fixed
+//! seed or byte string, so tests can check exact serialized outputs:
+//! `with_mock_rng` for the draws of one operation on the calling thread,
+//! which other threads' operations can't disturb, or `set_mock_rng` for the
+//! next draws on any thread. The feature must never be enabled in
+//! production.
+
+use std::{
+  cell::RefCell,
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+use opaque_ke::rand::{rngs::OsRng, CryptoRng, Error, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+pub const RESEED_AFTER_BYTES: u64 = 64 * 1024;
+
+/// Bumped by `force_reseed`; a generator seeded under an older generation is
+/// replaced on its next use
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+struct ThreadRng {
+  rng: ChaCha20Rng,
+  remaining_bytes: u64,
+  pid: u32,
+  generation: u64,
+}
+
+impl ThreadRng {
+  fn seed(pid: u32, generation: u64) -> Self {
+    Self {
+      rng: ChaCha20Rng::from_rng(OsRng)
+        .expect("OS random number generator is available"),
+      remaining_bytes: RESEED_AFTER_BYTES,
+      pid,
+      generation,
+    }
+  }
+
+  fn is_stale(&self, pid: u32, generation: u64, bytes: u64) -> bool {
+    self.pid != pid
+      || self.generation != generation
+      || self.remaining_bytes < bytes
+  }
+}
+
+thread_local! {
+  static THREAD_RNG: RefCell<Option<ThreadRng>> = const { RefCell::new(None) };
+}
+
+/// Makes every thread seed its generator from the OS again before using it
+pub fn force_reseed() {
+  GENERATION.fetch_add(1, Ordering::AcqRel);
+}
+
+/// wasm32 has no processes to fork, and `process::id` panics there
+fn current_pid() -> u32 {
+  #[cfg(target_arch = "wasm32")]
+  return 0;
+  #[cfg(not(target_arch = "wasm32"))]
+  std::process::id()
+}
+
+fn with_rng<T>(bytes: usize, f: impl FnOnce(&mut ChaCha20Rng) -> T) -> T {
+  THREAD_RNG.with(|thread_rng| {
+    let mut thread_rng = thread_rng.borrow_mut();
+    let pid = current_pid();
+    let generation = GENERATION.load(Ordering::Acquire);
+    let bytes = bytes as u64;
+    if thread_rng
+      .as_ref()
+      .is_none_or(|current| current.is_stale(pid, generation, bytes))
+    {
+      *thread_rng = Some(ThreadRng::seed(pid, generation));
+    }
+    let current = thread_rng.as_mut().expect("seeded above");
+    current.remaining_bytes = current.remaining_bytes.saturating_sub(bytes);
+    f(&mut current.rng)
+  })
+}
+
+/// Handle to the calling thread's generator, used in place of `OsRng`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CommRng;
+
+impl RngCore for CommRng {
+  fn next_u32(&mut self) -> u32 {
+    #[cfg(feature = "mock-rng")]
+    if let Some(value) = mock::next_u32() {
+      return value;
+    }
+    with_rng(4, |rng| rng.next_u32())
+  }
+
+  fn next_u64(&mut self) -> u64 {
+    #[cfg(feature = "mock-rng")]
+    if let Some(value) = mock::next_u64() {
+      return value;
+    }
+    with_rng(8, |rng| rng.next_u64())
+  }
+
+  fn fill_bytes(&mut self, dest: &mut [u8]) {
+    #[cfg(feature = "mock-rng")]
+    if mock::fill_bytes(dest) {
+      return;
+    }
+    with_rng(dest.len(), |rng| rng.fill_bytes(dest))
+  }
+
+  fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+    self.fill_bytes(dest);
+    Ok(())
+  }
+}
+
+impl CryptoRng for CommRng {}
+
+#[cfg(feature = "mock-rng")]
+pub use mock::{clear_mock_rng, set_mock_rng, with_mock_rng, MockSource};
+
+#[cfg(feature = "mock-rng")]
+mod mock {
+  use std::{cell::RefCell, sync::Mutex};
+
+  use opaque_ke::rand::{RngCore, SeedableRng};
+  use rand_chacha::ChaCha20Rng;
+
+  pub enum MockSource {
+    /// A ChaCha20 generator with this seed
+    Seed([u8; 32]),
+    /// These bytes, over and over
+    Bytes(Vec<u8>),
+  }
+
+  enum MockState {
+    Seeded(Box<ChaCha20Rng>),
+    Bytes { bytes: Vec<u8>, position: usize },
+  }
+
+  pub(super) struct MockRng {
+    state: MockState,
+    remaining_draws: u32,
+  }
+
+  impl MockRng {
+    /// `None` for an empty byte string, which has nothing to serve
+    pub(super) fn new(source: MockSource, draws: u32) -> Option<Self> {
+      let state = match source {
+        MockSource::Seed(seed) => {
+          MockState::Seeded(Box::new(ChaCha20Rng::from_seed(seed)))
+        }
+        MockSource::Bytes(bytes) if bytes.is_empty() => return None,
+        MockSource::Bytes(bytes) => MockState::Bytes { bytes, position: 0 },
+      };
+      Some(Self {
+        state,
+        remaining_draws: draws,
+      })
+    }
+
+    /// Whether the mock filled `dest`; `false` once it has served all its
+    /// draws
+    pub(super) fn fill_bytes(&mut self, dest: &mut [u8]) -> bool {
+      if self.remaining_draws == 0 {
+        return false;
+      }
+      self.remaining_draws -= 1;
+      match &mut self.state {
+        MockState::Seeded(rng) => rng.fill_bytes(dest),
+        MockState::Bytes { bytes, position } => {
+          for byte in dest.iter_mut() {
+            *byte = bytes[*position];
+            *position = (*position + 1) % bytes.len();
+          }
+        }
+      }
+      true
+    }
+  }
+
+  static MOCK_RNG: Mutex<Option<MockRng>> = Mutex::new(None);
+
+  /// Serves the next `draws` draws from `CommRng`, on any thread, from
+  /// `source` instead of the real generator. A draw is one call to
+  /// `fill_bytes`, `next_u32` or `next_u64`. An empty byte string clears
+  /// the mock.
+  pub fn set_mock_rng(source: MockSource, draws: u32) {
+    *MOCK_RNG.lock().unwrap_or_else(|e| e.into_inner()) =
+      MockRng::new(source, draws);
+  }
+
+  pub fn clear_mock_rng() {
+    *MOCK_RNG.lock().unwrap_or_else(|e| e.into_inner()) = None;
+  }
+
+  thread_local! {
+    static SCOPED_MOCK_RNG: RefCell<Option<MockRng>> =
+      const { RefCell::new(None) };
+  }
+
+  /// Puts the previous scoped mock back, even if `f` panics
+  struct RestoreScoped(Option<MockRng>);
+
+  impl Drop for RestoreScoped {
+    fn drop(&mut self) {
+      let previous = self.0.take();
+      SCOPED_MOCK_RNG.with(|scoped| *scoped.borrow_mut() = previous);
+    }
+  }
+
+  /// Runs `f` with every draw from `CommRng` on this thread served from
+  /// `source`, so an operation gives the same output for the same source
+  /// whatever runs on other threads meanwhile. Takes precedence over
+  /// `set_mock_rng`. Draws made on other threads, such as by a thread pool
+  /// `f` hands work to, aren't mocked. As with `set_mock_rng`, an empty byte
+  /// string mocks nothing: `f` runs without a scoped mock, even inside
+  /// another `with_mock_rng`.
+  pub fn with_mock_rng<T>(source: MockSource, f: impl FnOnce() -> T) -> T {
+    let mock = MockRng::new(source, u32::MAX);
+    let _restore =
+      RestoreScoped(SCOPED_MOCK_RNG.with(|scoped| scoped.replace(mock)));
+    f()
+  }
+
+  pub(super) fn fill_bytes(dest: &mut [u8]) -> bool {
+    let scoped = SCOPED_MOCK_RNG.with(|scoped| {
+      scoped
+        .borrow_mut()
+        .as_mut()
+        .map(|mock| mock.fill_bytes(dest))
+    });
+    if let Some(filled) = scoped {
+      return filled;
+    }
+    MOCK_RNG
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .as_mut()
+      .is_some_and(|mock| mock.fill_bytes(dest))
+  }
+
+  pub(super) fn next_u32() -> Option<u32> {
+    let mut bytes = [0; 4];
+    fill_bytes(&mut bytes).then(|| u32::from_le_bytes(bytes))
+  }
+
+  pub(super) fn next_u64() -> Option<u64> {
+    let mut bytes = [0; 8];
+    fill_bytes(&mut bytes).then(|| u64::from_le_bytes(bytes))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn current_seed() -> [u8; 32] {
+    with_rng(0, |rng| rng.get_seed())
+  }
+
+  #[test]
+  fn test_staleness() {
+    let mut rng = ThreadRng::seed(1, 0);
+    assert!(!rng.is_stale(1, 0, RESEED_AFTER_BYTES));
+    assert!(rng.is_stale(2, 0, 0));
+    assert!(rng.is_stale(1, 1, 0));
+    rng.remaining_bytes = 3;
+    assert!(rng.is_stale(1, 0, 4));
+  }
+
+  #[test]
+  fn test_force_reseed() {
+    let seed = current_seed();
+    force_reseed();
+    assert_ne!(current_seed(), seed);
+  }
+
+  #[test]
+  fn test_threads_have_separate_generators() {
+    let seed = current_seed();
+    let other = std::thread::spawn(current_seed).join().unwrap();
+    assert_ne!(other, seed);
+  }
+
+  #[cfg(feature = "mock-rng")]
+  #[test]
+  fn test_mock_draws() {
+    let mut mock =
+      mock::MockRng::new(MockSource::Bytes(vec![1, 2, 3]), 2).unwrap();
+    let mut first = [0; 4];
+    let mut second = [0; 4];
+    assert!(mock.fill_bytes(&mut first));
+    assert!(mock.fill_bytes(&mut second));
+    assert_eq!((first, second), ([1, 2, 3, 1], [2, 3, 1, 2]));
+    assert!(!mock.fill_bytes(&mut first));
+
+    let draw = |seed| {
+      let mut output = [0; 32];
+      mock::MockRng::new(MockSource::Seed(seed), 1)
+        .unwrap()
+        .fill_bytes(&mut output);
+      output
+    };
+    assert_eq!(draw([7; 32]), draw([7; 32]));
+    assert_ne!(draw([7; 32]), draw([8; 32]));
+    assert!(mock::MockRng::new(MockSource::Bytes(vec![]), 1).is_none());
+  }
+
+  #[cfg(feature = "mock-rng")]
+  #[test]
+  fn test_with_mock_rng() {
+    let draw = || {
+      let mut output = [0; 32];
+      CommRng.fill_bytes(&mut output);
+      output
+    };
+    let operation = || (draw(), draw());
+    let first = with_mock_rng(MockSource::Seed([7; 32]), operation);
+    let concurrent = std::thread::spawn(move || {
+      for _ in 0..1000 {
+        draw();
+      }
+    });
+    let second = with_mock_rng(MockSource::Seed([7; 32]), operation);
+    concurrent.join().unwrap();
+    assert_eq!(first, second);
+    assert_ne!(first.0, first.1);
+    assert_ne!(draw(), first.0);
+
+    let nested = with_mock_rng(MockSource::Bytes(vec![1]), || {
+      with_mock_rng(MockSource::Bytes(vec![2]), draw);
+      draw()
+    });
+    assert_eq!(nested, [1; 32]);
+
+    // Empty bytes don't mock the draws, not even an outer scope's
+    let unmocked = with_mock_rng(MockSource::Bytes(vec![1]), || {
+      with_mock_rng(MockSource::Bytes(vec![]), operation)
+    });
+    assert_ne!(unmocked.0, [1; 32]);
+    assert_ne!(unmocked.0, unmocked.1);
+  }
+}
@@ -0,0 +1,116 @@
+//! Planning a rotation of the server's static keypair.
+//!
+//! An OPAQUE envelope is sealed by the client, with a key only the password
+//! unlocks, and it commits to the server public key the user registered
+//! against. So the server can't move a record to a new key by itself: every
+//! user has to register again, which `upgrade` lets them do right after a
+//! login against the old key. What the server can do without the user is
+//! rewrite records in the current container format, and find the records it
+//! can't parse before the campaign starts.
+
+use crate::{
+  record::{upgrade_record_format, PasswordRecord, CURRENT_RECORD_FORMAT},
+  server_setup::ServerSetup,
+};
+
+pub struct RotationReport<Id> {
+  /// Records rewritten in the current container format, to store in place
+  /// of the ones passed in. The password files are unchanged.
+  pub resealed: Vec<(Id, Vec<u8>)>,
+  /// Users who have to register again before the old key can be retired:
+  /// everyone, if the key changes, otherwise only users whose records use
+  /// an outdated ciphersuite or KSF
+  pub needs_reregistration: Vec<Id>,
+  /// Records that don't parse. These users can't log in with either key.
+  pub invalid: Vec<Id>,
+}
+
+impl<Id> Default for RotationReport<Id> {
+  fn default() -> Self {
+    Self {
+      resealed: Vec::new(),
+      needs_reregistration: Vec::new(),
+      invalid: Vec::new(),
+    }
+  }
+}
+
+/// Sorts `records`, serialized in any container format, into a report for
+/// moving from `old_setup` to `new_setup`. Nothing is written anywhere; the
+/// caller stores the resealed records and schedules the re-registrations.
+pub fn plan_rotation<Id: Clone>(
+  old_setup: &ServerSetup,
+  new_setup: &ServerSetup,
+  records: impl IntoIterator<Item = (Id, Vec<u8>)>,
+) -> RotationReport<Id> {
+  let key_changes = old_setup.fingerprint() != new_setup.fingerprint();
+  let mut report = RotationReport::default();
+  for (id, serialized) in records {
+    let record = match PasswordRecord::deserialize(&serialized) {
+      Ok(record) => record,
+      Err(_) => {
+        report.invalid.push(id);
+        continue;
+      }
+    };
+    if record.format != CURRENT_RECORD_FORMAT {
+      match upgrade_record_format(&serialized) {
+        Ok(upgraded) => report.resealed.push((id.clone(), upgraded)),
+        Err(_) => {
+          report.invalid.push(id);
+          continue;
+        }
+      }
+    }
+    if key_changes || record.needs_reregistration() {
+      report.needs_reregistration.push(id);
+    }
+  }
+  report
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{OpaqueClient, OpaqueServer};
+
+  fn register(server_setup: &ServerSetup) -> PasswordRecord {
+    let server = OpaqueServer::from_setup(server_setup);
+    let (client_registering, registration_request) =
+      OpaqueClient::register(b"hunter2").unwrap();
+    let (server_registering, registration_response) =
+      server.register(&registration_request).unwrap();
+    server_registering
+      .finish(&client_registering.finish(&registration_response).unwrap())
+      .unwrap()
+  }
+
+  #[test]
+  fn test_plan_rotation() {
+    let old_setup = ServerSetup::generate();
+    let current = register(&old_setup).serialize();
+    let bare = register(&old_setup).password_file.serialize();
+    let records = || {
+      [
+        ("current", current.clone()),
+        ("bare", bare.clone()),
+        ("garbage", b"garbage".to_vec()),
+      ]
+    };
+
+    let report = plan_rotation(&old_setup, &ServerSetup::generate(), records());
+    assert_eq!(report.resealed.len(), 1);
+    let (id, resealed) = &report.resealed[0];
+    assert_eq!(*id, "bare");
+    assert_eq!(
+      PasswordRecord::deserialize(resealed).unwrap().format,
+      CURRENT_RECORD_FORMAT
+    );
+    assert_eq!(report.needs_reregistration, ["current", "bare"]);
+    assert_eq!(report.invalid, ["garbage"]);
+
+    let same_key = plan_rotation(&old_setup, &old_setup, records());
+    assert_eq!(same_key.resealed.len(), 1);
+    assert!(same_key.needs_reregistration.is_empty());
+  }
+}
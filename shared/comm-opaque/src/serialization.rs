@@ -0,0 +1,156 @@
+//! Canonical encoding for every message, record and state type this crate
+//! defines (as opposed to the opaque-ke types, which have their own
+//! serialization). Integers are fixed-width big-endian, and every
+//! variable-length field carries an explicit u32 length prefix, so there is
+//! exactly one way to encode a value and no two values share an encoding.
+//! Decoding is strict: truncated input and trailing bytes are both errors.
+
+use crate::Error;
+
+#[derive(Default)]
+pub struct Encoder {
+  output: Vec<u8>,
+}
+
+impl Encoder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn u8(mut self, value: u8) -> Self {
+    self.output.push(value);
+    self
+  }
+
+  pub fn u16(mut self, value: u16) -> Self {
+    self.output.extend_from_slice(&value.to_be_bytes());
+    self
+  }
+
+  pub fn u32(mut self, value: u32) -> Self {
+    self.output.extend_from_slice(&value.to_be_bytes());
+    self
+  }
+
+  pub fn u64(mut self, value: u64) -> Self {
+    self.output.extend_from_slice(&value.to_be_bytes());
+    self
+  }
+
+  /// A field whose length is fixed by the format, such as a magic number or
+  /// a key of known size, written without a length prefix
+  pub fn fixed(mut self, bytes: &[u8]) -> Self {
+    self.output.extend_from_slice(bytes);
+    self
+  }
+
+  /// A variable-length field, written with a u32 length prefix
+  pub fn bytes(self, bytes: &[u8]) -> Self {
+    let len = u32::try_from(bytes.len())
+      .expect("serialized fields are smaller than 4 GiB");
+    self.u32(len).fixed(bytes)
+  }
+
+  pub fn finish(self) -> Vec<u8> {
+    self.output
+  }
+}
+
+pub struct Decoder<'a> {
+  input: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+  pub fn new(input: &'a [u8]) -> Self {
+    Self { input }
+  }
+
+  pub fn u8(&mut self) -> Result<u8, Error> {
+    Ok(self.fixed(1)?[0])
+  }
+
+  pub fn u16(&mut self) -> Result<u16, Error> {
+    let bytes = self.fixed(2)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+  }
+
+  pub fn u32(&mut self) -> Result<u32, Error> {
+    let bytes = self.fixed(4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  }
+
+  pub fn u64(&mut self) -> Result<u64, Error> {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(self.fixed(8)?);
+    Ok(u64::from_be_bytes(bytes))
+  }
+
+  pub fn fixed(&mut self, len: usize) -> Result<&'a [u8], Error> {
+    if self.input.len() < len {
+      return Err(Error::Serialization);
+    }
+    let (field, rest) = self.input.split_at(len);
+    self.input = rest;
+    Ok(field)
+  }
+
+  pub fn bytes(&mut self) -> Result<&'a [u8], Error> {
+    let len = self.u32()? as usize;
+    self.fixed(len)
+  }
+
+  /// Fails if any input is left over
+  pub fn finish(self) -> Result<(), Error> {
+    if !self.input.is_empty() {
+      return Err(Error::Serialization);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_round_trip() {
+    let encoded = Encoder::new()
+      .fixed(b"MAGC")
+      .u8(1)
+      .u16(515)
+      .u32(7)
+      .u64(u64::MAX)
+      .bytes(b"variable")
+      .bytes(b"")
+      .finish();
+    let mut decoder = Decoder::new(&encoded);
+    assert_eq!(decoder.fixed(4).unwrap(), b"MAGC");
+    assert_eq!(decoder.u8().unwrap(), 1);
+    assert_eq!(decoder.u16().unwrap(), 515);
+    assert_eq!(decoder.u32().unwrap(), 7);
+    assert_eq!(decoder.u64().unwrap(), u64::MAX);
+    assert_eq!(decoder.bytes().unwrap(), b"variable");
+    assert_eq!(decoder.bytes().unwrap(), b"");
+    assert!(decoder.finish().is_ok());
+  }
+
+  #[test]
+  fn test_fields_are_unambiguous() {
+    let a = Encoder::new().bytes(b"ab").bytes(b"c").finish();
+    let b = Encoder::new().bytes(b"a").bytes(b"bc").finish();
+    assert_ne!(a, b);
+  }
+
+  #[test]
+  fn test_strict_decoding() {
+    let encoded = Encoder::new().bytes(b"field").finish();
+    let mut truncated = Decoder::new(&encoded[..encoded.len() - 1]);
+    assert!(matches!(truncated.bytes(), Err(Error::Serialization)));
+
+    let mut trailing = encoded.clone();
+    trailing.push(0);
+    let mut decoder = Decoder::new(&trailing);
+    decoder.bytes().unwrap();
+    assert!(matches!(decoder.finish(), Err(Error::Serialization)));
+  }
+}
@@ -0,0 +1,135 @@
+//! The server's static keypair in a form that can be handed to worker
+//! processes through the environment, plus a fingerprint so a cluster can
+//! check that every worker loaded the same key.
+//!
+//! The sealed blob is checksummed and versioned, not encrypted: a truncated
+//! or mangled variable fails to load instead of silently producing a
+//! different key, but the environment still has to be protected like the
+//! key file itself.
+
+use std::env;
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use opaque_ke::{ciphersuite::CipherSuite, keypair::KeyPair};
+use sha2::{Digest, Sha256};
+
+use crate::{
+  rng::CommRng,
+  serialization::{Decoder, Encoder},
+  Cipher, Error,
+};
+
+/// Variable `ServerSetup::from_env` reads by default
+pub const SERVER_SETUP_ENV_VAR: &str = "COMM_OPAQUE_SERVER_SETUP";
+
+pub(crate) const SEALED_MAGIC: &[u8; 4] = b"cOSS";
+pub(crate) const SEALED_FORMAT: u8 = 1;
+const CHECKSUM_LEN: usize = 32;
+const FINGERPRINT_LABEL: &[u8] = b"comm-opaque server setup fingerprint";
+
+#[derive(Clone)]
+pub struct ServerSetup {
+  keypair: KeyPair<RistrettoPoint>,
+}
+
+impl ServerSetup {
+  pub fn generate() -> Self {
+    Self {
+      keypair: Cipher::generate_random_keypair(&mut CommRng),
+    }
+  }
+
+  pub fn from_private_key(private_key: &[u8]) -> Result<Self, Error> {
+    // opaque-ke panics on keys of the wrong length
+    if private_key.len() != 32 {
+      return Err(Error::InvalidServerSetup);
+    }
+    let keypair = KeyPair::from_private_key_slice(private_key)
+      .map_err(|_| Error::InvalidServerSetup)?;
+    Ok(Self { keypair })
+  }
+
+  pub fn keypair(&self) -> &KeyPair<RistrettoPoint> {
+    &self.keypair
+  }
+
+  pub fn seal(&self) -> String {
+    let body = Encoder::new()
+      .fixed(SEALED_MAGIC)
+      .u8(SEALED_FORMAT)
+      .bytes(&self.keypair.private().to_arr())
+      .finish();
+    let checksum = Sha256::digest(&body);
+    hex::encode([&body[..], &checksum[..]].concat())
+  }
+
+  pub fn unseal(sealed: &str) -> Result<Self, Error> {
+    let bytes =
+      hex::decode(sealed.trim()).map_err(|_| Error::InvalidServerSetup)?;
+    if bytes.len() < CHECKSUM_LEN {
+      return Err(Error::InvalidServerSetup);
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if Sha256::digest(body).as_slice() != checksum {
+      return Err(Error::InvalidServerSetup);
+    }
+    let mut decoder = Decoder::new(body);
+    if decoder.fixed(SEALED_MAGIC.len())? != SEALED_MAGIC
+      || decoder.u8()? != SEALED_FORMAT
+    {
+      return Err(Error::InvalidServerSetup);
+    }
+    let private_key = decoder.bytes()?;
+    decoder.finish()?;
+    Self::from_private_key(private_key)
+  }
+
+  /// Reads a sealed setup from `var`, or `SERVER_SETUP_ENV_VAR` if none is
+  /// given
+  pub fn from_env(var: Option<&str>) -> Result<Self, Error> {
+    let sealed = env::var(var.unwrap_or(SERVER_SETUP_ENV_VAR))
+      .map_err(|_| Error::InvalidServerSetup)?;
+    Self::unseal(&sealed)
+  }
+
+  /// Hex-encoded hash of the public key. Safe to log and to send between
+  /// processes; two setups have the same fingerprint only if they hold the
+  /// same key.
+  pub fn fingerprint(&self) -> String {
+    let input = Encoder::new()
+      .bytes(FINGERPRINT_LABEL)
+      .bytes(&self.keypair.public().to_arr())
+      .finish();
+    hex::encode(Sha256::digest(&input))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_seal_round_trip() {
+    let setup = ServerSetup::generate();
+    let unsealed = ServerSetup::unseal(&setup.seal()).unwrap();
+    assert_eq!(unsealed.fingerprint(), setup.fingerprint());
+    assert_ne!(ServerSetup::generate().fingerprint(), setup.fingerprint());
+  }
+
+  #[test]
+  fn test_damaged_blob_rejected() {
+    let sealed = ServerSetup::generate().seal();
+    let mut flipped = sealed.clone().into_bytes();
+    flipped[20] = if flipped[20] == b'0' { b'1' } else { b'0' };
+    for damaged in [
+      &sealed[..sealed.len() - 2],
+      std::str::from_utf8(&flipped).unwrap(),
+      "",
+    ] {
+      assert!(matches!(
+        ServerSetup::unseal(damaged),
+        Err(Error::InvalidServerSetup)
+      ));
+    }
+  }
+}
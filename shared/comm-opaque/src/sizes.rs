@@ -0,0 +1,260 @@
+//! Byte lengths of every message, state, key and record this build writes
+//! with the current ciphersuite and formats, for callers that size buffers
+//! and columns or validate lengths before parsing.
+//!
+//! Client states hold the password, so their entries (`..._overhead`) are
+//! lengths without it: add the password's length in bytes.
+//!
+//! Every length is for `Cipher`. Keys and the session and export keys
+//! follow its group and hash, and opaque-ke's messages are fixed by them
+//! too; none of them apply to `FipsCipher`, whose P-256 points and SHA-256
+//! outputs have other lengths.
+
+use digest::{generic_array::typenum::Unsigned, Digest};
+use opaque_ke::{ciphersuite::CipherSuite, group::Group};
+
+use crate::{
+  attestation::{ATTESTATION_LEN, ROOT_KEY_LEN},
+  derivation::DERIVED_KEY_LEN,
+  keystore::{NONCE_LEN, PEPPER_LEN, SALT_LEN},
+  version::TAG_LEN,
+  Cipher,
+};
+
+type CipherGroup = <Cipher as CipherSuite>::Group;
+type CipherHash = <Cipher as CipherSuite>::Hash;
+
+pub const REGISTRATION_REQUEST_LEN: usize = 32;
+pub const REGISTRATION_RESPONSE_LEN: usize = 64;
+pub const REGISTRATION_UPLOAD_LEN: usize = 161;
+pub const CREDENTIAL_REQUEST_LEN: usize = 98;
+pub const CREDENTIAL_RESPONSE_LEN: usize = 323;
+pub const CREDENTIAL_FINALIZATION_LEN: usize = 64;
+
+pub const PRIVATE_KEY_LEN: usize = <CipherGroup as Group>::ScalarLen::USIZE;
+pub const PUBLIC_KEY_LEN: usize = <CipherGroup as Group>::ElemLen::USIZE;
+pub const SESSION_KEY_LEN: usize = <CipherHash as Digest>::OutputSize::USIZE;
+pub const EXPORT_KEY_LEN: usize = <CipherHash as Digest>::OutputSize::USIZE;
+/// SHA-512 whatever the suite (see `api::transcript_hash`)
+pub const TRANSCRIPT_HASH_LEN: usize =
+  <sha2::Sha512 as Digest>::OutputSize::USIZE;
+
+/// opaque-ke's `ServerRegistration`, the bare record
+pub const PASSWORD_FILE_LEN: usize = 193;
+const CLIENT_REGISTRATION_OVERHEAD: usize = 64;
+const CLIENT_LOGIN_OVERHEAD: usize = 198;
+const SERVER_LOGIN_LEN: usize = 192;
+
+/// Magic and format
+const HEADER_LEN: usize = 5;
+/// Of `bytes` fields
+const LENGTH_PREFIX_LEN: usize = 4;
+const POLY1305_TAG_LEN: usize = 16;
+
+pub const SIZES: &[(&str, usize)] = &[
+  ("registration_request", REGISTRATION_REQUEST_LEN),
+  ("registration_response", REGISTRATION_RESPONSE_LEN),
+  ("registration_upload", REGISTRATION_UPLOAD_LEN),
+  ("credential_request", CREDENTIAL_REQUEST_LEN),
+  ("credential_response", CREDENTIAL_RESPONSE_LEN),
+  ("credential_finalization", CREDENTIAL_FINALIZATION_LEN),
+  ("tagged_message_overhead", TAG_LEN),
+  ("reregistration_tag", 64),
+  (
+    "client_registration_state_overhead",
+    CLIENT_REGISTRATION_OVERHEAD,
+  ),
+  (
+    "client_login_state_overhead",
+    HEADER_LEN
+      + LENGTH_PREFIX_LEN
+      + CREDENTIAL_REQUEST_LEN
+      + LENGTH_PREFIX_LEN
+      + CLIENT_LOGIN_OVERHEAD,
+  ),
+  (
+    "server_login_state",
+    HEADER_LEN + 2 + TRANSCRIPT_HASH_LEN + LENGTH_PREFIX_LEN + SERVER_LOGIN_LEN,
+  ),
+  (
+    "sealed_server_login_state",
+    HEADER_LEN
+      + NONCE_LEN
+      + HEADER_LEN
+      + 2
+      + TRANSCRIPT_HASH_LEN
+      + LENGTH_PREFIX_LEN
+      + SERVER_LOGIN_LEN
+      + POLY1305_TAG_LEN,
+  ),
+  ("server_private_key", PRIVATE_KEY_LEN),
+  ("server_public_key", PUBLIC_KEY_LEN),
+  ("session_key", SESSION_KEY_LEN),
+  ("export_key", EXPORT_KEY_LEN),
+  ("transcript_hash", TRANSCRIPT_HASH_LEN),
+  ("derived_key", DERIVED_KEY_LEN),
+  ("pepper", PEPPER_LEN),
+  ("attestation_root_key", ROOT_KEY_LEN),
+  ("server_key_attestation", ATTESTATION_LEN),
+  ("password_file", PASSWORD_FILE_LEN),
+  (
+    "password_record",
+    HEADER_LEN + 2 + LENGTH_PREFIX_LEN + PASSWORD_FILE_LEN,
+  ),
+  // Hex-encoded, so two characters per byte
+  (
+    "sealed_server_setup",
+    2 * (HEADER_LEN + LENGTH_PREFIX_LEN + PRIVATE_KEY_LEN + 32),
+  ),
+  (
+    "keystore",
+    HEADER_LEN
+      + 1
+      + SALT_LEN
+      + NONCE_LEN
+      + 2 * LENGTH_PREFIX_LEN
+      + PRIVATE_KEY_LEN
+      + PEPPER_LEN
+      + POLY1305_TAG_LEN,
+  ),
+  // Magic, format, method, length and checksum, then the payload's prefix
+  (
+    "compressed_overhead",
+    HEADER_LEN + 1 + 4 + 32 + LENGTH_PREFIX_LEN,
+  ),
+];
+
+/// The length `SIZES` gives `name`, if there is such an entry
+pub fn size(name: &str) -> Option<usize> {
+  SIZES
+    .iter()
+    .find(|(entry, _)| *entry == name)
+    .map(|(_, len)| *len)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    api::{ClientLoggingIn, LoginStateKey},
+    attestation, client, compression,
+    derivation::derive_token,
+    keystore::Keystore,
+    ksf::KSF_DEFAULT,
+    server_setup::ServerSetup,
+    upgrade::{self, UpgradeSession},
+    version, OpaqueClient, OpaqueServer,
+  };
+
+  const PASSWORD: &[u8] = b"hunter2";
+
+  #[test]
+  fn test_sizes_match_what_is_written() {
+    let setup = ServerSetup::generate();
+    let server = OpaqueServer::from_setup(&setup);
+    let registration_start = client::register_start(PASSWORD).unwrap();
+    assert_eq!(
+      registration_start.state.serialize().len(),
+      size("client_registration_state_overhead").unwrap() + PASSWORD.len()
+    );
+    let (client_registering, registration_request) =
+      OpaqueClient::register(PASSWORD).unwrap();
+    let (server_registering, registration_response) =
+      server.register(&registration_request).unwrap();
+    let registration_upload =
+      client_registering.finish(&registration_response).unwrap();
+    let record = server_registering.finish(&registration_upload).unwrap();
+    let serialized_record = record.serialize();
+    let password_file = record.password_file.serialize();
+
+    let (client_logging_in, credential_request) =
+      OpaqueClient::login(PASSWORD).unwrap();
+    let client_state = client_logging_in.serialize().unwrap();
+    let (server_logging_in, credential_response) =
+      server.login(record, &credential_request).unwrap();
+    let logged_in = ClientLoggingIn::deserialize(&client_state)
+      .unwrap()
+      .finish(&credential_response, server_logging_in.ksf())
+      .unwrap();
+    let root_key = attestation::generate_root_key();
+    let (_, reregistration_tag) = upgrade::client_reregistration_start(
+      &UpgradeSession::new(&logged_in.session_key),
+      PASSWORD,
+    )
+    .unwrap();
+
+    let written = [
+      ("registration_request", registration_request.len()),
+      ("registration_response", registration_response.len()),
+      ("registration_upload", registration_upload.len()),
+      ("credential_request", credential_request.len()),
+      ("credential_response", credential_response.len()),
+      (
+        "credential_finalization",
+        logged_in.credential_finalization.len(),
+      ),
+      (
+        "client_login_state_overhead",
+        client_state.len() - PASSWORD.len(),
+      ),
+      (
+        "server_login_state",
+        server_logging_in.serialize().unwrap().len(),
+      ),
+      (
+        "sealed_server_login_state",
+        server_logging_in
+          .seal(&LoginStateKey::derive(setup.keypair()).unwrap())
+          .unwrap()
+          .len(),
+      ),
+      (
+        "server_private_key",
+        setup.keypair().private().to_arr().len(),
+      ),
+      ("server_public_key", logged_in.server_public_key.len()),
+      ("session_key", logged_in.session_key.len()),
+      ("export_key", logged_in.export_key.len()),
+      ("reregistration_tag", reregistration_tag.len()),
+      ("transcript_hash", logged_in.transcript_hash.len()),
+      (
+        "derived_key",
+        derive_token(&logged_in.session_key, "").unwrap().len(),
+      ),
+      ("attestation_root_key", root_key.len()),
+      (
+        "server_key_attestation",
+        attestation::attest_server_key(&root_key, &logged_in.server_public_key)
+          .unwrap()
+          .len(),
+      ),
+      (
+        "tagged_message_overhead",
+        version::tag_message(
+          version::CURRENT_WIRE_VERSION,
+          version::MessageType::CredentialRequest,
+          &[],
+        )
+        .len(),
+      ),
+      ("password_file", password_file.len()),
+      ("password_record", serialized_record.len()),
+      ("sealed_server_setup", setup.seal().len()),
+      (
+        "keystore",
+        Keystore::generate()
+          .seal_with_ksf(PASSWORD, KSF_DEFAULT)
+          .unwrap()
+          .len(),
+      ),
+      (
+        "compressed_overhead",
+        compression::compress(&[]).unwrap().len(),
+      ),
+    ];
+    for (name, len) in written {
+      assert_eq!(size(name), Some(len), "{}", name);
+    }
+    assert_eq!(size("no such thing"), None);
+  }
+}
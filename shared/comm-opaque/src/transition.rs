@@ -0,0 +1,272 @@
+//! Server-side login for the period during which some users still only have
+//! a legacy bcrypt hash on file. The decision of which mechanism to use is
+//! made here rather than by the caller, so that a user who already has an
+//! OPAQUE registration can never be authenticated by the weaker legacy path.
+
+use opaque_ke::{
+  CredentialFinalization, CredentialRequest, ServerLogin,
+  ServerLoginStartResult,
+};
+
+use crate::{
+  fips::{self, Primitive},
+  key_backend::{self, ServerKeyBackend},
+  legacy,
+  metrics::{self, Operation},
+  policy::version_policy,
+  record::PasswordRecord,
+  replay,
+  rng::CommRng,
+  Cipher, Error,
+};
+
+/// Whatever the server has on file for the user logging in
+pub struct StoredCredentials {
+  pub record: Option<PasswordRecord>,
+  pub legacy_hash: Option<String>,
+}
+
+/// What the client sent to start logging in
+pub enum LoginRequest {
+  Opaque(Box<CredentialRequest<Cipher>>),
+  Legacy(String),
+}
+
+pub enum LoginOutcome {
+  /// The user has an OPAQUE registration. The credential response should be
+  /// sent to the client and the state kept around for `ServerLogin::finish`.
+  /// `ksf` is the KSF parameter set the client has to finish the login with.
+  /// If `needs_reregistration` is set, the client should be asked to register
+  /// again once the login completes (see `upgrade`).
+  OpaqueStarted {
+    result: Box<ServerLoginStartResult<Cipher>>,
+    ksf: u8,
+    needs_reregistration: bool,
+  },
+  /// The legacy password matched. The user is authenticated, and the record
+  /// should be stored in place of the legacy hash.
+  LegacyMigrated(PasswordRecord),
+}
+
+/// Starts a login, preferring OPAQUE and falling back to verifying the legacy
+/// hash (followed by automatic OPAQUE enrollment) only for users without an
+/// OPAQUE registration.
+///
+/// A client that attempts OPAQUE for a user who hasn't been migrated yet gets
+/// `Error::OpaqueRegistrationNotFound`, which is the signal to retry with the
+/// plaintext password. Records older than the version policy allows are
+/// rejected with `Error::VersionBelowMinimum`; those users have to go through
+/// a password reset. A credential request seen before within the replay
+/// window fails with `Error::ReplayedMessage`.
+pub fn server_login_start(
+  stored: StoredCredentials,
+  request: LoginRequest,
+  server_key: &dyn ServerKeyBackend,
+) -> Result<LoginOutcome, Error> {
+  fips::require_approved(Primitive::Ristretto255Suite)?;
+  metrics::time(Operation::LoginStart, || {
+    login_start(stored, request, server_key)
+  })
+}
+
+fn login_start(
+  stored: StoredCredentials,
+  request: LoginRequest,
+  server_key: &dyn ServerKeyBackend,
+) -> Result<LoginOutcome, Error> {
+  if let LoginRequest::Opaque(credential_request) = &request {
+    replay::check(
+      &server_key.public_key().to_arr(),
+      &credential_request.serialize()?,
+    )?;
+  }
+  match (stored.record, request) {
+    (Some(record), LoginRequest::Opaque(credential_request)) => {
+      version_policy().check_record(&record)?;
+      let ksf = record.suite_version.ksf;
+      let needs_reregistration = record.needs_reregistration();
+      let server_login_start_result = key_backend::login_start(
+        &mut CommRng,
+        record.password_file,
+        server_key,
+        *credential_request,
+      )?;
+      Ok(LoginOutcome::OpaqueStarted {
+        result: Box::new(server_login_start_result),
+        ksf,
+        needs_reregistration,
+      })
+    }
+    (Some(_), LoginRequest::Legacy(_)) => Err(Error::LegacyLoginNotAllowed),
+    (None, request) => {
+      let legacy_hash = stored.legacy_hash.ok_or(Error::CredentialsNotFound)?;
+      let password = match request {
+        LoginRequest::Opaque(_) => {
+          return Err(Error::OpaqueRegistrationNotFound)
+        }
+        LoginRequest::Legacy(password) => password,
+      };
+      legacy::migrate(&legacy_hash, &password, server_key)?
+        .map(LoginOutcome::LegacyMigrated)
+        .ok_or(Error::InvalidCredentials)
+    }
+  }
+}
+
+/// Completes an OPAQUE login started by `server_login_start`, returning the
+/// session key. Fails if the client didn't prove knowledge of the password.
+pub fn server_login_finish(
+  server_login: ServerLogin<Cipher>,
+  credential_finalization: CredentialFinalization<Cipher>,
+) -> Result<Vec<u8>, Error> {
+  fips::require_approved(Primitive::Ristretto255Suite)?;
+  metrics::time(Operation::LoginFinish, || {
+    Ok(server_login.finish(credential_finalization)?.session_key)
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use opaque_ke::{
+    ciphersuite::CipherSuite, rand::rngs::OsRng, ClientLogin,
+    ClientLoginFinishParameters, ClientLoginStartParameters,
+    ClientLoginStartResult,
+  };
+
+  const PASSWORD: &str = "hunter2";
+
+  fn client_login_start() -> ClientLoginStartResult<Cipher> {
+    ClientLogin::<Cipher>::start(
+      &mut OsRng,
+      PASSWORD.as_bytes(),
+      ClientLoginStartParameters::default(),
+    )
+    .unwrap()
+  }
+
+  fn legacy_only() -> StoredCredentials {
+    StoredCredentials {
+      record: None,
+      legacy_hash: Some(bcrypt::hash(PASSWORD, 4).unwrap()),
+    }
+  }
+
+  #[test]
+  fn test_legacy_user_is_migrated_then_logs_in_with_opaque() {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+
+    let opaque_attempt = server_login_start(
+      legacy_only(),
+      LoginRequest::Opaque(Box::new(client_login_start().message)),
+      &server_keypair,
+    );
+    assert!(matches!(
+      opaque_attempt,
+      Err(Error::OpaqueRegistrationNotFound)
+    ));
+
+    let record = match server_login_start(
+      legacy_only(),
+      LoginRequest::Legacy(PASSWORD.to_string()),
+      &server_keypair,
+    ) {
+      Ok(LoginOutcome::LegacyMigrated(record)) => record,
+      _ => panic!("expected legacy login to migrate the user"),
+    };
+
+    let client_start_result = client_login_start();
+    let server_start_result = match server_login_start(
+      StoredCredentials {
+        record: Some(record),
+        legacy_hash: None,
+      },
+      LoginRequest::Opaque(Box::new(client_start_result.message)),
+      &server_keypair,
+    ) {
+      Ok(LoginOutcome::OpaqueStarted {
+        result,
+        needs_reregistration: false,
+        ..
+      }) => result,
+      _ => panic!("expected OPAQUE login to start"),
+    };
+    let client_finish_result = client_start_result
+      .state
+      .finish(
+        server_start_result.message,
+        ClientLoginFinishParameters::default(),
+      )
+      .unwrap();
+    let session_key = server_login_finish(
+      server_start_result.state,
+      client_finish_result.message,
+    )
+    .unwrap();
+    assert_eq!(client_finish_result.session_key, session_key);
+  }
+
+  #[test]
+  fn test_legacy_login_rejected_once_migrated() {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let record = match server_login_start(
+      legacy_only(),
+      LoginRequest::Legacy(PASSWORD.to_string()),
+      &server_keypair,
+    ) {
+      Ok(LoginOutcome::LegacyMigrated(record)) => record,
+      _ => panic!("expected legacy login to migrate the user"),
+    };
+    let result = server_login_start(
+      StoredCredentials {
+        record: Some(record),
+        legacy_hash: Some(bcrypt::hash(PASSWORD, 4).unwrap()),
+      },
+      LoginRequest::Legacy(PASSWORD.to_string()),
+      &server_keypair,
+    );
+    assert!(matches!(result, Err(Error::LegacyLoginNotAllowed)));
+  }
+
+  #[test]
+  fn test_wrong_legacy_password() {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let result = server_login_start(
+      legacy_only(),
+      LoginRequest::Legacy("hunter3".to_string()),
+      &server_keypair,
+    );
+    assert!(matches!(result, Err(Error::InvalidCredentials)));
+  }
+
+  #[test]
+  fn test_unknown_user() {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let result = server_login_start(
+      StoredCredentials {
+        record: None,
+        legacy_hash: None,
+      },
+      LoginRequest::Legacy(PASSWORD.to_string()),
+      &server_keypair,
+    );
+    assert!(matches!(result, Err(Error::CredentialsNotFound)));
+  }
+
+  #[test]
+  fn test_replayed_credential_request_rejected() {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let credential_request = client_login_start().message.serialize().unwrap();
+    let attempt = || {
+      server_login_start(
+        legacy_only(),
+        LoginRequest::Opaque(Box::new(
+          CredentialRequest::deserialize(&credential_request).unwrap(),
+        )),
+        &server_keypair,
+      )
+    };
+    assert!(matches!(attempt(), Err(Error::OpaqueRegistrationNotFound)));
+    assert!(matches!(attempt(), Err(Error::ReplayedMessage)));
+  }
+}
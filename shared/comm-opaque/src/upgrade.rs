@@ -0,0 +1,199 @@
+//! Re-registration within an authenticated login session, used to move
+//! records created under an older ciphersuite or KSF to the current one.
+//!
+//! Both sides derive a MAC key from the session key of the login that just
+//! completed and tag every re-registration message with it, so the server
+//! only accepts a new password file from the client it just authenticated.
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac, NewMac};
+use opaque_ke::{
+  keypair::KeyPair, ClientRegistration, ClientRegistrationFinishParameters,
+  ClientRegistrationStartResult, RegistrationRequest, RegistrationResponse,
+  RegistrationUpload, ServerRegistration, ServerRegistrationStartResult,
+};
+use sha2::Sha512;
+
+use crate::{
+  fips::{self, Primitive},
+  ksf::{registration_ksf, with_ksf},
+  metrics::{self, Operation},
+  policy::version_policy,
+  record::{PasswordRecord, CURRENT_SUITE_VERSION},
+  rng::CommRng,
+  serialization::Encoder,
+  Cipher, Error,
+};
+
+const UPGRADE_KEY_INFO: &[u8] = b"comm-opaque reregistration";
+const REQUEST_LABEL: &[u8] = b"RegistrationRequest";
+const UPLOAD_LABEL: &[u8] = b"RegistrationUpload";
+
+/// MAC key bound to a single completed login
+pub struct UpgradeSession {
+  key: [u8; 64],
+}
+
+impl UpgradeSession {
+  pub fn new(session_key: &[u8]) -> Self {
+    let mut key = [0u8; 64];
+    Hkdf::<Sha512>::new(None, session_key)
+      .expand(UPGRADE_KEY_INFO, &mut key)
+      .expect("64 bytes is a valid HKDF-SHA512 output length");
+    Self { key }
+  }
+
+  fn mac(&self, label: &[u8], message: &[u8]) -> Hmac<Sha512> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(&self.key)
+      .expect("HMAC accepts keys of any length");
+    mac.update(&Encoder::new().bytes(label).bytes(message).finish());
+    mac
+  }
+
+  fn tag(&self, label: &[u8], message: &[u8]) -> Vec<u8> {
+    self.mac(label, message).finalize().into_bytes().to_vec()
+  }
+
+  fn verify(
+    &self,
+    label: &[u8],
+    message: &[u8],
+    tag: &[u8],
+  ) -> Result<(), Error> {
+    self
+      .mac(label, message)
+      .verify(tag)
+      .map_err(|_| Error::InvalidUpgradeTag)
+  }
+}
+
+impl Drop for UpgradeSession {
+  fn drop(&mut self) {
+    self.key.iter_mut().for_each(|byte| *byte = 0);
+  }
+}
+
+/// Returns the registration start result along with the tag to send next to
+/// the serialized registration request
+pub fn client_reregistration_start(
+  session: &UpgradeSession,
+  password: &[u8],
+) -> Result<(ClientRegistrationStartResult<Cipher>, Vec<u8>), Error> {
+  fips::require_approved(Primitive::Ristretto255Suite)?;
+  let client_start_result =
+    ClientRegistration::<Cipher>::start(&mut CommRng, password)?;
+  let tag =
+    session.tag(REQUEST_LABEL, &client_start_result.message.serialize());
+  Ok((client_start_result, tag))
+}
+
+/// Returns the registration upload message along with its tag. Like
+/// `client::register_finish`, runs the KSF with the parameter set chosen by
+/// `ksf::init_ksf`.
+pub fn client_reregistration_finish(
+  session: &UpgradeSession,
+  client_registration: ClientRegistration<Cipher>,
+  registration_response: RegistrationResponse<Cipher>,
+) -> Result<(RegistrationUpload<Cipher>, Vec<u8>), Error> {
+  fips::require_approved(Primitive::Ristretto255Suite)?;
+  let client_finish_result = with_ksf(registration_ksf(), || {
+    client_registration.finish(
+      &mut CommRng,
+      registration_response,
+      ClientRegistrationFinishParameters::default(),
+    )
+  })??;
+  let tag =
+    session.tag(UPLOAD_LABEL, &client_finish_result.message.serialize());
+  Ok((client_finish_result.message, tag))
+}
+
+pub fn server_reregistration_start(
+  session: &UpgradeSession,
+  registration_request: &[u8],
+  tag: &[u8],
+  server_keypair: &KeyPair<RistrettoPoint>,
+) -> Result<ServerRegistrationStartResult<Cipher>, Error> {
+  fips::require_approved(Primitive::Ristretto255Suite)?;
+  version_policy().check_suite_version(CURRENT_SUITE_VERSION)?;
+  session.verify(REQUEST_LABEL, registration_request, tag)?;
+  Ok(ServerRegistration::<Cipher>::start(
+    &mut CommRng,
+    RegistrationRequest::deserialize(registration_request)?,
+    server_keypair.public(),
+  )?)
+}
+
+/// Returns the record to store in place of the outdated one, stamped with
+/// the KSF parameter set chosen by `ksf::init_ksf`
+pub fn server_reregistration_finish(
+  session: &UpgradeSession,
+  server_registration: ServerRegistration<Cipher>,
+  registration_upload: &[u8],
+  tag: &[u8],
+) -> Result<PasswordRecord, Error> {
+  fips::require_approved(Primitive::Ristretto255Suite)?;
+  metrics::time(Operation::Reregistration, || {
+    session.verify(UPLOAD_LABEL, registration_upload, tag)?;
+    let password_file = server_registration
+      .finish(RegistrationUpload::deserialize(registration_upload)?)?;
+    Ok(PasswordRecord::with_ksf(password_file, registration_ksf()))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use opaque_ke::{ciphersuite::CipherSuite, rand::rngs::OsRng};
+
+  const PASSWORD: &[u8] = b"hunter2";
+
+  #[test]
+  fn test_reregistration_in_session() {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let client_session = UpgradeSession::new(b"session key");
+    let server_session = UpgradeSession::new(b"session key");
+
+    let (client_start_result, request_tag) =
+      client_reregistration_start(&client_session, PASSWORD).unwrap();
+    let server_start_result = server_reregistration_start(
+      &server_session,
+      &client_start_result.message.serialize(),
+      &request_tag,
+      &server_keypair,
+    )
+    .unwrap();
+    let (upload, upload_tag) = client_reregistration_finish(
+      &client_session,
+      client_start_result.state,
+      server_start_result.message,
+    )
+    .unwrap();
+    let record = server_reregistration_finish(
+      &server_session,
+      server_start_result.state,
+      &upload.serialize(),
+      &upload_tag,
+    )
+    .unwrap();
+    assert!(!record.needs_reregistration());
+  }
+
+  #[test]
+  fn test_reregistration_rejected_from_other_session() {
+    let server_keypair = Cipher::generate_random_keypair(&mut OsRng);
+    let client_session = UpgradeSession::new(b"session key");
+    let server_session = UpgradeSession::new(b"other session key");
+
+    let (client_start_result, request_tag) =
+      client_reregistration_start(&client_session, PASSWORD).unwrap();
+    let result = server_reregistration_start(
+      &server_session,
+      &client_start_result.message.serialize(),
+      &request_tag,
+      &server_keypair,
+    );
+    assert!(matches!(result, Err(Error::InvalidUpgradeTag)));
+  }
+}
@@ -0,0 +1,140 @@
+//! Wire format versioning. Every message sent between client and server can
+//! be wrapped in a small header carrying the format version and message type,
+//! and the two sides agree on a version up front so that a fleet of clients
+//! on different releases can talk to the same server during a rollout.
+
+use crate::{policy::version_policy, Error};
+
+pub const CURRENT_WIRE_VERSION: u16 = 1;
+/// Supported wire versions, newest first
+pub const SUPPORTED_WIRE_VERSIONS: &[u16] = &[CURRENT_WIRE_VERSION];
+
+pub(crate) const TAG_LEN: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageType {
+  RegistrationRequest = 1,
+  RegistrationResponse = 2,
+  RegistrationUpload = 3,
+  CredentialRequest = 4,
+  CredentialResponse = 5,
+  CredentialFinalization = 6,
+}
+
+impl TryFrom<u8> for MessageType {
+  type Error = Error;
+
+  fn try_from(value: u8) -> Result<Self, Self::Error> {
+    match value {
+      1 => Ok(Self::RegistrationRequest),
+      2 => Ok(Self::RegistrationResponse),
+      3 => Ok(Self::RegistrationUpload),
+      4 => Ok(Self::CredentialRequest),
+      5 => Ok(Self::CredentialResponse),
+      6 => Ok(Self::CredentialFinalization),
+      _ => Err(Error::InvalidMessageTag),
+    }
+  }
+}
+
+/// Picks the highest version supported by both sides and allowed by the
+/// version policy, or `None` if there is no such version
+pub fn negotiate_version(
+  client_supported: &[u16],
+  server_supported: &[u16],
+) -> Option<u16> {
+  let policy = version_policy();
+  client_supported
+    .iter()
+    .filter(|version| server_supported.contains(version))
+    .filter(|&&version| policy.check_wire_version(version).is_ok())
+    .max()
+    .copied()
+}
+
+pub fn tag_message(
+  version: u16,
+  message_type: MessageType,
+  payload: &[u8],
+) -> Vec<u8> {
+  let mut output = Vec::with_capacity(TAG_LEN + payload.len());
+  output.extend_from_slice(&version.to_be_bytes());
+  output.push(message_type as u8);
+  output.extend_from_slice(payload);
+  output
+}
+
+/// Returns the version and payload of a tagged message, checking that it's
+/// of the expected type and in a version this build understands
+pub fn untag_message(
+  message: &[u8],
+  expected_type: MessageType,
+) -> Result<(u16, &[u8]), Error> {
+  if message.len() < TAG_LEN {
+    return Err(Error::InvalidMessageTag);
+  }
+  let version = u16::from_be_bytes([message[0], message[1]]);
+  if !SUPPORTED_WIRE_VERSIONS.contains(&version) {
+    return Err(Error::UnsupportedWireVersion(version));
+  }
+  version_policy().check_wire_version(version)?;
+  if MessageType::try_from(message[2])? != expected_type {
+    return Err(Error::InvalidMessageTag);
+  }
+  Ok((version, &message[TAG_LEN..]))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_negotiate_highest_common_version() {
+    assert_eq!(negotiate_version(&[1, 2, 3], &[2, 3, 4]), Some(3));
+    assert_eq!(negotiate_version(&[3, 1], &[1]), Some(1));
+    assert_eq!(negotiate_version(&[1], &[1]), Some(1));
+  }
+
+  #[test]
+  fn test_negotiate_without_overlap() {
+    assert_eq!(negotiate_version(&[1, 2], &[3]), None);
+    assert_eq!(negotiate_version(&[], &[1]), None);
+    assert_eq!(negotiate_version(&[1], &[]), None);
+  }
+
+  #[test]
+  fn test_tag_round_trip() {
+    let tagged = tag_message(
+      CURRENT_WIRE_VERSION,
+      MessageType::CredentialRequest,
+      b"payload",
+    );
+    let (version, payload) =
+      untag_message(&tagged, MessageType::CredentialRequest).unwrap();
+    assert_eq!(version, CURRENT_WIRE_VERSION);
+    assert_eq!(payload, b"payload");
+  }
+
+  #[test]
+  fn test_untag_rejects_wrong_type_and_version() {
+    let tagged = tag_message(
+      CURRENT_WIRE_VERSION,
+      MessageType::CredentialRequest,
+      b"payload",
+    );
+    assert!(matches!(
+      untag_message(&tagged, MessageType::RegistrationRequest),
+      Err(Error::InvalidMessageTag)
+    ));
+    let future = tag_message(99, MessageType::CredentialRequest, b"payload");
+    assert!(matches!(
+      untag_message(&future, MessageType::CredentialRequest),
+      Err(Error::UnsupportedWireVersion(99))
+    ));
+    assert!(matches!(
+      untag_message(&[0, 1], MessageType::CredentialRequest),
+      Err(Error::InvalidMessageTag)
+    ));
+  }
+}